@@ -2,21 +2,34 @@ use regex_syntax::hir;
 use std::fmt;
 
 /// Represent a set of characters as an union of ranges.
+///
+/// Only unicode atoms can be represented, byte atoms are rejected when the
+/// regex is parsed.
 #[derive(Debug)]
 pub enum Atom {
-    Literal(hir::Literal),
-    Class(hir::Class),
+    Literal(char),
+    Class(hir::ClassUnicode),
 }
 
 impl Atom {
     /// Check if a unicode character matches an atom.
     pub fn is_match(&self, a: &char) -> bool {
         match self {
-            Atom::Literal(hir::Literal::Unicode(x)) => a == x,
-            Atom::Class(hir::Class::Unicode(class)) => class
+            Atom::Literal(x) => a == x,
+            Atom::Class(class) => class
                 .iter()
                 .any(|range| range.start() <= *a && *a <= range.end()),
-            _ => panic!("Byte regex are not supported"),
+        }
+    }
+
+    /// List the inclusive ranges of characters matched by an atom.
+    pub fn ranges(&self) -> Vec<(char, char)> {
+        match self {
+            Atom::Literal(x) => vec![(*x, *x)],
+            Atom::Class(class) => class
+                .iter()
+                .map(|range| (range.start(), range.end()))
+                .collect(),
         }
     }
 }
@@ -24,15 +37,14 @@ impl Atom {
 impl fmt::Display for Atom {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Atom::Literal(hir::Literal::Unicode(x)) => write!(f, "'{}'", x),
-            Atom::Class(hir::Class::Unicode(class)) => {
+            Atom::Literal(x) => write!(f, "'{}'", x),
+            Atom::Class(class) => {
                 write!(f, "[")?;
                 for range in class.iter() {
                     write!(f, "{}-{}", range.start(), range.end())?;
                 }
                 write!(f, "]")
             }
-            _ => panic!("Byte regex are not supported"),
         }
     }
 }
@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error;
+use std::fmt;
+use std::iter;
+
+use super::super::mapping::Marker;
+use super::{prev_char, Automaton, Label, LabelSpec};
+
+//  ____  _____ _
+// |  _ \|  ___/ \
+// | | | | |_ / _ \
+// | |_| |  _/ ___ \
+// |____/|_|/_/   \_\
+//
+
+/// Deterministic automaton reading a text without assigning variables, built
+/// from an `Automaton` with `Automaton::to_dfa`.
+///
+/// Characters are grouped into classes that can't be distinguished by the
+/// original automaton, each class is identified by its smallest character.
+#[derive(Clone, Debug)]
+pub struct Dfa {
+    classes:     Vec<char>,
+    transitions: Vec<Vec<Option<usize>>>,
+    finals:      Vec<bool>,
+}
+
+impl Dfa {
+    pub fn get_nb_states(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// Check if the whole text is accepted by the automaton.
+    pub fn is_match(&self, text: &str) -> bool {
+        let mut state = 0;
+
+        for x in text.chars() {
+            match self.transitions[state][self.class_of(x)] {
+                Some(target) => state = target,
+                None => return false,
+            }
+        }
+
+        self.finals[state]
+    }
+
+    /// Index of the class containing a character.
+    fn class_of(&self, x: char) -> usize {
+        // The first class always starts at '\0'.
+        self.classes.partition_point(|&start| start <= x) - 1
+    }
+
+    /// Inclusive range of characters of a class.
+    fn class_range(&self, class: usize) -> (char, char) {
+        let end = match self.classes.get(class + 1) {
+            Some(&next) => prev_char(next).unwrap(),
+            None => char::MAX,
+        };
+
+        (self.classes[class], end)
+    }
+}
+
+/// Error occuring when an automaton that assigns variables is used where
+/// only capture-free automata are supported.
+#[derive(Debug)]
+pub struct HasCaptures {
+    marker: Marker,
+}
+
+impl HasCaptures {
+    /// One of the markers assigned by the automaton.
+    pub fn marker(&self) -> &Marker {
+        &self.marker
+    }
+}
+
+impl fmt::Display for HasCaptures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "only capture-free automata are supported, this one assigns {}",
+            self.marker
+        )
+    }
+}
+
+impl error::Error for HasCaptures {}
+
+impl Automaton {
+    /// Build a DFA accepting the same language as this automaton, with the
+    /// subset construction.
+    ///
+    /// Only automata without assignations are supported, such as automata
+    /// compiled from patterns without captures with `regex::compile_raw`. The
+    /// DFA may have an exponential number of states.
+    pub fn to_dfa(&self) -> Result<Dfa, HasCaptures> {
+        if let Some((_, label, _)) = self
+            .transitions
+            .iter()
+            .find(|(_, label, _)| matches!(**label, Label::Assignation(_)))
+        {
+            let marker = label.get_marker().unwrap().clone();
+            return Err(HasCaptures { marker });
+        }
+
+        let classes = Automaton::alphabet_partition(&[self]);
+        let start = self.closure(iter::once(self.get_initial()));
+
+        let mut ids = HashMap::new();
+        let mut sets = Vec::new();
+        let mut queue = VecDeque::new();
+        ids.insert(start.clone(), 0);
+        sets.push(start.clone());
+        queue.push_back(start);
+
+        let mut transitions = Vec::new();
+
+        while let Some(states) = queue.pop_front() {
+            let adj = classes
+                .iter()
+                .map(|&x| {
+                    let next = self.read(&states, x);
+
+                    if next.is_empty() {
+                        return None;
+                    }
+
+                    let id = *ids.entry(next.clone()).or_insert_with(|| {
+                        sets.push(next.clone());
+                        queue.push_back(next);
+                        sets.len() - 1
+                    });
+
+                    Some(id)
+                })
+                .collect();
+
+            transitions.push(adj);
+        }
+
+        let finals = sets.iter().map(|set| self.is_accepting(set)).collect();
+
+        Ok(Dfa {
+            classes,
+            transitions,
+            finals,
+        })
+    }
+
+    /// Build an automaton accepting exactly the texts that are rejected by
+    /// this automaton.
+    ///
+    /// The DFA of this automaton is completed with a sink state before its
+    /// final states are flipped, thus only automata without assignations are
+    /// supported.
+    pub fn complement(&self) -> Result<Automaton, HasCaptures> {
+        let dfa = self.to_dfa()?;
+        let sink = dfa.get_nb_states();
+        let mut transitions = Vec::new();
+
+        for (source, adj) in dfa.transitions.iter().enumerate() {
+            // Classes leading to the same state are merged into a single label.
+            let mut ranges: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+
+            for (class, target) in adj.iter().enumerate() {
+                ranges
+                    .entry(target.unwrap_or(sink))
+                    .or_default()
+                    .push(dfa.class_range(class));
+            }
+
+            transitions.extend(
+                ranges
+                    .into_iter()
+                    .map(|(target, ranges)| (source, LabelSpec::Class(ranges), target)),
+            );
+        }
+
+        transitions.push((sink, LabelSpec::Class(vec![('\0', char::MAX)]), sink));
+        let finals = (0..sink).filter(|&state| !dfa.finals[state]);
+
+        Ok(Automaton::from_parts(
+            sink + 1,
+            transitions.into_iter(),
+            finals.chain(iter::once(sink)),
+        ))
+    }
+}
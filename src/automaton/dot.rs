@@ -0,0 +1,168 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+use super::{Automaton, LabelSpec};
+
+//  ____        _     ____
+// |  _ \  ___ | |_  |  _ \ __ _ _ __ ___  ___ _ __
+// | | | |/ _ \| __| | |_) / _` | '__/ __|/ _ \ '__|
+// | |_| | (_) | |_  |  __/ (_| | |  \__ \  __/ |
+// |____/ \___/ \__| |_|   \__,_|_|  |___/\___|_|
+//
+
+/// Error occuring while reading an automaton from a DOT file.
+#[derive(Debug)]
+pub enum DotParseError {
+    /// The input could not be read.
+    Io(io::Error),
+    /// A line of the input is not part of the DOT subset written by
+    /// `Automaton::render_dot`, lines are numbered from 1.
+    Syntax { line: usize, message: String },
+}
+
+impl fmt::Display for DotParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DotParseError::Io(err) => write!(f, "could not read DOT input: {}", err),
+            DotParseError::Syntax { line, message } => {
+                write!(f, "invalid DOT input at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl error::Error for DotParseError {}
+
+impl Automaton {
+    /// Build an automaton from the DOT representation written by
+    /// `Automaton::render_dot`.
+    ///
+    /// Labels that were shortened to `[...]` can't be read back and result in
+    /// an error. Variables are identified by their name, as in
+    /// `Automaton::from_parts`.
+    pub fn from_dot<R: Read>(reader: R) -> Result<Automaton, DotParseError> {
+        let mut shape = String::new();
+        let mut nb_states = 1;
+        let mut transitions = Vec::new();
+        let mut finals = Vec::new();
+
+        for (index, line) in io::BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(DotParseError::Io)?;
+            let line = line.trim();
+            let syntax_error = |message: String| DotParseError::Syntax {
+                line: index + 1,
+                message,
+            };
+
+            if line.is_empty() || line == "digraph automaton {" || line == "}" {
+                continue;
+            }
+
+            if let Some(node_shape) = line
+                .strip_prefix("node [shape=")
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                shape = node_shape.to_string();
+                continue;
+            }
+
+            if let Some(target) = line.strip_prefix("before_q0 -> ") {
+                match target {
+                    "q0" => continue,
+                    _ => {
+                        let message = format!("initial state must be q0, not {}", target);
+                        return Err(syntax_error(message));
+                    }
+                }
+            }
+
+            match line.split_once(" -> ") {
+                None if shape == "doublecircle" => {
+                    let state = parse_state(line).map_err(syntax_error)?;
+                    nb_states = nb_states.max(state + 1);
+                    finals.push(state);
+                }
+                None => return Err(syntax_error(format!("unexpected node `{}`", line))),
+                Some((source, rest)) => {
+                    let (target, label) = rest
+                        .split_once(" [label=\" ")
+                        .and_then(|(target, label)| Some((target, label.strip_suffix(" \"]")?)))
+                        .ok_or_else(|| syntax_error(format!("invalid edge `{}`", line)))?;
+
+                    let source = parse_state(source).map_err(syntax_error)?;
+                    let target = parse_state(target).map_err(syntax_error)?;
+                    let label = parse_label(label).map_err(syntax_error)?;
+                    nb_states = nb_states.max(source + 1).max(target + 1);
+                    transitions.push((source, label, target));
+                }
+            }
+        }
+
+        Ok(Automaton::from_parts(
+            nb_states,
+            transitions.into_iter(),
+            finals.into_iter(),
+        ))
+    }
+}
+
+/// Parse the name of a state, of the form `qN`.
+fn parse_state(name: &str) -> Result<usize, String> {
+    name.strip_prefix('q')
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| format!("invalid state `{}`", name))
+}
+
+/// Parse a label written as the debug-escaped display of a `Label`.
+fn parse_label(label: &str) -> Result<LabelSpec, String> {
+    let label = unescape(label).ok_or_else(|| format!("invalid escape in `{}`", label))?;
+    let chars: Vec<_> = label.chars().collect();
+
+    match chars.as_slice() {
+        ['\'', x, '\''] => Ok(LabelSpec::Literal(*x)),
+        ['[', '.', '.', '.', ']'] => Err(String::from("label was shortened when rendered")),
+        ['[', ranges @ .., ']'] if ranges.len() % 3 == 0 => ranges
+            .chunks(3)
+            .map(|range| match range {
+                [start, '-', end] => Ok((*start, *end)),
+                _ => Err(format!("invalid class `{}`", label)),
+            })
+            .collect::<Result<_, _>>()
+            .map(LabelSpec::Class),
+        ['⊢', name @ ..] if !name.is_empty() => Ok(LabelSpec::Open(name.iter().collect())),
+        [name @ .., '⊣'] if !name.is_empty() => Ok(LabelSpec::Close(name.iter().collect())),
+        _ => Err(format!("unknown label `{}`", label)),
+    }
+}
+
+/// Revert the escaping of `str::escape_debug`.
+fn unescape(text: &str) -> Option<String> {
+    let mut ret = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+
+        let unescaped = match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let code: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let code = code.strip_prefix('{')?;
+                std::char::from_u32(u32::from_str_radix(code, 16).ok()?)?
+            }
+            other => other,
+        };
+
+        ret.push(unescaped);
+    }
+
+    Some(ret)
+}
@@ -1,12 +1,24 @@
 pub mod atom;
 
-use std::collections::{HashMap, HashSet};
+mod dfa;
+mod dot;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
-use std::rc::Rc;
+use std::iter;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use regex_syntax::hir;
+
+use super::mapping::{Marker, Variable};
 
-use super::mapping::Marker;
+pub use dfa::{Dfa, HasCaptures};
+pub use dot::DotParseError;
 
 //     _         _                        _
 //    / \  _   _| |_ ___  _ __ ___   __ _| |_ ___  _ __
@@ -17,21 +29,79 @@ use super::mapping::Marker;
 #[derive(Clone, Debug)]
 pub struct Automaton {
     pub nb_states:   usize,
-    pub transitions: Vec<(usize, Rc<Label>, usize)>,
+    transitions:     Vec<(usize, Arc<Label>, usize)>,
     pub finals:      HashSet<usize>,
 
     // Redundant caching structures
-    adj: Vec<Vec<(Rc<Label>, usize)>>,
-    adj_for_char: HashMap<char, Vec<Vec<usize>>>,
-    assignations: Vec<Vec<(Rc<Label>, usize)>>,
-    rev_assignations: Vec<Vec<(Rc<Label>, usize)>>,
-    closure_for_assignations: Vec<Vec<usize>>,
+    adj: Vec<Vec<(Arc<Label>, usize)>>,
+    adj_cache: Arc<Mutex<AdjCache>>,
+    assignations: Vec<Vec<(Arc<Label>, usize)>>,
+    rev_assignations: Vec<Vec<(Arc<Label>, usize)>>,
+    closure_for_assignations: Vec<Vec<StateId>>,
+}
+
+/// Maximal number of chars for which the adjacency lists of an automaton are
+/// cached. Any size gives the same results, lists are computed again when they
+/// are needed after being evicted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AdjCacheSize {
+    #[default]
+    Unbounded,
+    /// Keep at most `n` lists, at least one is always kept.
+    Bounded(usize),
+}
+
+/// Compact index of a state, used by the adjacency lists built for each char
+/// and by the levels of an `IndexedDag`. It is 32 bits wide, unless the feature
+/// `wide-states` is enabled for automata with more than `2^32` states.
+#[cfg(not(feature = "wide-states"))]
+pub type StateId = u32;
+#[cfg(feature = "wide-states")]
+pub type StateId = u64;
+
+/// Convert a state to its compact index.
+///
+/// Panics if the state doesn't fit in a `StateId`.
+pub fn state_id(state: usize) -> StateId {
+    StateId::try_from(state).expect("Too many states, consider the feature `wide-states`")
+}
+
+/// Convert a compact index back to a state.
+pub fn state_index(id: StateId) -> usize {
+    id as usize
+}
+
+/// Targets of transitions from each state that can be used when reading a
+/// given char.
+pub type CharAdj = Arc<Vec<Vec<StateId>>>;
+
+/// Adjacency lists computed for each char read by an automaton, see
+/// `Automaton::get_adj_for_char`.
+#[derive(Debug)]
+struct AdjCache {
+    size:    AdjCacheSize,
+    clock:   u64,
+    lists:   HashMap<char, (u64, CharAdj)>,
+    /// Cached chars indexed by their last use, only kept if the cache is
+    /// bounded.
+    recency: BTreeMap<u64, char>,
+}
+
+impl AdjCache {
+    fn new(size: AdjCacheSize) -> AdjCache {
+        AdjCache {
+            size,
+            clock: 0,
+            lists: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
 }
 
 impl Automaton {
     pub fn new<T, U>(nb_states: usize, transitions: T, finals: U) -> Automaton
     where
-        T: Iterator<Item = (usize, Rc<Label>, usize)>,
+        T: Iterator<Item = (usize, Arc<Label>, usize)>,
         U: Iterator<Item = usize>,
     {
         let mut automaton = Automaton {
@@ -40,7 +110,7 @@ impl Automaton {
             finals: finals.collect(),
 
             adj: Vec::new(),
-            adj_for_char: HashMap::new(),
+            adj_cache: Arc::new(Mutex::new(AdjCache::new(AdjCacheSize::default()))),
             assignations: Vec::new(),
             rev_assignations: Vec::new(),
             closure_for_assignations: Vec::new(),
@@ -54,6 +124,47 @@ impl Automaton {
         automaton
     }
 
+    /// Build an automaton from a description of its transitions, the initial
+    /// state being `0`.
+    ///
+    /// Variables are identified by their name: an opening and a closing marker
+    /// with the same name refer to the same variable.
+    pub fn from_parts<T, U>(nb_states: usize, transitions: T, finals: U) -> Automaton
+    where
+        T: Iterator<Item = (usize, LabelSpec, usize)>,
+        U: Iterator<Item = usize>,
+    {
+        let mut variables: HashMap<String, Arc<Variable>> = HashMap::new();
+
+        let transitions: Vec<_> = transitions
+            .map(|(source, spec, target)| {
+                let mut get_var = |name: String| {
+                    let id = variables.len() as u64;
+                    variables
+                        .entry(name.clone())
+                        .or_insert_with(|| Arc::new(Variable::new(name, id)))
+                        .clone()
+                };
+
+                let label = match spec {
+                    LabelSpec::Literal(x) => Label::Atom(atom::Atom::Literal(x)),
+                    LabelSpec::Class(ranges) => {
+                        let ranges = ranges
+                            .into_iter()
+                            .map(|(start, end)| hir::ClassUnicodeRange::new(start, end));
+                        Label::Atom(atom::Atom::Class(hir::ClassUnicode::new(ranges)))
+                    }
+                    LabelSpec::Open(name) => Label::Assignation(Marker::Open(get_var(name))),
+                    LabelSpec::Close(name) => Label::Assignation(Marker::Close(get_var(name))),
+                };
+
+                (source, Arc::new(label), target)
+            })
+            .collect();
+
+        Automaton::new(nb_states, transitions.into_iter(), finals)
+    }
+
     pub fn get_initial(&self) -> usize {
         0
     }
@@ -62,65 +173,347 @@ impl Automaton {
         self.nb_states
     }
 
-    pub fn get_adj(&self) -> &Vec<Vec<(Rc<Label>, usize)>> {
+    pub fn nb_transitions(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// Iterate over transitions as triples `(source, label, target)`, each
+    /// transition being visited once.
+    pub fn iter_transitions(&self) -> impl Iterator<Item = (usize, &Label, usize)> {
+        self.transitions
+            .iter()
+            .map(|(source, label, target)| (*source, &**label, *target))
+    }
+
+    pub fn get_adj(&self) -> &Vec<Vec<(Arc<Label>, usize)>> {
         &self.adj
     }
 
     /// Get the adjacency list representing transitions of the automaton that
     /// can be used when reading a given char.
-    pub fn get_adj_for_char(&mut self, x: char) -> &Vec<Vec<usize>> {
-        let nb_states = self.get_nb_states();
-        let adj_for_char = &mut self.adj_for_char;
-        let transitions = &self.transitions;
+    ///
+    /// Adjacency lists are cached for each char, if the cache is bounded the
+    /// least recently used one is evicted when it is full. The cache is shared
+    /// with clones of the automaton, thus an automaton can be cloned to match
+    /// several texts without computing the same lists again.
+    pub fn get_adj_for_char(&self, x: char) -> CharAdj {
+        let mut cache = self.adj_cache.lock().expect("Adjacency cache was poisoned");
+        cache.clock += 1;
+        let clock = cache.clock;
+        let AdjCache {
+            size,
+            lists,
+            recency,
+            ..
+        } = &mut *cache;
+
+        if let Some((last_use, adj)) = lists.get_mut(&x) {
+            if let AdjCacheSize::Bounded(_) = size {
+                recency.remove(last_use);
+                recency.insert(clock, x);
+                *last_use = clock;
+            }
 
-        adj_for_char.entry(x).or_insert_with(|| {
-            let mut res = vec![Vec::new(); nb_states];
+            return adj.clone();
+        }
 
-            for (source, label, target) in transitions {
-                if let Label::Atom(atom) = &**label {
-                    if atom.is_match(&x) {
-                        res[*source].push(*target);
-                    }
-                }
+        if let AdjCacheSize::Bounded(size) = *size {
+            while lists.len() >= cmp::max(size, 1) {
+                let (_, evicted) = recency
+                    .pop_first()
+                    .expect("Recency of cached chars should match the cache");
+                lists.remove(&evicted);
             }
 
-            res
-        })
+            recency.insert(clock, x);
+        }
+
+        let adj = Arc::new(self.init_adj_for_char(x));
+        lists.insert(x, (clock, adj.clone()));
+        adj
+    }
+
+    /// Bound the number of chars for which adjacency lists are cached.
+    ///
+    /// The automaton is given a new empty cache, which is no longer shared
+    /// with its previous clones.
+    pub fn set_adj_cache_size(&mut self, size: AdjCacheSize) {
+        self.adj_cache = Arc::new(Mutex::new(AdjCache::new(size)));
+    }
+
+    /// Get the bound on the number of chars for which adjacency lists are
+    /// cached.
+    pub fn adj_cache_size(&self) -> AdjCacheSize {
+        self.adj_cache.lock().expect("Adjacency cache was poisoned").size
+    }
+
+    /// Number of chars for which adjacency lists are currently cached.
+    pub fn adj_cache_len(&self) -> usize {
+        self.adj_cache.lock().expect("Adjacency cache was poisoned").lists.len()
+    }
+
+    /// Compute in advance the adjacency lists of some chars, for example the
+    /// alphabet of texts that will be matched.
+    pub fn precompute_adjacency<T>(&self, alphabet: T)
+    where
+        T: IntoIterator<Item = char>,
+    {
+        for x in alphabet {
+            self.get_adj_for_char(x);
+        }
     }
 
     /// Get adjacency lists labeled with the corresponding marker for
     /// transitions labeled with an assignation.
-    pub fn get_assignations(&self) -> &Vec<Vec<(Rc<Label>, usize)>> {
+    pub fn get_assignations(&self) -> &Vec<Vec<(Arc<Label>, usize)>> {
         &self.assignations
     }
 
     /// Get the reverse of assignations as defined in
     /// `Automata::get_assignations`.
-    pub fn get_rev_assignations(&self) -> &Vec<Vec<(Rc<Label>, usize)>> {
+    pub fn get_rev_assignations(&self) -> &Vec<Vec<(Arc<Label>, usize)>> {
         &self.rev_assignations
     }
 
     /// Get the closure as adjacency lists for transitions labeled with an
     /// assignation.
-    pub fn get_closure_for_assignations(&self) -> &Vec<Vec<usize>> {
+    pub fn get_closure_for_assignations(&self) -> &Vec<Vec<StateId>> {
         &self.closure_for_assignations
     }
 
+    /// List distinct variables assigned by the automaton, sorted by position
+    /// in the pattern, see `Variable`.
+    pub fn variables(&self) -> Vec<&Variable> {
+        let mut variables: Vec<_> = self
+            .transitions
+            .iter()
+            .filter_map(|(_, label, _)| label.get_marker().ok())
+            .map(|marker| marker.variable())
+            .collect();
+
+        variables.sort();
+        variables.dedup();
+        variables
+    }
+
+    /// Summarize the set of characters that can be read by the automaton,
+    /// which is the union of the atoms of all its transitions.
+    ///
+    /// This includes the padding added by `regex::compile` around unanchored
+    /// patterns, which reads any character: the alphabet of the pattern itself
+    /// is given by `regex::compile_raw` or by an anchored pattern.
+    pub fn alphabet(&self) -> CharClassSummary {
+        let atoms = self
+            .transitions
+            .iter()
+            .filter_map(|(_, label, _)| match &**label {
+                Label::Atom(atom) => Some(atom.ranges()),
+                Label::Assignation(_) => None,
+            });
+
+        CharClassSummary::from_atoms(atoms)
+    }
+
+    /// Check if all states are reachable from the initial state and can reach
+    /// a final state, markers being followed as any other transition.
+    pub fn is_trim(&self) -> bool {
+        self.nb_unreachable_states() == 0 && self.nb_dead_states() == 0
+    }
+
+    /// Number of states that can't be reached from the initial state.
+    pub fn nb_unreachable_states(&self) -> usize {
+        let accessible = self.reachable(vec![self.get_initial()], false);
+        accessible.into_iter().filter(|&x| !x).count()
+    }
+
+    /// Number of states from which no final state can be reached.
+    pub fn nb_dead_states(&self) -> usize {
+        let coaccessible = self.reachable(self.finals.iter().copied().collect(), true);
+        coaccessible.into_iter().filter(|&x| !x).count()
+    }
+
+    /// Remove states that are unreachable or dead, see `Automaton::is_trim`,
+    /// the initial state is always kept.
+    pub fn prune(&self) -> Automaton {
+        let accessible = self.reachable(vec![self.get_initial()], false);
+        let coaccessible = self.reachable(self.finals.iter().copied().collect(), true);
+
+        // New id of each kept state, the initial state is kept first.
+        let mut new_ids = vec![None; self.nb_states];
+        let mut nb_states = 0;
+
+        for state in 0..self.nb_states {
+            if state == self.get_initial() || (accessible[state] && coaccessible[state]) {
+                new_ids[state] = Some(nb_states);
+                nb_states += 1;
+            }
+        }
+
+        let transitions: Vec<_> = self
+            .transitions
+            .iter()
+            .filter_map(|(source, label, target)| {
+                Some((new_ids[*source]?, label.clone(), new_ids[*target]?))
+            })
+            .collect();
+
+        let finals = self.finals.iter().filter_map(|&state| new_ids[state]);
+        Automaton::new(nb_states, transitions.into_iter(), finals)
+    }
+
+    /// Compute the set of states that can be reached from some states, or
+    /// that can reach them if `reverse` is set.
+    fn reachable(&self, from: Vec<usize>, reverse: bool) -> Vec<bool> {
+        let mut adj = vec![Vec::new(); self.nb_states];
+
+        for (source, _, target) in &self.transitions {
+            match reverse {
+                false => adj[*source].push(*target),
+                true => adj[*target].push(*source),
+            }
+        }
+
+        let mut reached = vec![false; self.nb_states];
+        let mut stack = from;
+
+        while let Some(state) = stack.pop() {
+            if !reached[state] {
+                reached[state] = true;
+                stack.extend(adj[state].iter().copied());
+            }
+        }
+
+        reached
+    }
+
+    /// Check if two automata accept the same language, ignoring variables.
+    ///
+    /// Both automata are determinized on the fly, this takes exponential time
+    /// in the worst case.
+    pub fn accepts_same_language(&self, other: &Automaton) -> bool {
+        !self.reaches_accepting_pair(other, |self_acc, other_acc| self_acc != other_acc)
+    }
+
+    /// Check if the language of this automaton is included in the language of
+    /// `other`, ignoring variables.
+    ///
+    /// Both automata are determinized on the fly, this takes exponential time
+    /// in the worst case.
+    pub fn is_subset_of(&self, other: &Automaton) -> bool {
+        !self.reaches_accepting_pair(other, |self_acc, other_acc| self_acc && !other_acc)
+    }
+
+    /// Explore the product of the determinized versions of two automata and
+    /// check if a pair of states satisfying `target` can be reached, where the
+    /// predicate is given if each state is accepting.
+    fn reaches_accepting_pair<F>(&self, other: &Automaton, target: F) -> bool
+    where
+        F: Fn(bool, bool) -> bool,
+    {
+        let alphabet = Automaton::alphabet_partition(&[self, other]);
+
+        let start = (
+            self.closure(iter::once(self.get_initial())),
+            other.closure(iter::once(other.get_initial())),
+        );
+
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::new();
+        seen.insert(start.clone());
+        queue.push_back(start);
+
+        while let Some((self_set, other_set)) = queue.pop_front() {
+            if target(self.is_accepting(&self_set), other.is_accepting(&other_set)) {
+                return true;
+            }
+
+            for &x in &alphabet {
+                let next = (self.read(&self_set, x), other.read(&other_set, x));
+
+                if !seen.contains(&next) {
+                    seen.insert(next.clone());
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Split the alphabet into intervals of characters that can't be
+    /// distinguished by any atom of the automata, and return a representative
+    /// character for each of them.
+    fn alphabet_partition(automata: &[&Automaton]) -> Vec<char> {
+        // Surrogates are not valid chars, they are delimited to be skipped.
+        let mut bounds: BTreeSet<u32> = [0, 0xD800, 0xE000].iter().copied().collect();
+
+        for automaton in automata {
+            for (_, label, _) in &automaton.transitions {
+                if let Label::Atom(atom) = &**label {
+                    for (start, end) in atom.ranges() {
+                        bounds.insert(start as u32);
+                        bounds.insert(end as u32 + 1);
+                    }
+                }
+            }
+        }
+
+        bounds.into_iter().filter_map(std::char::from_u32).collect()
+    }
+
+    /// Close a set of states with transitions labeled with an assignation.
+    fn closure<T>(&self, states: T) -> BTreeSet<usize>
+    where
+        T: Iterator<Item = usize>,
+    {
+        let mut ret = BTreeSet::new();
+
+        for state in states {
+            ret.insert(state);
+            let closure = self.closure_for_assignations[state].iter().copied();
+            ret.extend(closure.map(state_index));
+        }
+
+        ret
+    }
+
+    /// Compute the closed set of states reached from a set of states by
+    /// reading a character.
+    fn read(&self, states: &BTreeSet<usize>, x: char) -> BTreeSet<usize> {
+        let targets = states.iter().flat_map(|&source| {
+            self.adj[source].iter().filter_map(move |(label, target)| match &**label {
+                Label::Atom(atom) if atom.is_match(&x) => Some(*target),
+                _ => None,
+            })
+        });
+
+        self.closure(targets)
+    }
+
+    fn is_accepting(&self, states: &BTreeSet<usize>) -> bool {
+        states.iter().any(|state| self.finals.contains(state))
+    }
+
     /// Render the automaton as a dotfile for later rendering with graphviz.
     pub fn render(&self, filename: &str) -> std::io::Result<()> {
-        let mut buf = File::create(filename)?;
-        buf.write(b"digraph automaton {\n")?;
+        self.render_dot(File::create(filename)?)
+    }
+
+    /// Write a DOT representation of the automaton, labels longer than 10
+    /// characters are replaced with `[...]`.
+    pub fn render_dot<W: Write>(&self, mut buf: W) -> std::io::Result<()> {
+        buf.write_all(b"digraph automaton {\n")?;
 
         // Use doublecircles for final states
-        buf.write(b"\tnode [shape=doublecircle]\n")?;
+        buf.write_all(b"\tnode [shape=doublecircle]\n")?;
 
         for state in &self.finals {
             let node = format!("\tq{}\n", state);
-            buf.write(node.as_bytes())?;
+            buf.write_all(node.as_bytes())?;
         }
 
         // Draw edges
-        buf.write(b"\n\tnode [shape=circle]\n")?;
+        buf.write_all(b"\n\tnode [shape=circle]\n")?;
 
         for (source, label, target) in &self.transitions {
             let mut label_str = format!("{}", label).escape_debug().to_string();
@@ -130,18 +523,39 @@ impl Automaton {
             }
 
             let edge = format!("\tq{} -> q{} [label=\" {} \"]\n", source, target, label_str);
-            buf.write(edge.as_bytes())?;
+            buf.write_all(edge.as_bytes())?;
         }
 
         // Add an arrow towards initial state
-        buf.write(b"\n\tnode [shape=point]\n")?;
-        buf.write(b"\tbefore_q0 -> q0\n")?;
+        buf.write_all(b"\n\tnode [shape=point]\n")?;
+        buf.write_all(b"\tbefore_q0 -> q0\n")?;
 
-        buf.write(b"}\n")?;
+        buf.write_all(b"}\n")?;
         Ok(())
     }
 
-    fn init_adj(&self) -> Vec<Vec<(Rc<Label>, usize)>> {
+    fn init_adj_for_char(&self, x: char) -> Vec<Vec<StateId>> {
+        let mut res = vec![Vec::new(); self.nb_states];
+
+        // Labels shared by several transitions are only matched once.
+        let mut is_match = HashMap::new();
+
+        for (source, label, target) in &self.transitions {
+            if let Label::Atom(atom) = &**label {
+                let matched = *is_match
+                    .entry(Arc::as_ptr(label))
+                    .or_insert_with(|| atom.is_match(&x));
+
+                if matched {
+                    res[*source].push(state_id(*target));
+                }
+            }
+        }
+
+        res
+    }
+
+    fn init_adj(&self) -> Vec<Vec<(Arc<Label>, usize)>> {
         let mut ret = vec![Vec::new(); self.nb_states];
 
         for (source, label, target) in &self.transitions {
@@ -151,7 +565,7 @@ impl Automaton {
         ret
     }
 
-    fn init_assignations(&self) -> Vec<Vec<(Rc<Label>, usize)>> {
+    fn init_assignations(&self) -> Vec<Vec<(Arc<Label>, usize)>> {
         // Compute adjacency list
         let mut adj = vec![Vec::new(); self.get_nb_states()];
 
@@ -164,7 +578,7 @@ impl Automaton {
         adj
     }
 
-    fn init_rev_assignations(&self) -> Vec<Vec<(Rc<Label>, usize)>> {
+    fn init_rev_assignations(&self) -> Vec<Vec<(Arc<Label>, usize)>> {
         // Compute adjacency list
         let mut adj = vec![Vec::new(); self.get_nb_states()];
 
@@ -177,7 +591,7 @@ impl Automaton {
         adj
     }
 
-    fn init_closure_for_assignations(&self) -> Vec<Vec<usize>> {
+    fn init_closure_for_assignations(&self) -> Vec<Vec<StateId>> {
         // Compute adjacency list
         let assignations = self.get_assignations();
         let adj: Vec<Vec<usize>> = (0..self.get_nb_states())
@@ -187,14 +601,14 @@ impl Automaton {
         // Compute closure
         let mut closure = vec![Vec::new(); self.get_nb_states()];
 
-        for state in 0..self.get_nb_states() {
+        for (state, state_closure) in closure.iter_mut().enumerate() {
             let mut heap = vec![state];
             let mut seen = HashSet::new();
             seen.insert(state);
 
             while let Some(source) = heap.pop() {
                 for target in &adj[source] {
-                    closure[state].push(*target);
+                    state_closure.push(state_id(*target));
 
                     if !seen.contains(target) {
                         heap.push(*target);
@@ -230,6 +644,20 @@ impl Label {
     }
 }
 
+/// Public description of a label, used to build an automaton with
+/// `Automaton::from_parts`.
+#[derive(Clone, Debug)]
+pub enum LabelSpec {
+    /// Read a single character.
+    Literal(char),
+    /// Read any character in one of the inclusive ranges.
+    Class(Vec<(char, char)>),
+    /// Open the variable with given name.
+    Open(String),
+    /// Close the variable with given name.
+    Close(String),
+}
+
 impl fmt::Display for Label {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -238,3 +666,98 @@ impl fmt::Display for Label {
         }
     }
 }
+
+//     _    _       _           _          _
+//    / \  | |_ __ | |__   __ _| |__   ___| |_
+//   / _ \ | | '_ \| '_ \ / _` | '_ \ / _ \ __|
+//  / ___ \| | |_) | | | | (_| | |_) |  __/ |_
+// /_/   \_\_| .__/|_| |_|\__,_|_.__/ \___|\__|
+//           |_|
+
+/// Number of valid chars, which excludes surrogates.
+const NB_CHARS: u32 = 0x11_0000 - 0x800;
+
+/// Set of characters that can be read by an automaton, see
+/// `Automaton::alphabet`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CharClassSummary {
+    /// Sorted disjoint ranges, two ranges are never contiguous.
+    pub ranges:    Vec<RangeInclusive<char>>,
+    /// Whether a single atom reads any character, except maybe a line break,
+    /// as `.` does.
+    pub unbounded: bool,
+}
+
+impl CharClassSummary {
+    /// Build the union of atoms given by their ranges.
+    fn from_atoms<T>(atoms: T) -> CharClassSummary
+    where
+        T: Iterator<Item = Vec<(char, char)>>,
+    {
+        let mut unbounded = false;
+        let mut all_ranges = Vec::new();
+
+        for ranges in atoms {
+            let size: u32 = ranges.iter().map(|&(start, end)| range_size(start, end)).sum();
+            unbounded |= size + 1 >= NB_CHARS;
+            all_ranges.extend(ranges);
+        }
+
+        all_ranges.sort_unstable();
+        let mut ranges: Vec<RangeInclusive<char>> = Vec::new();
+
+        for (start, end) in all_ranges {
+            match ranges.last_mut() {
+                Some(last) if start <= next_char(*last.end()).unwrap_or(char::MAX) => {
+                    if end > *last.end() {
+                        *last = *last.start()..=end;
+                    }
+                }
+                _ => ranges.push(start..=end),
+            }
+        }
+
+        CharClassSummary { ranges, unbounded }
+    }
+
+    /// Check if a character can be read.
+    pub fn contains(&self, x: char) -> bool {
+        self.ranges.iter().any(|range| range.contains(&x))
+    }
+}
+
+/// Number of chars in an inclusive range.
+fn range_size(start: char, end: char) -> u32 {
+    let size = end as u32 - start as u32 + 1;
+
+    match (start as u32) < 0xD800 && (end as u32) >= 0xE000 {
+        true => size - 0x800,
+        false => size,
+    }
+}
+
+/// Get the char following a given char, skipping surrogates.
+fn next_char(x: char) -> Option<char> {
+    match x {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => std::char::from_u32(x as u32 + 1),
+    }
+}
+
+/// Get the char preceding a given char, skipping surrogates.
+fn prev_char(x: char) -> Option<char> {
+    match x {
+        '\u{E000}' => Some('\u{D7FF}'),
+        _ => std::char::from_u32((x as u32).checked_sub(1)?),
+    }
+}
+
+//  _____         _
+// |_   _|__  ___| |_ ___
+//   | |/ _ \/ __| __/ __|
+//   | |  __/\__ \ |_\__ \
+//   |_|\___||___/\__|___/
+//
+
+#[cfg(test)]
+mod tests;
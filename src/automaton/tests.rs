@@ -0,0 +1,313 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::super::mapping::Mapping;
+use super::super::regex;
+use super::{AdjCacheSize, Automaton, LabelSpec};
+
+#[test]
+fn from_parts() {
+    let any = || LabelSpec::Class(vec![('\0', char::MAX)]);
+
+    // Match any 'a' in the text, captured in the variable `x`
+    let automaton = Automaton::from_parts(
+        4,
+        vec![
+            (0, any(), 0),
+            (0, LabelSpec::Open("x".to_string()), 1),
+            (1, LabelSpec::Literal('a'), 2),
+            (2, LabelSpec::Close("x".to_string()), 3),
+            (3, any(), 3),
+        ]
+        .into_iter(),
+        vec![3].into_iter(),
+    );
+
+    let compiled = regex::compile_matches(automaton, "baba");
+    let mut spans: Vec<_> = compiled
+        .iter()
+        .map(|mapping| {
+            let groups: Vec<_> = mapping.iter_groups().collect();
+            assert_eq!(groups.len(), 1);
+            assert_eq!(groups[0].0, "x");
+            groups[0].1.clone()
+        })
+        .collect();
+
+    spans.sort_by_key(|span| span.start);
+    assert_eq!(spans, vec![1..2, 3..4]);
+}
+
+#[test]
+fn same_language() {
+    let union_1 = regex::compile_raw("a|b").unwrap();
+    let union_2 = regex::compile_raw("b|a").unwrap();
+    assert!(union_1.accepts_same_language(&union_2));
+    assert!(union_1.is_subset_of(&union_2));
+
+    let star = regex::compile_raw("a*").unwrap();
+    let plus = regex::compile_raw("a+").unwrap();
+    assert!(!star.accepts_same_language(&plus));
+    assert!(plus.is_subset_of(&star));
+    assert!(!star.is_subset_of(&plus));
+
+    // Variables are ignored
+    let group = regex::compile_raw("(?P<x>a)b").unwrap();
+    let plain = regex::compile_raw("ab").unwrap();
+    assert!(group.accepts_same_language(&plain));
+}
+
+#[test]
+fn bounded_adj_cache() {
+    let unbounded = regex::compile(r"[\p{Greek}a-z]\p{Han}").unwrap();
+    let mut bounded = unbounded.clone();
+    bounded.set_adj_cache_size(AdjCacheSize::Bounded(8));
+
+    // Chars are read several times, from a large alphabet
+    let chars = ('a'..='z').chain('α'..='ω').chain('一'..='丿');
+
+    for x in chars.clone().chain(chars.rev()).chain("aαa一".chars()) {
+        assert_eq!(bounded.get_adj_for_char(x), unbounded.get_adj_for_char(x));
+        assert!(bounded.adj_cache_len() <= 8);
+    }
+
+    assert_eq!(bounded.adj_cache_len(), 8);
+    assert!(unbounded.adj_cache_len() > 8);
+}
+
+#[test]
+fn variables() {
+    let names = |automaton: &Automaton| -> Vec<String> {
+        automaton
+            .variables()
+            .iter()
+            .map(|var| var.get_name().to_string())
+            .collect()
+    };
+
+    assert_eq!(names(&regex::compile_raw(r"(?P<a>x)(?P<b>y)").unwrap()), vec!["a", "b"]);
+    assert_eq!(names(&regex::compile_raw(r"(?P<a>x)*y").unwrap()), vec!["a"]);
+    assert_eq!(names(&regex::compile_raw(r"xy").unwrap()), Vec::<String>::new());
+    assert_eq!(names(&regex::compile(r"x+").unwrap()), vec!["match"]);
+    assert_eq!(
+        names(&regex::compile(r"(?P<a>x)(?P<b>y)").unwrap()),
+        vec!["match", "a", "b"]
+    );
+
+    let compiled = regex::compile_matches(regex::compile(r"(?P<a>x)").unwrap(), "x");
+    assert_eq!(compiled.variables().len(), 2);
+}
+
+#[test]
+fn dot_round_trip() {
+    /// Matches given as sorted lists of named groups, as variables of two
+    /// automata are not comparable.
+    fn results(automaton: Automaton, text: &str) -> Vec<Vec<(String, Range<usize>)>> {
+        let describe = |mapping: Mapping| {
+            let mut groups: Vec<_> = mapping
+                .iter_groups()
+                .map(|(name, range)| (name.to_string(), range))
+                .collect();
+            groups.sort_by(|a, b| (&a.0, a.1.start).cmp(&(&b.0, b.1.start)));
+            groups
+        };
+
+        let mut results: Vec<_> = regex::compile_matches(automaton, text)
+            .iter()
+            .map(describe)
+            .collect();
+        results.sort_by_key(|groups| format!("{:?}", groups));
+        results
+    }
+
+    let automaton = regex::compile_raw(r"[ab]*(?P<x>a+)[\n-]*'?(?P<y>b|\t)?").unwrap();
+    let mut dot = Vec::new();
+    automaton.render_dot(&mut dot).unwrap();
+    let parsed = Automaton::from_dot(&dot[..]).unwrap();
+
+    assert_eq!(parsed.get_nb_states(), automaton.get_nb_states());
+    assert_eq!(parsed.transitions.len(), automaton.transitions.len());
+    assert_eq!(parsed.finals, automaton.finals);
+
+    for text in &["abaab", "aa\n-'b", "ba-\t", "bbb"] {
+        assert_eq!(results(parsed.clone(), text), results(automaton.clone(), text));
+    }
+
+    assert!(!results(parsed, "aa\n-'b").is_empty());
+}
+
+#[test]
+fn dot_errors() {
+    use super::DotParseError;
+
+    let line_of = |dot: &str| match Automaton::from_dot(dot.as_bytes()) {
+        Err(DotParseError::Syntax { line, .. }) => Some(line),
+        _ => None,
+    };
+
+    assert_eq!(line_of("digraph automaton {\n\tq0 -> q1 [label=\" [...] \"]\n}"), Some(2));
+    assert_eq!(line_of("digraph automaton {\n\tq0 -> x [label=\" \\'a\\' \"]\n}"), Some(2));
+    assert_eq!(line_of("\tnode [shape=point]\n\tbefore_q0 -> q1\n"), Some(2));
+    assert_eq!(line_of("digraph automaton {\n\tnode [shape=circle]\n\tq1\n}"), Some(3));
+    assert!(Automaton::from_dot("digraph automaton {\n}\n".as_bytes()).is_ok());
+}
+
+#[test]
+fn alphabet() {
+    let alphabet = regex::compile_raw(r"[a-c]|x").unwrap().alphabet();
+    assert_eq!(alphabet.ranges, vec!['a'..='c', 'x'..='x']);
+    assert!(!alphabet.unbounded);
+    assert!(alphabet.contains('b') && !alphabet.contains('d'));
+
+    let alphabet = regex::compile(r"^(?P<x>[b-d]+)[a-c]\z").unwrap().alphabet();
+    assert_eq!(alphabet.ranges, vec!['a'..='d']);
+
+    // Atoms outside of groups are part of the alphabet
+    let alphabet = regex::compile_raw(r"(?P<x>a)b").unwrap().alphabet();
+    assert_eq!(alphabet.ranges, vec!['a'..='b']);
+
+    assert!(regex::compile_raw(r".").unwrap().alphabet().unbounded);
+    assert!(regex::compile_raw(r"a\W").unwrap().alphabet().ranges.len() > 1);
+    assert!(!regex::compile_raw(r"a\W").unwrap().alphabet().unbounded);
+
+    // The padding around unanchored patterns reads any character
+    assert!(regex::compile(r"a").unwrap().alphabet().unbounded);
+
+    let compiled = regex::compile_matches(regex::compile(r"^\d+\z").unwrap(), "12");
+    assert_eq!(compiled.alphabet().ranges.first(), Some(&('0'..='9')));
+    assert!(!compiled.alphabet().unbounded);
+}
+
+#[test]
+fn trim() {
+    // The Glushkov construction only builds useful states
+    let automaton = regex::compile(r"(a)").unwrap();
+    assert!(automaton.is_trim());
+    assert_eq!(automaton.prune().get_nb_states(), automaton.get_nb_states());
+
+    // State 3 is unreachable and state 4 is dead
+    let automaton = Automaton::from_parts(
+        5,
+        vec![
+            (0, LabelSpec::Open("x".to_string()), 1),
+            (1, LabelSpec::Literal('a'), 2),
+            (2, LabelSpec::Close("x".to_string()), 2),
+            (3, LabelSpec::Literal('b'), 2),
+            (1, LabelSpec::Literal('c'), 4),
+        ]
+        .into_iter(),
+        vec![2].into_iter(),
+    );
+    assert!(!automaton.is_trim());
+    assert_eq!(automaton.nb_unreachable_states(), 1);
+    assert_eq!(automaton.nb_dead_states(), 1);
+
+    let pruned = automaton.prune();
+    assert!(pruned.is_trim());
+    assert_eq!(pruned.get_nb_states(), 3);
+    assert!(pruned.accepts_same_language(&automaton));
+}
+
+#[test]
+fn shared_adj_cache() {
+    let automaton = regex::compile(r"\w+@\w+").unwrap();
+    let texts = ["a@b", "ab@ba", "b@a"];
+
+    for text in texts.iter() {
+        regex::compile_matches(automaton.clone(), text);
+    }
+
+    // Lists computed while matching clones are kept by the original
+    assert_eq!(automaton.adj_cache_len(), 3);
+    let adj = automaton.get_adj_for_char('a');
+    assert!(Arc::ptr_eq(&adj, &automaton.clone().get_adj_for_char('a')));
+
+    // A cache with another size is not shared
+    let mut bounded = automaton.clone();
+    bounded.set_adj_cache_size(AdjCacheSize::Bounded(2));
+    assert_eq!(bounded.adj_cache_len(), 0);
+    assert_eq!(automaton.adj_cache_len(), 3);
+
+    let precomputed = regex::compile(r"\w+@\w+").unwrap();
+    precomputed.precompute_adjacency("ab@".chars());
+    assert_eq!(precomputed.adj_cache_len(), 3);
+    assert_eq!(precomputed.get_adj_for_char('@'), automaton.get_adj_for_char('@'));
+}
+
+#[test]
+fn to_dfa() {
+    let automaton = regex::compile_raw(r"a(b|c)*d").unwrap();
+    let dfa = automaton.to_dfa().unwrap();
+
+    // Simulate the automaton on all texts over a small alphabet
+    let mut texts = vec![String::new()];
+    let mut last = texts.clone();
+
+    for _ in 0..5 {
+        last = last
+            .iter()
+            .flat_map(|text| "abcdx".chars().map(move |x| format!("{}{}", text, x)))
+            .collect();
+        texts.extend(last.iter().cloned());
+    }
+
+    for text in &texts {
+        let states = text.chars().fold(
+            automaton.closure(std::iter::once(automaton.get_initial())),
+            |states, x| automaton.read(&states, x),
+        );
+
+        assert_eq!(dfa.is_match(text), automaton.is_accepting(&states));
+    }
+
+    assert!(dfa.is_match("abcbd"));
+    assert!(!dfa.is_match("abcb"));
+    assert!(dfa.get_nb_states() <= automaton.get_nb_states());
+
+    // Captures can't be handled by a DFA
+    let err = regex::compile(r"a").unwrap().to_dfa().unwrap_err();
+    assert_eq!(err.marker().variable().get_name(), "match");
+}
+
+#[test]
+fn complement() {
+    let automaton = regex::compile_raw(r"a+").unwrap();
+    let complement = automaton.complement().unwrap();
+    let dfa = complement.to_dfa().unwrap();
+
+    assert!(dfa.is_match("b"));
+    assert!(dfa.is_match(""));
+    assert!(dfa.is_match("aab"));
+    assert!(dfa.is_match("\u{E000}\u{10FFFF}"));
+    assert!(!dfa.is_match("aaa"));
+    assert!(!dfa.is_match("a"));
+
+    // Complementing twice gives back the same language
+    assert!(complement.complement().unwrap().accepts_same_language(&automaton));
+    assert!(regex::compile(r"a+").unwrap().complement().is_err());
+}
+
+#[test]
+fn iter_transitions() {
+    use std::collections::HashSet;
+
+    let automaton = regex::compile_raw(r"(a|b)").unwrap();
+    assert_eq!(automaton.nb_transitions(), 2);
+
+    let transitions: HashSet<_> = automaton
+        .iter_transitions()
+        .map(|(source, label, target)| (source, label.to_string(), target))
+        .collect();
+
+    let expected: HashSet<_> = vec![(0, "'a'".to_string(), 1), (0, "'b'".to_string(), 2)]
+        .into_iter()
+        .collect();
+
+    assert_eq!(automaton.iter_transitions().count(), 2);
+    assert_eq!(transitions, expected);
+
+    // Markers are transitions too
+    let automaton = regex::compile(r"(a|b)").unwrap();
+    assert_eq!(automaton.nb_transitions(), automaton.iter_transitions().count());
+    assert!(automaton.nb_transitions() > 4);
+}
@@ -1,17 +1,293 @@
+use std::cmp;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::time::Instant;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize};
 
 use super::regex;
+use super::regex::literal::LiteralMatcher;
+
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+pub struct BenchmarkCase {
+    name:     String,
+    comment:  String,
+    filename: String,
+    regex:    String,
+}
+
+impl BenchmarkCase {
+    fn new(name: &str, comment: &str, filename: &str, regex: &str) -> BenchmarkCase {
+        BenchmarkCase {
+            name:     name.to_string(),
+            comment:  comment.to_string(),
+            filename: filename.to_string(),
+            regex:    regex.to_string(),
+        }
+    }
+}
 
-struct BenchmarkCase {
-    name:     &'static str,
-    comment:  &'static str,
-    filename: &'static str,
-    regex:    &'static str,
+/// List of benchmarks that are run when no manifest is specified.
+fn default_benchmarks() -> Vec<BenchmarkCase> {
+    vec![
+        BenchmarkCase::new(
+            "First columns of CSV",
+            "Extract the first three columns of the input CSV document.",
+            "benchmarks/pablo_alto_trees.csv",
+            r"\n(?P<x>[^,]+),(?P<y>[^,]+),(?P<z>[^,]+),",
+        ),
+        BenchmarkCase::new(
+            "Pairs of words",
+            "Extract all pairs of words that are in the same sentence.",
+            "benchmarks/lorem_ipsum.txt",
+            r"[^\w](?P<word1>\w+)[^\w]((.|\n)*[^\w])?(?P<word2>\w+)[^\w]",
+        ),
+        BenchmarkCase::new(
+            "Close DNA",
+            "Find two substrings of a DNA sequence that are close from one another.",
+            "benchmarks/dna.txt",
+            r"TTAC.{0,1000}CACC",
+        ),
+        BenchmarkCase::new(
+            "Fixed DNA gap",
+            "Find two substrings of a DNA sequence separated by a fixed gap, most levels \
+             visited by the enumeration don't assign any group.",
+            "benchmarks/dna.txt",
+            r"TTAC.{500}CACC",
+        ),
+        BenchmarkCase::new(
+            "Ordered blocks",
+            "Match the whole document with a block of `e` followed by a block of `s`, each \
+             level can assign markers of both blocks.",
+            "benchmarks/lorem_ipsum.txt",
+            r"^((.|\n)*[^e])?(?P<block_e>e+)([^e](.|\n)*[^s]|[^es])?(?P<block_s>s+)([^s](.|\n)*)?$",
+        ),
+        BenchmarkCase::new(
+            "Literal word",
+            "Find a word of the input CSV document, which is also looked for with a substring \
+             search as it is a literal.",
+            "benchmarks/pablo_alto_trees.csv",
+            r"Good",
+        ),
+        BenchmarkCase::new(
+            "All substrings",
+            "Extract all non-empty substrings from the input document.",
+            "benchmarks/lorem_ipsum.txt",
+            r"(.|\n)+",
+        ),
+    ]
 }
 
-pub fn run_all_tests<T>(stream: &mut T) -> Result<(), std::io::Error>
+/// Read a list of benchmarks from a JSON manifest, which must contain a list of
+/// objects with fields `name`, `comment`, `filename` and `regex`.
+///
+/// Relative filenames are resolved from `base_dir`, an error is returned if
+/// one of the referenced files doesn't exist.
+pub fn parse_manifest<R>(reader: R, base_dir: &Path) -> Result<Vec<BenchmarkCase>, io::Error>
+where
+    R: Read,
+{
+    let mut benchmarks: Vec<BenchmarkCase> = serde_json::from_reader(reader).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid benchmark manifest: {}", err),
+        )
+    })?;
+
+    for benchmark in &mut benchmarks {
+        let path = base_dir.join(&benchmark.filename);
+
+        if !path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "file `{}` of benchmark \"{}\" doesn't exist",
+                    path.display(),
+                    benchmark.name
+                ),
+            ));
+        }
+
+        benchmark.filename = path.to_string_lossy().into_owned();
+    }
+
+    Ok(benchmarks)
+}
+
+/// Format used to report benchmark results.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// Human-readable report, written while the benchmark is running
+    Text,
+    /// One JSON object per line for each benchmark
+    Json,
+    /// A CSV header followed by one line for each benchmark
+    Csv,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<OutputFormat, String> {
+        match format {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(format!("unknown benchmark format `{}`", other)),
+        }
+    }
+}
+
+/// Statistics over the durations of several runs of a same phase.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    min:    Duration,
+    median: Duration,
+    mean:   Duration,
+}
+
+impl Stats {
+    fn from_durations(mut durations: Vec<Duration>) -> Stats {
+        if durations.is_empty() {
+            return Stats::default();
+        }
+
+        durations.sort();
+        let total: Duration = durations.iter().sum();
+        let middle = durations.len() / 2;
+
+        let median = match durations.len() % 2 {
+            0 => (durations[middle - 1] + durations[middle]) / 2,
+            _ => durations[middle],
+        };
+
+        Stats {
+            min: durations[0],
+            median,
+            mean: total / durations.len() as u32,
+        }
+    }
+}
+
+impl Serialize for Stats {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("Stats", 3)?;
+        state.serialize_field("min", &as_millis(self.min))?;
+        state.serialize_field("median", &as_millis(self.median))?;
+        state.serialize_field("mean", &as_millis(self.mean))?;
+        state.end()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.2?}", self.median)?;
+
+        if self.min != self.mean {
+            write!(f, " (min: {:.2?}, mean: {:.2?})", self.min, self.mean)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Measures collected while running a benchmark, the durations of each phase
+/// are given in milliseconds.
+#[derive(Debug, Serialize)]
+pub struct BenchmarkResult {
+    name:               String,
+    load_ms:            Stats,
+    compile_regex_ms:   Stats,
+    nb_states:          usize,
+    compile_matches_ms: Stats,
+    nb_levels:          usize,
+    enumerate_ms:       Stats,
+    nb_matches:         usize,
+    /// Time spent on a substring search, zero if the regex is not a literal.
+    literal_ms:         Stats,
+}
+
+impl BenchmarkResult {
+    /// Header of the CSV output, following fields order.
+    const CSV_HEADER: &'static str = "name,\
+                                      load_ms_min,load_ms_median,load_ms_mean,\
+                                      compile_regex_ms_min,compile_regex_ms_median,\
+                                      compile_regex_ms_mean,nb_states,\
+                                      compile_matches_ms_min,compile_matches_ms_median,\
+                                      compile_matches_ms_mean,nb_levels,\
+                                      enumerate_ms_min,enumerate_ms_median,enumerate_ms_mean,\
+                                      nb_matches,\
+                                      literal_ms_min,literal_ms_median,literal_ms_mean";
+
+    fn new(name: &str) -> BenchmarkResult {
+        BenchmarkResult {
+            name:               name.to_string(),
+            load_ms:            Stats::default(),
+            compile_regex_ms:   Stats::default(),
+            nb_states:          0,
+            compile_matches_ms: Stats::default(),
+            nb_levels:          0,
+            enumerate_ms:       Stats::default(),
+            nb_matches:         0,
+            literal_ms:         Stats::default(),
+        }
+    }
+
+    /// Write the result as a single record in the given format, nothing is
+    /// written for text format since the report is written during the
+    /// benchmark.
+    fn write<T>(&self, stream: &mut T, format: OutputFormat) -> Result<(), io::Error>
+    where
+        T: std::io::Write,
+    {
+        let csv_stats = |stats: &Stats| {
+            format!(
+                "{},{},{}",
+                as_millis(stats.min),
+                as_millis(stats.median),
+                as_millis(stats.mean)
+            )
+        };
+
+        match format {
+            OutputFormat::Text => Ok(()),
+            OutputFormat::Json => writeln!(stream, "{}", serde_json::to_string(self)?),
+            OutputFormat::Csv => writeln!(
+                stream,
+                "\"{}\",{},{},{},{},{},{},{},{}",
+                self.name.replace('"', "\"\""),
+                csv_stats(&self.load_ms),
+                csv_stats(&self.compile_regex_ms),
+                self.nb_states,
+                csv_stats(&self.compile_matches_ms),
+                self.nb_levels,
+                csv_stats(&self.enumerate_ms),
+                self.nb_matches,
+                csv_stats(&self.literal_ms)
+            ),
+        }
+    }
+}
+
+/// Run all benchmarks listed in a manifest, or the default list of benchmarks
+/// if no manifest is given.
+///
+/// Each phase of a benchmark is run `iterations` times, if there is more than
+/// one iteration, the first one is only used as a warmup and is not part of
+/// the statistics.
+pub fn run_all_tests<T>(
+    stream: &mut T,
+    manifest: Option<&str>,
+    format: OutputFormat,
+    iterations: usize,
+) -> Result<(), io::Error>
 where
     T: std::io::Write,
 {
@@ -19,108 +295,307 @@ where
         eprintln!("[WARNING]    Running benchmarks in debug mode.");
     }
 
-    let benchmarks = vec![
-        BenchmarkCase {
-            name:     "First columns of CSV",
-            comment:  "Extract the first three columns of the input CSV document.",
-            filename: "benchmarks/pablo_alto_trees.csv",
-            regex:    r"\n(?P<x>[^,]+),(?P<y>[^,]+),(?P<z>[^,]+),",
-        },
-        BenchmarkCase {
-            name:     "Pairs of words",
-            comment:  "Extract all pairs of words that are in the same sentence.",
-            filename: "benchmarks/lorem_ipsum.txt",
-            regex:    r"[^\w](?P<word1>\w+)[^\w]((.|\n)*[^\w])?(?P<word2>\w+)[^\w]",
-        },
-        BenchmarkCase {
-            name:     "Close DNA",
-            comment:  "Find two substrings of a DNA sequence that are close from one another.",
-            filename: "benchmarks/dna.txt",
-            regex:    r"TTAC.{0,1000}CACC",
-        },
-        BenchmarkCase {
-            name:     "All substrings",
-            comment:  "Extract all non-empty substrings from the input document.",
-            filename: "benchmarks/lorem_ipsum.txt",
-            regex:    r"(.|\n)+",
-        },
-    ];
+    let benchmarks = match manifest {
+        None => default_benchmarks(),
+        Some(manifest) => {
+            let base_dir = Path::new(manifest).parent().unwrap_or_else(|| Path::new(""));
+            parse_manifest(File::open(manifest)?, base_dir)?
+        }
+    };
+
+    if format == OutputFormat::Csv {
+        writeln!(stream, "{}", BenchmarkResult::CSV_HEADER)?;
+    }
 
     for benchmark in benchmarks {
-        let mut input = String::new();
+        let mut result = BenchmarkResult::new(&benchmark.name);
+
+        // Only the text report is written along the benchmark.
+        let mut report = match format {
+            OutputFormat::Text => Some(&mut *stream),
+            _ => None,
+        };
 
-        write!(stream, "-- {} ---------------\n", benchmark.name)?;
-        write!(stream, "{}\n", benchmark.comment)?;
+        if let Some(report) = &mut report {
+            writeln!(report, "-- {} ---------------", benchmark.name)?;
+            writeln!(report, "{}", benchmark.comment)?;
+        }
 
         // Read input file content.
-        write!(stream, " - Loading file content ... ")?;
-        stream.flush()?;
-        let timer = Instant::now();
+        if let Some(report) = &mut report {
+            write!(report, " - Loading file content ... ")?;
+            report.flush()?;
+        }
 
-        File::open(benchmark.filename)?.read_to_string(&mut input)?;
+        let (stats, input) = measure(iterations, || {
+            let mut input = String::new();
+            File::open(&benchmark.filename)?.read_to_string(&mut input)?;
+            Ok::<_, io::Error>(input)
+        });
 
-        write!(
-            stream,
-            "{:.2?}\t({} bytes)\n",
-            timer.elapsed(),
-            input.as_bytes().len()
-        )?;
+        let input = input?;
+        result.load_ms = stats;
+
+        if let Some(report) = &mut report {
+            writeln!(report, "{}\t({} bytes)", stats, input.len())?;
+        }
 
         // Run the test itself.
-        run_test(stream, benchmark.regex, input)?;
+        run_test(&mut report, &benchmark.regex, input, iterations, &mut result)?;
+
+        if let Some(report) = &mut report {
+            writeln!(report)?;
+        }
 
-        write!(stream, "\n")?;
+        result.write(stream, format)?;
     }
 
     Ok(())
 }
 
 /// Compute time spent on running the regex over the given input file.
-fn run_test<T>(stream: &mut T, regex: &str, input: String) -> Result<(), std::io::Error>
+fn run_test<T>(
+    report: &mut Option<&mut T>,
+    regex: &str,
+    input: String,
+    iterations: usize,
+    result: &mut BenchmarkResult,
+) -> Result<(), std::io::Error>
 where
     T: std::io::Write,
 {
+    let literal = regex::as_literal(regex, regex::Flags::default());
+
     // Compile the regex.
-    write!(stream, " - Compiling regex      ... ")?;
-    stream.flush()?;
-    let timer = Instant::now();
+    if let Some(report) = report {
+        write!(report, " - Compiling regex      ... ")?;
+        report.flush()?;
+    }
 
-    let regex = regex::compile(regex);
+    let (stats, regex) = measure(iterations, || regex::compile(regex));
+    let regex = regex.map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    result.compile_regex_ms = stats;
+    result.nb_states = regex.get_nb_states();
 
-    write!(
-        stream,
-        "{:.2?}\t({} states)\n",
-        timer.elapsed(),
-        regex.get_nb_states()
-    )?;
+    if let Some(report) = report {
+        writeln!(report, "{}\t({} states)", stats, result.nb_states)?;
+    }
 
     // Prepare the enumeration.
-    write!(stream, " - Compiling matches    ... ")?;
-    stream.flush()?;
-    let timer = Instant::now();
+    if let Some(report) = report {
+        write!(report, " - Compiling matches    ... ")?;
+        report.flush()?;
+    }
 
-    let compiled_matches = regex::compile_matches(regex, &input);
+    let (stats, compiled_matches) = measure(iterations, || {
+        regex::compile_matches(regex.clone(), &input)
+    });
+    result.compile_matches_ms = stats;
+    result.nb_levels = compiled_matches.get_nb_levels();
 
-    write!(
-        stream,
-        "{:.2?}\t({} levels)\n",
-        timer.elapsed(),
-        compiled_matches.get_nb_levels()
-    )?;
+    if let Some(report) = report {
+        writeln!(report, "{}\t({} levels)", stats, result.nb_levels)?;
+    }
 
     // Enumerate matches.
-    write!(stream, " - Enumerate matches    ... ")?;
-    stream.flush()?;
-    let timer = Instant::now();
+    if let Some(report) = report {
+        write!(report, " - Enumerate matches    ... ")?;
+        report.flush()?;
+    }
 
-    let count_matches = compiled_matches.iter().count();
+    let (stats, count_matches) = measure(iterations, || compiled_matches.iter().count());
+    result.enumerate_ms = stats;
+    result.nb_matches = count_matches;
 
-    write!(
-        stream,
-        "{:.2?}\t({} matches)\n",
-        timer.elapsed(),
-        count_matches
-    )?;
+    if let Some(report) = report {
+        writeln!(report, "{}\t({} matches)", stats, result.nb_matches)?;
+    }
+
+    // Compare with a substring search if the regex is a literal.
+    if let Some(literal) = literal {
+        if let Some(report) = report {
+            write!(report, " - Literal search       ... ")?;
+            report.flush()?;
+        }
+
+        let (stats, count_matches) = measure(iterations, || {
+            LiteralMatcher::new(&literal, &input).count()
+        });
+        result.literal_ms = stats;
+
+        if let Some(report) = report {
+            writeln!(report, "{}\t({} matches)", stats, count_matches)?;
+        }
+    }
 
     Ok(())
 }
+
+/// Run a function and return the time spent running it along with its output.
+fn time<F, R>(f: F) -> (Duration, R)
+where
+    F: FnOnce() -> R,
+{
+    let timer = Instant::now();
+    let output = f();
+    (timer.elapsed(), output)
+}
+
+/// Run a function `iterations` times (at least once) and return statistics over
+/// the time spent for each run along with the output of the last run. If there
+/// are several iterations, the first one is considered as a warmup and is
+/// discarded.
+fn measure<F, R>(iterations: usize, mut f: F) -> (Stats, R)
+where
+    F: FnMut() -> R,
+{
+    let mut durations = Vec::with_capacity(cmp::max(1, iterations));
+    let mut output = None;
+
+    for _ in 0..cmp::max(1, iterations) {
+        // Only the last output is kept, the previous one is dropped before
+        // running again.
+        drop(output.take());
+        let (duration, run_output) = time(&mut f);
+        durations.push(duration);
+        output = Some(run_output);
+    }
+
+    if durations.len() > 1 {
+        durations.remove(0);
+    }
+
+    (Stats::from_durations(durations), output.unwrap())
+}
+
+/// Convert a duration to a floating number of milliseconds.
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000.
+}
+
+//  _____         _
+// |_   _|__  ___| |_ ___
+//   | |/ _ \/ __| __/ __|
+//   | |  __/\__ \ |_\__ \
+//   |_|\___||___/\__|___/
+//
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+
+    use super::{default_benchmarks, parse_manifest, run_test, BenchmarkCase, BenchmarkResult};
+    use super::{measure, OutputFormat};
+
+    #[test]
+    fn manifest() {
+        let manifest = r#"[
+            {
+                "name": "Close DNA",
+                "comment": "Find close substrings.",
+                "filename": "dna.txt",
+                "regex": "TTAC.{0,1000}CACC"
+            }
+        ]"#;
+
+        let benchmarks = parse_manifest(manifest.as_bytes(), Path::new("benchmarks")).unwrap();
+        assert_eq!(
+            benchmarks,
+            vec![BenchmarkCase::new(
+                "Close DNA",
+                "Find close substrings.",
+                "benchmarks/dna.txt",
+                "TTAC.{0,1000}CACC",
+            )]
+        );
+    }
+
+    #[test]
+    fn manifest_missing_file() {
+        let manifest = r#"[
+            {"name": "Missing", "comment": "", "filename": "missing.txt", "regex": "a"}
+        ]"#;
+
+        let err = parse_manifest(manifest.as_bytes(), Path::new("benchmarks")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn manifest_invalid() {
+        let err = parse_manifest("[{}]".as_bytes(), Path::new("benchmarks")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn json_output() {
+        let mut output = Vec::new();
+
+        for benchmark in default_benchmarks() {
+            BenchmarkResult::new(&benchmark.name)
+                .write(&mut output, OutputFormat::Json)
+                .unwrap();
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        let names: Vec<_> = output
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                record["name"].as_str().unwrap().to_string()
+            })
+            .collect();
+
+        let expected: Vec<_> = default_benchmarks().into_iter().map(|b| b.name).collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn csv_output() {
+        let mut output = Vec::new();
+        BenchmarkResult::new(r#"Some "quoted" name"#)
+            .write(&mut output, OutputFormat::Csv)
+            .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(r#""Some ""quoted"" name",0,0,0,"#));
+        assert_eq!(
+            output.trim_end().split(',').count(),
+            BenchmarkResult::CSV_HEADER.split(',').count()
+        );
+    }
+
+    #[test]
+    fn iterations() {
+        let mut count = 0;
+        let (_, output) = measure(3, || {
+            count += 1;
+            count
+        });
+        assert_eq!(output, 3);
+
+        let mut result = BenchmarkResult::new("Iterations");
+        let mut report = Some(Vec::new());
+        let input = "a@b aa@bb".to_string();
+        run_test(&mut report.as_mut(), r"\w+@\w+", input, 3, &mut result).unwrap();
+
+        assert_eq!(result.nb_matches, 5);
+        assert!(result.enumerate_ms.min <= result.enumerate_ms.median);
+
+        let report = String::from_utf8(report.unwrap()).unwrap();
+        assert_eq!(report.lines().count(), 3);
+    }
+
+    #[test]
+    fn literal() {
+        let mut result = BenchmarkResult::new("Literal");
+        let mut report = Some(Vec::new());
+        let input = "abcabc abc".to_string();
+        run_test(&mut report.as_mut(), "abc", input, 1, &mut result).unwrap();
+        assert_eq!(result.nb_matches, 3);
+
+        let report = String::from_utf8(report.unwrap()).unwrap();
+        assert_eq!(report.lines().count(), 4);
+        assert!(report.lines().last().unwrap().ends_with("(3 matches)"));
+    }
+}
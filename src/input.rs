@@ -0,0 +1,300 @@
+use std::iter;
+use std::ops::Range;
+use std::str;
+
+//  ___                   _
+// |_ _|_ __  _ __  _   _| |_
+//  | || '_ \| '_ \| | | | __|
+//  | || | | | |_) | |_| | |_
+// |___|_| |_| .__/ \__,_|\__|
+//           |_|
+
+/// A text decoded from raw bytes of an input.
+///
+/// When the input is not valid UTF-8, it can be decoded lossily: each invalid
+/// sequence is then replaced by U+FFFD (which is 3 bytes long) in the text. As
+/// a consequence, offsets in the text may not match offsets in the source
+/// anymore, `Input::source_offset` allows to convert them back.
+pub struct Input {
+    pub text: String,
+
+    /// Pairs of offsets `(text offset, source offset)` at the end of each
+    /// replaced sequence, sorted by increasing offsets.
+    breakpoints: Vec<(usize, usize)>,
+
+    /// How lines of the text are terminated.
+    terminator: LineTerminator,
+}
+
+impl Input {
+    /// Decode bytes as an UTF-8 text. If `lossy` is set, invalid sequences are
+    /// replaced, overwise an error is returned.
+    pub fn from_bytes(bytes: Vec<u8>, lossy: bool) -> Result<Input, str::Utf8Error> {
+        let bytes = match String::from_utf8(bytes) {
+            Ok(text) => {
+                return Ok(Input {
+                    text,
+                    breakpoints: Vec::new(),
+                    terminator: LineTerminator::default(),
+                })
+            }
+            Err(err) if !lossy => return Err(err.utf8_error()),
+            Err(err) => err.into_bytes(),
+        };
+
+        let mut text = String::with_capacity(bytes.len());
+        let mut breakpoints = Vec::new();
+        let mut remaining = &bytes[..];
+
+        while !remaining.is_empty() {
+            match str::from_utf8(remaining) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    break;
+                }
+                Err(err) => {
+                    let valid_len = err.valid_up_to();
+                    let invalid_len = err.error_len().unwrap_or(remaining.len() - valid_len);
+
+                    // The prefix is valid UTF-8 according to the error
+                    text.push_str(str::from_utf8(&remaining[..valid_len]).unwrap());
+                    text.push(char::REPLACEMENT_CHARACTER);
+
+                    remaining = &remaining[valid_len + invalid_len..];
+                    breakpoints.push((text.len(), bytes.len() - remaining.len()));
+                }
+            }
+        }
+
+        Ok(Input {
+            text,
+            breakpoints,
+            terminator: LineTerminator::default(),
+        })
+    }
+
+    /// Set how lines of the text are terminated.
+    pub fn with_line_terminator(mut self, terminator: LineTerminator) -> Input {
+        self.terminator = terminator;
+        self
+    }
+
+    /// Remove the line terminator at the end of the text, if there is one, so
+    /// that `$` matches before it as grep does. Only one terminator is
+    /// removed: preceding blank lines are kept.
+    ///
+    /// As only the end of the text is removed, offsets of the remaining text
+    /// are unchanged.
+    pub fn strip_line_terminator(mut self) -> Input {
+        if let Some(stripped) = self.terminator.strip_suffix(&self.text) {
+            let len = stripped.len();
+            self.text.truncate(len);
+        }
+
+        self
+    }
+
+    /// Index the lines of the text, as terminated by `Input::with_line_terminator`.
+    pub fn line_index(&self) -> LineIndex {
+        LineIndex::with_terminator(&self.text, self.terminator)
+    }
+
+    /// Check if some sequences of the source had to be replaced.
+    pub fn is_lossy(&self) -> bool {
+        !self.breakpoints.is_empty()
+    }
+
+    /// Convert an offset in the text into an offset in the source bytes.
+    pub fn source_offset(&self, offset: usize) -> usize {
+        let index = match self.breakpoints.binary_search_by_key(&offset, |&(text, _)| text) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+
+        match index {
+            0 => offset,
+            _ => {
+                let (text_offset, source_offset) = self.breakpoints[index - 1];
+                source_offset + offset - text_offset
+            }
+        }
+    }
+}
+
+//  _     _              ___           _
+// | |   (_)_ __   ___  |_ _|_ __   __| | _____  __
+// | |   | | '_ \ / _ \  | || '_ \ / _` |/ _ \ \/ /
+// | |___| | | | |  __/  | || | | | (_| |  __/>  <
+// |_____|_|_| |_|\___| |___|_| |_|\__,_|\___/_/\_\
+//
+
+/// Sequence of characters ending a line.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LineTerminator {
+    /// Lines end with `\n`.
+    #[default]
+    Lf,
+    /// Lines end with `\r\n`, or with a lone `\n`.
+    CrLf,
+}
+
+impl LineTerminator {
+    /// Remove one line terminator at the end of a text, if there is one.
+    fn strip_suffix(self, text: &str) -> Option<&str> {
+        let text = text.strip_suffix('\n')?;
+
+        match self {
+            LineTerminator::Lf => Some(text),
+            LineTerminator::CrLf => Some(text.strip_suffix('\r').unwrap_or(text)),
+        }
+    }
+}
+
+/// Positions of the lines of a text, allowing to find the line containing an
+/// offset in logarithmic time.
+pub struct LineIndex {
+    /// Offset of the first byte of each line, the last line ends with the
+    /// text.
+    starts: Vec<usize>,
+    /// Offset of the end of each line, before its line terminator.
+    ends:   Vec<usize>,
+    /// Length of the indexed text
+    len:    usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        LineIndex::with_terminator(text, LineTerminator::Lf)
+    }
+
+    /// Index the lines of a text, where lines are ended by a given terminator.
+    pub fn with_terminator(text: &str, terminator: LineTerminator) -> LineIndex {
+        let starts: Vec<_> = iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+
+        let ends = starts
+            .iter()
+            .skip(1)
+            .map(|&next_start| {
+                let line = &text[..next_start];
+                terminator.strip_suffix(line).unwrap_or(line).len()
+            })
+            .chain(iter::once(text.len()))
+            .collect();
+
+        LineIndex {
+            starts,
+            ends,
+            len: text.len(),
+        }
+    }
+
+    /// Number of lines in the text, which is never 0 as an empty text still
+    /// contains an empty line.
+    pub fn nb_lines(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Get the 0-based index of the line containing a byte offset. An offset
+    /// pointing to a line break belongs to the line it ends.
+    pub fn line_of(&self, offset: usize) -> usize {
+        debug_assert!(offset <= self.len);
+
+        match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        }
+    }
+
+    /// Get the span of a line in the text, without its line terminator.
+    pub fn line_span(&self, line: usize) -> Range<usize> {
+        self.starts[line]..self.ends[line]
+    }
+}
+
+//  _____         _
+// |_   _|__  ___| |_ ___
+//   | |/ _ \/ __| __/ __|
+//   | |  __/\__ \ |_\__ \
+//   |_|\___||___/\__|___/
+//
+
+#[cfg(test)]
+mod tests {
+    use super::{Input, LineIndex};
+
+    #[test]
+    fn valid_input() {
+        let input = Input::from_bytes(b"abc".to_vec(), false).unwrap();
+        assert_eq!(input.text, "abc");
+        assert!(!input.is_lossy());
+        assert_eq!(input.source_offset(2), 2);
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert!(Input::from_bytes(b"ab\xffcd".to_vec(), false).is_err());
+    }
+
+    #[test]
+    fn lossy_input() {
+        // The single invalid byte becomes a 3 bytes long replacement character
+        let input = Input::from_bytes(b"ab\xffcd\xe2\x82ef".to_vec(), true).unwrap();
+        assert_eq!(input.text, "ab\u{fffd}cd\u{fffd}ef");
+        assert!(input.is_lossy());
+
+        assert_eq!(input.source_offset(0), 0);
+        assert_eq!(input.source_offset(2), 2);
+        assert_eq!(input.source_offset(5), 3);
+        assert_eq!(input.source_offset(7), 5);
+        assert_eq!(input.source_offset(10), 7);
+        assert_eq!(input.source_offset(12), 9);
+    }
+
+    #[test]
+    fn line_index() {
+        let index = LineIndex::new("ab\n\ncd\n");
+        assert_eq!(index.nb_lines(), 4);
+        assert_eq!(index.line_of(0), 0);
+        assert_eq!(index.line_of(2), 0);
+        assert_eq!(index.line_of(3), 1);
+        assert_eq!(index.line_of(5), 2);
+        assert_eq!(index.line_of(7), 3);
+        assert_eq!(index.line_span(0), 0..2);
+        assert_eq!(index.line_span(1), 3..3);
+        assert_eq!(index.line_span(2), 4..6);
+        assert_eq!(index.line_span(3), 7..7);
+
+        assert_eq!(LineIndex::new("").line_span(0), 0..0);
+    }
+
+    #[test]
+    fn crlf_line_index() {
+        use super::LineTerminator;
+
+        let index = LineIndex::with_terminator("ab\r\n\r\ncd\nef\r", LineTerminator::CrLf);
+        assert_eq!(index.nb_lines(), 4);
+        assert_eq!(index.line_of(2), 0);
+        assert_eq!(index.line_of(3), 0);
+        assert_eq!(index.line_of(4), 1);
+        assert_eq!(index.line_span(0), 0..2);
+        assert_eq!(index.line_span(1), 4..4);
+        assert_eq!(index.line_span(2), 6..8);
+        assert_eq!(index.line_span(3), 9..12);
+
+        // Carriage returns are kept by default
+        let index = LineIndex::new("ab\r\ncd");
+        assert_eq!(index.line_span(0), 0..3);
+
+        let strip = |bytes: &[u8], terminator| {
+            let input = Input::from_bytes(bytes.to_vec(), false).unwrap();
+            input.with_line_terminator(terminator).strip_line_terminator().text
+        };
+
+        assert_eq!(strip(b"ab\r\n\r\n\n", LineTerminator::CrLf), "ab\r\n\r\n");
+        assert_eq!(strip(b"ab\r\n\r\n", LineTerminator::CrLf), "ab\r\n");
+        assert_eq!(strip(b"ab\r\n\r\n", LineTerminator::Lf), "ab\r\n\r");
+        assert_eq!(strip(b"ab", LineTerminator::Lf), "ab");
+    }
+}
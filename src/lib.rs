@@ -0,0 +1,13 @@
+pub mod automaton;
+pub mod benchmark;
+pub mod input;
+pub mod mapping;
+pub mod matrix;
+pub mod progress;
+pub mod regex;
+pub mod replace;
+
+mod tools;
+
+extern crate regex as lib_regex;
+extern crate regex_syntax;
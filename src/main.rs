@@ -1,30 +1,59 @@
-mod automaton;
-mod benchmark;
-mod mapping;
-mod matrix;
-mod progress;
-mod regex;
-mod tools;
-
 extern crate clap;
-extern crate regex as lib_regex;
-extern crate regex_syntax;
+extern crate enum_spanner_rs;
+#[cfg(feature = "gzip")]
+extern crate flate2;
 
-use std::fs::File;
+use std::cell::Cell;
+use std::cmp;
+use std::env;
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::io::{stdin, stdout};
+use std::io;
+use std::io::{stderr, stdin, stdout, BufWriter, IsTerminal};
+use std::iter;
+use std::process;
 use std::time;
 
 use clap::{App, Arg};
 
-#[derive(PartialEq, Eq)]
+use enum_spanner_rs::automaton::Automaton;
+use enum_spanner_rs::input::{Input, LineIndex, LineTerminator};
+use enum_spanner_rs::mapping::indexed_dag::{CleanStrategy, ToggleProgress};
+use enum_spanner_rs::replace::{self, Preference, Template};
+use enum_spanner_rs::{benchmark, mapping, regex};
+
+/// Exit status when at least one match was found.
+const EXIT_MATCH: i32 = 0;
+/// Exit status when no match was found.
+const EXIT_NO_MATCH: i32 = 1;
+/// Exit status when an error occured.
+const EXIT_ERROR: i32 = 2;
+
 enum DisplayFormat {
+    /// Don't display anything, the result is only given by the exit code
+    Quiet,
     /// Only display the count of matches
     Count,
-    /// Display in the re-compare format: https://github.com/gchase/re-compare
-    CompareFormat,
-    /// Human-readable format
-    Verbose { show_offset: bool },
+    /// Display in the re-compare format: https://github.com/gchase/re-compare,
+    /// optionally including the span of each named group
+    CompareFormat { groups: bool },
+    /// Display the text where matches are replaced with a template, each line
+    /// being prefixed with the filename
+    Replace(Template),
+    /// Only display distinct spans of matches
+    Spans,
+    /// Display the text of leftmost non-overlapping matches
+    OnlyMatching(Preference),
+    /// Display lines containing a match, surrounded by some context lines
+    Context { before: usize, after: usize },
+    /// Display a CSV row for each match, with a column for each named group
+    Csv { columns: Vec<String> },
+    /// Display a single JSON array, with an object for each match of all
+    /// inputs, `empty` telling if no object was written yet
+    JsonArray { empty: Cell<bool> },
+    /// Human-readable format, where matching lines can be reprinted with
+    /// highlighted matches
+    Verbose { show_offset: bool, color: bool },
 }
 
 fn main() {
@@ -34,7 +63,7 @@ fn main() {
     // |  __/ (_| | |  \__ \  __/ |
     // |_|   \__,_|_|  |___/\___|_|
     //
-    let matches = App::new("Enumerate matchings")
+    let app = App::new("Enumerate matchings")
         .version("0.1")
         .author("Rémi Dupré <remi.dupre@ens-paris-saclay.fr>")
         .about("Enumerate all matches of a regular expression on a text.")
@@ -43,14 +72,152 @@ fn main() {
                 .long("benchmark")
                 .help("Run benchmarks."),
         )
+        .arg(
+            Arg::with_name("benchmark_manifest")
+                .long("benchmark-manifest")
+                .takes_value(true)
+                .value_name("path")
+                .help("A JSON file listing benchmarks to run instead of the default ones."),
+        )
+        .arg(
+            Arg::with_name("benchmark_format")
+                .long("benchmark-format")
+                .takes_value(true)
+                .possible_values(&["text", "json", "csv"])
+                .default_value("text")
+                .help("Format of the benchmarks report."),
+        )
+        .arg(
+            Arg::with_name("benchmark_iterations")
+                .long("benchmark-iterations")
+                .takes_value(true)
+                .value_name("N")
+                .default_value("1")
+                .validator(is_number)
+                .help("Number of runs of each benchmark, the first run is a warmup if N > 1."),
+        )
         .arg(
             Arg::with_name("regex")
-                .help("The pattern to look for.")
-                .required(true),
+                .help(
+                    "The pattern to look for, it is read as the first file if patterns are given \
+                     with --regex or --pattern-file.",
+                )
+                .required_unless_one(&["regexes", "pattern_file"]),
+        )
+        .arg(
+            Arg::with_name("regexes")
+                .short("e")
+                .long("regex")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("pattern")
+                .help(
+                    "A pattern to look for, can be repeated to match any of several patterns. \
+                     Each of them is tagged with a group patternN, N starting from 1.",
+                ),
+        )
+        .arg(
+            Arg::with_name("pattern_file")
+                .short("f")
+                .long("pattern-file")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("path")
+                .help(
+                    "Read patterns to look for from a file, one per line, as --regex does. \
+                     Empty lines and lines starting with # are ignored.",
+                ),
         )
         .arg(
             Arg::with_name("file")
-                .help("The file to be read, if none is specified, STDIN is used."),
+                .multiple(true)
+                .help("The files to be read, if none is specified, STDIN is used."),
+        )
+        .arg(
+            Arg::with_name("with_filename")
+                .short("H")
+                .long("with-filename")
+                .overrides_with("no_filename")
+                .help("Prefix each match with the name of the file, default with several files."),
+        )
+        .arg(
+            Arg::with_name("no_filename")
+                .long("no-filename")
+                .overrides_with("with_filename")
+                .help("Never prefix matches with the name of the file."),
+        )
+        .arg(
+            Arg::with_name("lossy")
+                .long("lossy")
+                .help(
+                    "Replace invalid UTF-8 sequences of the input with U+FFFD instead of \
+                     failing. Offsets are still given in bytes of the original input.",
+                ),
+        )
+        .arg(
+            Arg::with_name("crlf")
+                .long("crlf")
+                .help(
+                    "Treat \\r\\n as a single line terminator, which is not displayed with \
+                     lines and is removed at the end of the input as \\n is.",
+                ),
+        )
+        .arg(
+            Arg::with_name("keep_trailing_newline")
+                .long("keep-trailing-newline")
+                .help(
+                    "Keep the line terminator at the end of the input, which is removed by \
+                     default so that $ matches before it.",
+                ),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("seconds")
+                .validator(is_duration)
+                .help("Give up on an input if computing its matches takes too long."),
+        )
+        .arg(
+            Arg::with_name("max_levels")
+                .long("max-levels")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number)
+                .help(
+                    "Give up on an input if the index of its matches needs more than N levels, \
+                     which bounds the memory used.",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_width")
+                .long("max-width")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number)
+                .help(
+                    "Give up on an input if a level of the index of its matches has more than N \
+                     vertices, which reports patterns that blow up.",
+                ),
+        )
+        .arg(
+            Arg::with_name("clean_every")
+                .long("clean-every")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_positive_number)
+                .help(
+                    "Clean levels of the DAG every N > 0 levels instead of at levels divisible \
+                     by powers of two.",
+                ),
+        )
+        .arg(
+            Arg::with_name("no_clean")
+                .long("no-clean")
+                .conflicts_with("clean_every")
+                .help("Never clean levels of the DAG (faster but uses more memory)."),
         )
         .arg(
             Arg::with_name("count")
@@ -58,17 +225,143 @@ fn main() {
                 .long("count")
                 .help("Display the number of matches instead."),
         )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help(
+                    "Don't output anything, exit with status 0 if there is a match and 1 \
+                     otherwise.",
+                ),
+        )
+        .arg(
+            Arg::with_name("full_match")
+                .short("x")
+                .long("full-match")
+                .help(
+                    "Don't output anything, exit with status 0 if the whole text matches the \
+                     pattern and 1 otherwise.",
+                ),
+        )
+        .arg(
+            Arg::with_name("replace")
+                .short("r")
+                .long("replace")
+                .takes_value(true)
+                .value_name("template")
+                .help(
+                    "Print the text with leftmost-longest non-overlapping matches replaced \
+                     with a template, where ${name} refers to the group `name` and $$ to `$`. \
+                     The filename is prefixed to each line of the output.",
+                ),
+        )
+        .arg(
+            Arg::with_name("spans_only")
+                .long("spans-only")
+                .help("Display the offsets of each distinct matching part, ignoring groups."),
+        )
+        .arg(
+            Arg::with_name("only_matching")
+                .short("o")
+                .long("only-matching")
+                .help(
+                    "Display the text of leftmost non-overlapping matches, which are the \
+                     shortest ones if all repetitions of the pattern are non-greedy and the \
+                     longest ones overwise.",
+                ),
+        )
+        .arg(
+            Arg::with_name("leftmost_longest")
+                .long("leftmost-longest")
+                .conflicts_with_all(&["use_naive", "use_naive_cubic", "use_naive_quadratic"])
+                .help(
+                    "Only keep leftmost-longest matches as most regex engines do: the longest \
+                     match starting first is kept, then the search resumes after its end. Other \
+                     matches, overlapping or not the longest, are not displayed.",
+                ),
+        )
+        .arg(
+            Arg::with_name("csv")
+                .long("csv")
+                .help(
+                    "Output matches as CSV, with a header row of group names and a column for \
+                     each group, empty when it is not assigned.",
+                ),
+        )
+        .arg(
+            Arg::with_name("csv_match")
+                .long("csv-match")
+                .requires("csv")
+                .help("Include the group `match`, containing the whole match, in CSV output."),
+        )
+        .arg(
+            Arg::with_name("json_array")
+                .long("json-array")
+                .conflicts_with("csv")
+                .help(
+                    "Output matches as a JSON array, with an object for each match containing its \
+                     text, its span and the spans of its groups.",
+                ),
+        )
+        .arg(
+            Arg::with_name("after_context")
+                .short("A")
+                .long("after-context")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number)
+                .help("Display lines containing a match, followed by N lines of context."),
+        )
+        .arg(
+            Arg::with_name("before_context")
+                .short("B")
+                .long("before-context")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number)
+                .help("Display lines containing a match, preceded by N lines of context."),
+        )
+        .arg(
+            Arg::with_name("context")
+                .short("C")
+                .long("context")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number)
+                .help(
+                    "Display lines containing a match, surrounded by N lines of context. \
+                     Overlapping contexts are merged and non-contiguous groups of lines are \
+                     separated by `--`.",
+                ),
+        )
         .arg(
             Arg::with_name("bytes_offset")
                 .short("b")
                 .long("bytes-offset")
                 .help("Print the 0-based offset of each matching part and groups."),
         )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .help(
+                    "Reprint lines of each match with highlighted groups, `auto` enables it \
+                     when the output is a terminal and NO_COLOR is not set.",
+                ),
+        )
         .arg(Arg::with_name("compare")
                 .long("compare")
                 .help("Output matches in a format suitable with re-compare: \
                        https://github.com/gchase/re-compare")
         )
+        .arg(
+            Arg::with_name("compare_groups")
+                .long("compare-groups")
+                .requires("compare")
+                .help("Add the span of each named group to the output of --compare."),
+        )
         .arg(
             Arg::with_name("use_naive")
                 .long("naive")
@@ -86,31 +379,186 @@ fn main() {
                 .help("Use a naive algorithm to enumerate all subwords that match the input regex. \
                        This algorithm runs in time O(|regex||text|²)"),
         )
+        .arg(
+            Arg::with_name("dotall")
+                .long("dotall")
+                .help("Allow `.` to match a line break, as the inline flag `(?s)` does."),
+        )
+        .arg(
+            Arg::with_name("no_match_group")
+                .long("no-match-group")
+                .help("Don't assign the whole match to the implicit group `match`, only groups of \
+                       the pattern are assigned."),
+        )
+        .arg(
+            Arg::with_name("show_hir")
+                .long("show-hir")
+                .help("Display the simplified tree built from the pattern."),
+        )
         .arg(
             Arg::with_name("debug_infos")
                 .short("i")
                 .long("debug-infos")
                 .help("Display debuging infos"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("dot")
+                .long("dot")
+                .takes_value(true)
+                .value_name("path")
+                .help("Write the automaton of the pattern to a graphviz file."),
+        )
+        .arg(
+            Arg::with_name("jump_trace")
+                .long("jump-trace")
+                .takes_value(true)
+                .value_name("path")
+                .help(
+                    "Write a trace of the jump function built for each input to a file, this can \
+                     be slow and large for long inputs.",
+                ),
+        );
+
+    #[cfg(feature = "gzip")]
+    let app = app.arg(
+        Arg::with_name("gzip")
+            .short("z")
+            .long("gzip")
+            .help("Decompress inputs with gzip, default for files ending with `.gz`."),
+    );
+
+    // Usage errors must not be confused with the absence of match
+    let matches = app.get_matches_safe().unwrap_or_else(|err| match err.kind {
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => err.exit(),
+        _ => {
+            eprintln!("{}", err.message);
+            process::exit(EXIT_ERROR);
+        }
+    });
 
     // Extract parameters
     let benchmark = matches.is_present("benchmark");
+    let benchmark_manifest = matches.value_of("benchmark_manifest");
+    let benchmark_format = matches
+        .value_of("benchmark_format")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let benchmark_iterations = matches
+        .value_of("benchmark_iterations")
+        .unwrap()
+        .parse()
+        .expect("Invalid number of benchmark iterations");
+    let full_match = matches.is_present("full_match");
+    let quiet = matches.is_present("quiet") || full_match;
     let count = matches.is_present("count");
-    let regex_str = matches.value_of("regex").unwrap();
+    let has_patterns = matches.is_present("regexes") || matches.is_present("pattern_file");
+    let mut patterns: Vec<String> = matches
+        .values_of("regexes")
+        .map(|patterns| patterns.map(String::from).collect())
+        .unwrap_or_default();
+
+    for path in matches.values_of("pattern_file").into_iter().flatten() {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                patterns.extend(regex::read_patterns(&content).into_iter().map(String::from))
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                process::exit(EXIT_ERROR);
+            }
+        }
+    }
+
+    let regex_string = match has_patterns {
+        true if patterns.is_empty() => {
+            eprintln!("No pattern to look for.");
+            process::exit(EXIT_ERROR);
+        }
+        true => regex::union(&patterns),
+        false => matches.value_of("regex").unwrap().to_string(),
+    };
+    let regex_str = regex_string.as_str();
     let show_offset = matches.is_present("bytes_offset");
+    let color = match matches.value_of("color").unwrap() {
+        "always" => true,
+        "never" => false,
+        _ => {
+            let no_color = env::var_os("NO_COLOR").is_some_and(|var| !var.is_empty());
+            !no_color && stdout().is_terminal()
+        }
+    };
+    let dotall = matches.is_present("dotall");
+    let no_match_group = matches.is_present("no_match_group");
     let compare_format = matches.is_present("compare");
+    let compare_groups = matches.is_present("compare_groups");
+    let replace = matches.value_of("replace");
+    let spans_only = matches.is_present("spans_only");
+    let only_matching = matches.is_present("only_matching");
+    let leftmost_longest = matches.is_present("leftmost_longest");
+    let csv = matches.is_present("csv");
+    let csv_match = matches.is_present("csv_match");
+    let json_array = matches.is_present("json_array");
+    let context = matches
+        .value_of("context")
+        .map(|n| n.parse().expect("Invalid number of context lines"));
+    let before_context = matches
+        .value_of("before_context")
+        .map(|n| n.parse().expect("Invalid number of context lines"))
+        .or(context);
+    let after_context = matches
+        .value_of("after_context")
+        .map(|n| n.parse().expect("Invalid number of context lines"))
+        .or(context);
 
     let use_naive = matches.is_present("use_naive");
     let use_naive_cubic = matches.is_present("use_naive_cubic");
     let use_naive_quadratic = matches.is_present("use_naive_quadratic");
 
     let debug_infos = matches.is_present("debug_infos");
+    let dot_path = matches.value_of("dot");
+    let jump_trace_path = matches.value_of("jump_trace");
+    let show_hir = matches.is_present("show_hir");
+    let lossy = matches.is_present("lossy");
+    let keep_trailing_newline = matches.is_present("keep_trailing_newline");
+    let line_terminator = match matches.is_present("crlf") {
+        true => LineTerminator::CrLf,
+        false => LineTerminator::Lf,
+    };
+    let clean_strategy = match matches.value_of("clean_every") {
+        _ if matches.is_present("no_clean") => CleanStrategy::Never,
+        None => CleanStrategy::PowerOfTwo,
+        Some(n) => CleanStrategy::EveryN(n.parse().expect("Invalid cleaning period")),
+    };
+    let timeout = matches.value_of("timeout").map(|timeout| {
+        let seconds = timeout.parse().expect("Invalid timeout");
+        time::Duration::try_from_secs_f64(seconds).expect("Invalid timeout")
+    });
+    let max_levels = matches
+        .value_of("max_levels")
+        .map(|n| n.parse().expect("Invalid maximal number of levels"));
+    let max_width = matches
+        .value_of("max_width")
+        .map(|n| n.parse().expect("Invalid maximal width"));
+    let gzip = matches.is_present("gzip");
+
+    // When patterns are given with an option, the positional pattern is
+    // actually the first file.
+    let filenames: Vec<_> = matches
+        .values_of("regex")
+        .filter(|_| has_patterns)
+        .into_iter()
+        .flatten()
+        .chain(matches.values_of("file").into_iter().flatten())
+        .collect();
 
-    let display_format = match (count, compare_format, show_offset) {
-        (true, _, _) => DisplayFormat::Count,
-        (_, true, _) => DisplayFormat::CompareFormat,
-        _ => DisplayFormat::Verbose { show_offset },
+    let with_filename = match (
+        matches.is_present("with_filename"),
+        matches.is_present("no_filename"),
+    ) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => filenames.len() > 1,
     };
 
     //  ____                  _                          _
@@ -120,8 +568,17 @@ fn main() {
     // |____/ \___|_| |_|\___|_| |_|_| |_| |_|\__,_|_|  |_|\_\
     //
 
-    if benchmark {
-        benchmark::run_all_tests(&mut stdout()).unwrap();
+    if benchmark || benchmark_manifest.is_some() {
+        if let Err(err) = benchmark::run_all_tests(
+            &mut stdout(),
+            benchmark_manifest,
+            benchmark_format,
+            benchmark_iterations,
+        ) {
+            eprintln!("Failed to run benchmarks: {}", err);
+            process::exit(EXIT_ERROR);
+        }
+
         return;
     }
 
@@ -132,21 +589,50 @@ fn main() {
     // |___|_| |_| .__/ \__,_|\__|___/
     //           |_|
 
-    // Read the text
-    let mut text = String::new();
-    match matches.value_of("file") {
-        Some(filename) => {
-            let mut file = File::open(filename).unwrap();
-            file.read_to_string(&mut text).unwrap()
+    /// Read the full content of a file, or STDIN if no file is specified.
+    #[cfg_attr(not(feature = "gzip"), allow(unused_variables))]
+    fn read_input(
+        filename: Option<&str>,
+        lossy: bool,
+        gzip: bool,
+        line_terminator: LineTerminator,
+        keep_trailing_newline: bool,
+    ) -> io::Result<Input> {
+        let mut reader: Box<dyn Read> = match filename {
+            Some(filename) => Box::new(File::open(filename)?),
+            None => Box::new(stdin()),
+        };
+
+        #[cfg(feature = "gzip")]
+        {
+            if gzip || filename.is_some_and(|filename| filename.ends_with(".gz")) {
+                reader = Box::new(flate2::read::GzDecoder::new(reader));
+            }
         }
-        None => stdin().read_to_string(&mut text).unwrap(),
-    };
 
-    // Remove trailing newlines
-    while text.as_bytes().last() == Some(&b'\n') {
-        text.pop();
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let input = Input::from_bytes(bytes, lossy).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} (use --lossy to replace invalid sequences)", err),
+            )
+        })?;
+
+        let input = input.with_line_terminator(line_terminator);
+
+        match keep_trailing_newline {
+            true => Ok(input),
+            false => Ok(input.strip_line_terminator()),
+        }
     }
 
+    let inputs = match filenames.len() {
+        0 => vec![None],
+        _ => filenames.iter().map(|&filename| Some(filename)).collect(),
+    };
+
     //  __  __       _       _
     // |  \/  | __ _| |_ ___| |__
     // | |\/| |/ _` | __/ __| '_ \
@@ -154,92 +640,620 @@ fn main() {
     // |_|  |_|\__,_|\__\___|_| |_|
     //
 
-    let regex = regex::compile(regex_str);
-    regex
-        .render("automaton.dot")
-        .expect("Could not create the dotfile.");
+    let flags = regex::Flags {
+        dotall,
+        anchor_start: full_match,
+        anchor_end: full_match,
+        no_match_group,
+    };
+
+    if show_hir {
+        if let Ok(hir) = regex::compile_hir(regex_str, flags) {
+            eprint!("===== Hir =====\n{}", hir);
+        }
+    }
+
+    let regex = regex::compile_with_flags(regex_str, flags);
+
+    let regex = match regex {
+        Ok(regex) => regex,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    if let Some(path) = dot_path {
+        if let Err(err) = regex.render(path) {
+            eprintln!("Could not write the automaton to {}: {}", path, err);
+            process::exit(EXIT_ERROR);
+        }
+    }
+
+    // Traces of all inputs are written to the same file, one after the other.
+    let mut jump_trace = jump_trace_path.map(|path| match File::create(path) {
+        Ok(file) => (path, BufWriter::new(file)),
+        Err(err) => {
+            eprintln!("Could not write the jump function trace to {}: {}", path, err);
+            process::exit(EXIT_ERROR);
+        }
+    });
+
+    // Matches of a literal are found with a substring search, unless details
+    // about the DAG are requested.
+    let literal = regex::as_literal(regex_str, flags)
+        .filter(|_| !debug_infos && jump_trace_path.is_none());
+
+    let display_format = match (quiet, count, compare_format, replace) {
+        (true, _, _, _) => DisplayFormat::Quiet,
+        (_, true, _, _) => DisplayFormat::Count,
+        (_, _, true, _) => DisplayFormat::CompareFormat {
+            groups: compare_groups,
+        },
+        (_, _, _, Some(template)) => DisplayFormat::Replace(Template::new(template)),
+        _ if spans_only => DisplayFormat::Spans,
+        _ if only_matching => match regex::parse::is_lazy(regex_str) {
+            Ok(true) => DisplayFormat::OnlyMatching(Preference::Shortest),
+            _ => DisplayFormat::OnlyMatching(Preference::Longest),
+        },
+        _ if csv => {
+            let columns = regex
+                .variables()
+                .into_iter()
+                .map(|var| var.get_name().to_string())
+                .filter(|name| csv_match || name != "match")
+                .collect();
+            DisplayFormat::Csv { columns }
+        }
+        _ if json_array => DisplayFormat::JsonArray {
+            empty: Cell::new(true),
+        },
+        _ if before_context.is_some() || after_context.is_some() => DisplayFormat::Context {
+            before: before_context.unwrap_or(0),
+            after:  after_context.unwrap_or(0),
+        },
+        _ => DisplayFormat::Verbose { show_offset, color },
+    };
 
     let timer = time::Instant::now();
 
+    /// Output matches with respect to the display format and return true if
+    /// there was at least one match.
+    ///
+    /// Matches are written through a buffer which is flushed once all of them
+    /// have been enumerated: the progress bar is already finished by then and
+    /// printing each match with a separate `print!` is far too slow when there
+    /// are millions of them.
     fn handle_matches<'t>(
-        matches: impl Iterator<Item = mapping::Mapping<'t>>,
-        text: &str,
+        mut matches: impl Iterator<Item = mapping::Mapping<'t>>,
+        input: &Input,
+        filename: Option<&str>,
         timer: &time::Instant,
-        display_format: DisplayFormat,
-    ) {
-        match display_format {
+        display_format: &DisplayFormat,
+    ) -> io::Result<bool> {
+        let stdout = stdout();
+        let mut out = BufWriter::new(stdout.lock());
+
+        let prefix = match filename {
+            Some(filename) => format!("{}:", filename),
+            None => String::new(),
+        };
+
+        let found = match *display_format {
+            DisplayFormat::Quiet => matches.next().is_some(),
             DisplayFormat::Count => {
                 let count = matches.count();
-                println!("{}", count)
+                writeln!(out, "{}{}", prefix, count)?;
+                count > 0
             }
-            DisplayFormat::CompareFormat => {
+            DisplayFormat::CompareFormat { groups } => {
+                // Filenames are not displayed in this format, which is meant to
+                // be parsed by re-compare.
+                let mut found = false;
+
                 for mapping in matches {
-                    let span = mapping
-                        .main_span()
-                        .expect("A mapping should never be empty");
-
-                    println!(
-                        r#">>>>{{"match": {:?}, "span": [{},{}], "time": {}}}"#,
-                        &text[span.clone()],
-                        span.start,
-                        span.end,
-                        timer.elapsed().as_millis()
-                    )
+                    found = true;
+                    let span = mapping.main_span();
+
+                    write!(
+                        out,
+                        r#">>>>{{"match": {}, "span": [{},{}], "#,
+                        serde_json::to_string(&input.text[span.clone()])?,
+                        input.source_offset(span.start),
+                        input.source_offset(span.end),
+                    )?;
+
+                    if groups {
+                        // Unassigned groups are given a null span.
+                        let spans: serde_json::Map<_, _> = mapping
+                            .iter_declared_groups()
+                            .filter(|(name, _)| *name != "match")
+                            .map(|(name, range)| {
+                                let span = range.map(|range| {
+                                    let start = input.source_offset(range.start);
+                                    vec![start, input.source_offset(range.end)]
+                                });
+                                (name.to_string(), serde_json::json!(span))
+                            })
+                            .collect();
+
+                        write!(out, r#""groups": {}, "#, serde_json::Value::Object(spans))?;
+                    }
+
+                    writeln!(out, r#""time": {}}}"#, timer.elapsed().as_millis())?;
                 }
 
-                println!(
+                writeln!(
+                    out,
                     r#">>>>{{"match": "EOF", "span": [-1,-1], "time": {}}}"#,
                     timer.elapsed().as_millis()
-                );
+                )?;
+
+                found
+            }
+            DisplayFormat::Replace(ref template) => {
+                let mut matches = matches.peekable();
+                let found = matches.peek().is_some();
+                let replaced = replace::replace_all(&input.text, matches, template);
+
+                for line in replaced.split('\n') {
+                    writeln!(out, "{}{}", prefix, line)?;
+                }
+
+                found
             }
-            DisplayFormat::Verbose { show_offset } => {
+            DisplayFormat::Spans => {
+                let mut found = false;
+
+                for (count, span) in mapping::distinct_spans(matches).enumerate() {
+                    found = true;
+                    writeln!(
+                        out,
+                        "{}{} - {},{}",
+                        prefix,
+                        count + 1,
+                        input.source_offset(span.start),
+                        input.source_offset(span.end)
+                    )?;
+                }
+
+                found
+            }
+            DisplayFormat::OnlyMatching(preference) => {
+                let selected = replace::select_leftmost(matches, preference);
+
+                for mapping in &selected {
+                    let span = mapping.main_span();
+
+                    if !span.is_empty() {
+                        writeln!(out, "{}{}", prefix, &input.text[span])?;
+                    }
+                }
+
+                !selected.is_empty()
+            }
+            DisplayFormat::Csv { ref columns } => {
+                // The header is written once before all inputs.
+                let mut found = false;
+
+                for mapping in matches {
+                    found = true;
+                    let cells = columns
+                        .iter()
+                        .map(|name| mapping.group_text(name).unwrap_or(""));
+                    write_csv_row(&mut out, filename.into_iter().chain(cells))?;
+                }
+
+                found
+            }
+            DisplayFormat::JsonArray { ref empty } => {
+                // Objects are written as they are enumerated, each of them
+                // but the first one of all inputs being preceded by a comma.
+                // The array itself is opened and closed around all inputs.
+                let mut found = false;
+
+                for mapping in matches {
+                    if !empty.replace(false) {
+                        write!(out, ",")?;
+                    }
+
+                    found = true;
+                    let span = mapping.main_span();
+                    let to_json = |range: std::ops::Range<usize>| {
+                        let start = input.source_offset(range.start);
+                        serde_json::json!([start, input.source_offset(range.end)])
+                    };
+
+                    // Unassigned groups are given a null span.
+                    let groups: serde_json::Map<_, _> = mapping
+                        .iter_declared_groups()
+                        .map(|(name, range)| {
+                            (name.to_string(), range.map(to_json).unwrap_or_default())
+                        })
+                        .collect();
+
+                    let mut object = serde_json::json!({
+                        "match": &input.text[span.clone()],
+                        "span": to_json(span),
+                        "groups": groups,
+                    });
+
+                    if let Some(filename) = filename {
+                        object["file"] = serde_json::json!(filename);
+                    }
+
+                    write!(out, "\n{}", object)?;
+                }
+
+                found
+            }
+            DisplayFormat::Context { before, after } => {
+                let lines = input.line_index();
+
+                // Count, for each line, the number of spans starting minus the
+                // number of spans ending before it: prefix sums then give the
+                // number of spans covering each line.
+                let mut delta = vec![0i64; lines.nb_lines() + 1];
+
+                for span in mapping::distinct_spans(matches) {
+                    let first = lines.line_of(span.start);
+                    let last = match span.end > span.start {
+                        true => lines.line_of(span.end - 1),
+                        false => first,
+                    };
+
+                    delta[first] += 1;
+                    delta[last + 1] -= 1;
+                }
+
+                let matching: Vec<_> = delta
+                    .iter()
+                    .scan(0, |covering, diff| {
+                        *covering += diff;
+                        Some(*covering > 0)
+                    })
+                    .take(lines.nb_lines())
+                    .collect();
+
+                // Index of the first line that has not been displayed yet
+                let mut next_line = 0;
+
+                for line in (0..lines.nb_lines()).filter(|&line| matching[line]) {
+                    let start = cmp::max(next_line, line.saturating_sub(before));
+                    let end = cmp::min(lines.nb_lines(), line + after + 1);
+
+                    if start >= end {
+                        continue;
+                    }
+
+                    if next_line > 0 && start > next_line {
+                        writeln!(out, "--")?;
+                    }
+
+                    let window = matching.iter().enumerate().take(end).skip(start);
+
+                    for (displayed, &is_matching) in window {
+                        let separator = match is_matching {
+                            true => ':',
+                            false => '-',
+                        };
+
+                        if let Some(filename) = filename {
+                            write!(out, "{}{}", filename, separator)?;
+                        }
+
+                        writeln!(
+                            out,
+                            "{}{}{}",
+                            displayed + 1,
+                            separator,
+                            &input.text[lines.line_span(displayed)]
+                        )?;
+                    }
+
+                    next_line = end;
+                }
+
+                matching.contains(&true)
+            }
+            DisplayFormat::Verbose { show_offset, color } => {
+                let lines = match color {
+                    true => Some(input.line_index()),
+                    false => None,
+                };
+
+                let mut found = false;
+
                 for (count, mapping) in matches.enumerate() {
-                    print!("{} -", count + 1);
+                    found = true;
+                    write!(out, "{}{} -", prefix, count + 1)?;
 
                     if show_offset {
                         for (name, range) in mapping.iter_groups() {
-                            print!(" {}:{},{}", name, range.start, range.end);
+                            write!(
+                                out,
+                                " {}:{},{}",
+                                name,
+                                input.source_offset(range.start),
+                                input.source_offset(range.end)
+                            )?;
                         }
                     } else {
                         for (name, text) in mapping.iter_groups_text() {
-                            print!(" {}:{:?}", name, text);
+                            write!(out, " {}:{:?}", name, text)?;
                         }
                     }
 
-                    println!();
+                    writeln!(out)?;
+
+                    if let Some(lines) = &lines {
+                        write_highlighted(&mut out, &input.text, lines, &mapping)?;
+                    }
                 }
+
+                found
+            }
+        };
+
+        out.flush()?;
+        Ok(found)
+    }
+
+    /// Write a row of CSV, cells are quoted when they contain a separator, a
+    /// quote or a line break.
+    fn write_csv_row<'c>(
+        out: &mut impl Write,
+        cells: impl Iterator<Item = &'c str>,
+    ) -> io::Result<()> {
+        for (index, cell) in cells.enumerate() {
+            if index > 0 {
+                write!(out, ",")?;
+            }
+
+            if cell.contains(&[',', '"', '\n', '\r'][..]) {
+                write!(out, "\"{}\"", cell.replace('"', "\"\""))?;
+            } else {
+                write!(out, "{}", cell)?;
             }
         }
+
+        writeln!(out)
     }
 
-    if use_naive {
-        handle_matches(
-            mapping::naive::NaiveEnum::new(&regex, &text),
-            &text,
-            &timer,
-            display_format,
-        );
-    } else if use_naive_cubic {
-        handle_matches(
-            regex::naive::NaiveEnumCubic::new(regex_str, &text).unwrap(),
-            &text,
-            &timer,
-            display_format,
-        );
-    } else if use_naive_quadratic {
-        handle_matches(
-            regex::naive::NaiveEnumQuadratic::new(regex_str, &text),
-            &text,
-            &timer,
-            display_format,
-        );
-    } else {
-        handle_matches(
-            regex::compile_matches_progress(regex, &text).iter(),
-            &text,
-            &timer,
-            display_format,
-        );
+    /// Write the lines containing a mapping, where the main span and groups
+    /// are highlighted with ANSI escape codes.
+    fn write_highlighted(
+        out: &mut impl Write,
+        text: &str,
+        lines: &LineIndex,
+        mapping: &mapping::Mapping,
+    ) -> io::Result<()> {
+        const MATCH_STYLE: &str = "\x1b[1;31m";
+        const GROUP_STYLE: &str = "\x1b[1;32m";
+        const RESET_STYLE: &str = "\x1b[0m";
+
+        let span = mapping.main_span();
+        let groups: Vec<_> = mapping
+            .iter_groups()
+            .filter(|&(name, ref range)| name != "match" && !range.is_empty())
+            .map(|(_, range)| range)
+            .collect();
+
+        let first_line = lines.line_of(span.start);
+        let last_line = match span.end > span.start {
+            true => lines.line_of(span.end - 1),
+            false => first_line,
+        };
+        let displayed = lines.line_span(first_line).start..lines.line_span(last_line).end;
+
+        // Split displayed text at each border of a highlighted span
+        let mut borders: Vec<_> = groups
+            .iter()
+            .flat_map(|range| vec![range.start, range.end])
+            .chain(vec![displayed.start, displayed.end, span.start, span.end])
+            .collect();
+        borders.sort_unstable();
+        borders.dedup();
+
+        for part in borders.windows(2) {
+            let (start, end) = (part[0], part[1]);
+            let style = if groups.iter().any(|range| range.contains(&start)) {
+                Some(GROUP_STYLE)
+            } else if span.contains(&start) {
+                Some(MATCH_STYLE)
+            } else {
+                None
+            };
+
+            match style {
+                Some(style) => write!(out, "{}{}{}", style, &text[start..end], RESET_STYLE)?,
+                None => write!(out, "{}", &text[start..end])?,
+            }
+        }
+
+        writeln!(out)
+    }
+
+    let mut found = false;
+    let mut had_error = false;
+
+    // Formats giving a single document for all inputs open it beforehand.
+    let header = match &display_format {
+        DisplayFormat::JsonArray { .. } => write!(stdout(), "["),
+        DisplayFormat::Csv { columns } => {
+            let header = Some("file").filter(|_| with_filename).into_iter();
+            write_csv_row(&mut stdout(), header.chain(columns.iter().map(String::as_str)))
+        }
+        _ => Ok(()),
+    };
+
+    if let Err(err) = header {
+        eprintln!("Failed to write matches: {}", err);
+        had_error = true;
+    }
+
+    for filename in inputs {
+        let input = match read_input(
+            filename,
+            lossy,
+            gzip,
+            line_terminator,
+            keep_trailing_newline,
+        ) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("{}: {}", filename.unwrap_or("<stdin>"), err);
+                had_error = true;
+                continue;
+            }
+        };
+
+        let text = &input.text;
+        let prefix = match with_filename {
+            true => Some(filename.unwrap_or("<stdin>")),
+            false => None,
+        };
+
+        let result = if use_naive {
+            handle_matches(
+                mapping::naive::NaiveEnum::new(&regex, text),
+                &input,
+                prefix,
+                &timer,
+                &display_format,
+            )
+        } else if use_naive_cubic {
+            handle_matches(
+                regex::naive::NaiveEnumCubic::new(regex_str, text).unwrap(),
+                &input,
+                prefix,
+                &timer,
+                &display_format,
+            )
+        } else if use_naive_quadratic {
+            handle_matches(
+                regex::naive::NaiveEnumQuadratic::new(regex_str, text).unwrap(),
+                &input,
+                prefix,
+                &timer,
+                &display_format,
+            )
+        } else if let Some(literal) = &literal {
+            let matches = regex::literal::LiteralMatcher::new(literal, text);
+
+            match leftmost_longest {
+                true => handle_matches(
+                    replace::select_leftmost(matches, Preference::Longest).into_iter(),
+                    &input,
+                    prefix,
+                    &timer,
+                    &display_format,
+                ),
+                false => handle_matches(matches, &input, prefix, &timer, &display_format),
+            }
+        } else {
+            let toggle_progress = match display_format {
+                DisplayFormat::Quiet => ToggleProgress::Disabled,
+                _ => ToggleProgress::Enabled,
+            };
+
+            let mut options = mapping::IndexedDag::builder()
+                .progress(toggle_progress)
+                .clean_strategy(clean_strategy);
+
+            // A deadline too far to be represented is no deadline at all
+            if let Some(deadline) = timeout.and_then(|t| time::Instant::now().checked_add(t)) {
+                options = options.deadline(deadline);
+            }
+
+            if let Some(max_levels) = max_levels {
+                options = options.max_levels(max_levels);
+            }
+
+            if let Some(max_width) = max_width {
+                options = options.max_width(max_width);
+            }
+
+            let compiled_matches = match options.compile(regex.clone(), text) {
+                Ok(compiled_matches) => compiled_matches,
+                Err(err) => {
+                    eprintln!("{}: {}", filename.unwrap_or("<stdin>"), err);
+                    had_error = true;
+                    continue;
+                }
+            };
+
+            if debug_infos {
+                print_debug_infos(&compiled_matches, &regex, filename);
+            }
+
+            if let Some((path, trace)) = &mut jump_trace {
+                let res = writeln!(trace, "===== {} =====", filename.unwrap_or("<stdin>"))
+                    .and_then(|()| compiled_matches.dump_jump(&mut *trace))
+                    .and_then(|()| trace.flush());
+
+                if let Err(err) = res {
+                    eprintln!("Could not write the jump function trace to {}: {}", path, err);
+                    had_error = true;
+                }
+            }
+
+            let mut matches = compiled_matches.iter();
+
+            let result = match full_match {
+                true => Ok(compiled_matches.is_full_match()),
+                // Counting or checking the existence of matches doesn't require
+                // to start the enumeration when there is none.
+                false
+                    if compiled_matches.is_empty()
+                        && matches!(display_format, DisplayFormat::Quiet | DisplayFormat::Count) =>
+                {
+                    handle_matches(iter::empty(), &input, prefix, &timer, &display_format)
+                }
+                false if leftmost_longest => handle_matches(
+                    replace::select_leftmost(matches.by_ref(), Preference::Longest).into_iter(),
+                    &input,
+                    prefix,
+                    &timer,
+                    &display_format,
+                ),
+                false => handle_matches(
+                    // Other formats write matches as they are found, which
+                    // would be mixed with the spinner, which is also useless
+                    // when nobody looks at it.
+                    matches.with_progress(match display_format {
+                        DisplayFormat::Count if stderr().is_terminal() => toggle_progress,
+                        _ => ToggleProgress::Disabled,
+                    }),
+                    &input,
+                    prefix,
+                    &timer,
+                    &display_format,
+                ),
+            };
+
+            if let Some(err) = matches.error() {
+                eprintln!("{}: {}", filename.unwrap_or("<stdin>"), err);
+                had_error = true;
+            }
+
+            result
+        };
+
+        match result {
+            Ok(has_match) => found |= has_match,
+            Err(err) => {
+                eprintln!("Failed to write matches: {}", err);
+                had_error = true;
+            }
+        }
+    }
+
+    if let DisplayFormat::JsonArray { empty } = &display_format {
+        if let Err(err) = writeln!(stdout(), "{}]", if empty.get() { "" } else { "\n" }) {
+            eprintln!("Failed to write matches: {}", err);
+            had_error = true;
+        }
     }
 
     //  ____       _                   ___        __
@@ -249,8 +1263,66 @@ fn main() {
     // |____/ \___|_.__/ \__,_|\__, | |___|_| |_|_|  \___/|___/
     //                         |___/
 
-    if debug_infos {
-        eprintln!("===== Debug Infos =====");
-        // eprintln!(" - Levels count: {}", compiled_matches.get_nb_levels());
+    /// Display informations about the structure computed for an input.
+    fn print_debug_infos(
+        compiled_matches: &mapping::IndexedDag,
+        automaton: &Automaton,
+        filename: Option<&str>,
+    ) {
+        eprintln!("===== Debug Infos: {} =====", filename.unwrap_or("<stdin>"));
+        eprintln!(
+            " - Automaton: {} states, {} unreachable, {} dead ({})",
+            automaton.get_nb_states(),
+            automaton.nb_unreachable_states(),
+            automaton.nb_dead_states(),
+            if automaton.is_trim() { "trim" } else { "not trim" }
+        );
+
+        eprintln!(" - Levels count: {}", compiled_matches.get_nb_levels());
+        eprintln!(" - Vertices per level:");
+
+        for (level, count) in compiled_matches.level_sizes() {
+            eprintln!("    {:>8}: {}", level, count);
+        }
+
+        for (level, err) in compiled_matches.skipped_cleanings() {
+            eprintln!(" - Skipped cleaning of level {}: {}", level, err);
+        }
+    }
+
+    // As grep, a match found in quiet mode hides errors.
+    process::exit(match (found, had_error) {
+        (true, _) if matches!(display_format, DisplayFormat::Quiet) => EXIT_MATCH,
+        (_, true) => EXIT_ERROR,
+        (true, false) => EXIT_MATCH,
+        (false, false) => EXIT_NO_MATCH,
+    });
+}
+
+/// Check that the value of an option is a non-negative and finite number of
+/// seconds, see `is_number`.
+fn is_duration(value: String) -> Result<(), String> {
+    match value.parse::<f64>().map(time::Duration::try_from_secs_f64) {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(format!("`{}` is not a valid duration: {}", value, err)),
+        Err(err) => Err(format!("`{}` is not a valid number of seconds: {}", value, err)),
+    }
+}
+
+/// Check that the value of an option is a non-negative integer, which allows
+/// clap to report invalid values as usage errors.
+fn is_number(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("`{}` is not a valid number: {}", value, err)),
+    }
+}
+
+/// Check that the value of an option is a positive integer.
+fn is_positive_number(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err(format!("`{}` is not a positive number", value)),
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("`{}` is not a valid number: {}", value, err)),
     }
 }
@@ -1,10 +1,25 @@
+use std::cmp;
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
 use std::iter;
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Instant;
 
-use super::super::automaton::Automaton;
-use super::super::mapping::{Mapping, Marker};
+use super::super::automaton::{state_id, state_index, AdjCacheSize, Automaton, CharClassSummary};
+use super::super::mapping::{
+    distinct_spans, BytesMapping, Mapping, MappingError, MappingView, Marker, Variable,
+};
+use super::super::matrix::{ColMul, Matrix};
 use super::super::progress::Progress;
-use super::jump::Jump;
+use super::super::replace::{select_leftmost, Preference};
+use super::jump::{CleanError, Jump};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 //  ___           _                   _ ____
 // |_ _|_ __   __| | _____  _____  __| |  _ \  __ _  __ _
@@ -23,57 +38,375 @@ pub struct IndexedDag<'t> {
     text:         &'t str,
     jump:         Jump,
     char_offsets: Vec<usize>,
+    declared:     Arc<[Variable]>,
+
+    /// Levels left uncleaned after finding an inconsistency in `jump`.
+    skipped_cleanings: Vec<(usize, CleanError)>,
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ToggleProgress {
     Enabled,
     Disabled,
 }
 
-impl<'t> IndexedDag<'t> {
-    /// Compute the index of matches of an automaton over input text.
+/// Schedule of cleaning of levels during the compilation of an `IndexedDag`.
+/// Cleaning levels saves memory and speeds up the enumeration, but takes time
+/// during the compilation. Any strategy gives the same matches.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CleanStrategy {
+    /// Clean the last `2^k` levels when the current level is divisible by
+    /// `2^k` (and not by `2^(k+1)`).
+    #[default]
+    PowerOfTwo,
+    /// Clean the last `n` levels every `n` levels, `n` must be positive and
+    /// `EveryN(0)` is read as `Never` by `CompileOptions::clean_strategy`.
+    EveryN(usize),
+    /// Never clean levels.
+    Never,
+}
+
+/// Error returned when the compilation of an `IndexedDag` exceeds one of the
+/// bounds given by its `CompileOptions`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum DagCompileError {
+    /// The compilation reached its deadline.
+    Timeout,
+    /// The DAG has more levels than allowed, even after cleaning.
+    TooManyLevels(usize),
+    /// A level of the DAG has more vertices than allowed, which usually comes
+    /// from a pattern with nested repetitions.
+    Blowup { level: usize, width: usize },
+}
+
+impl fmt::Display for DagCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DagCompileError::Timeout => {
+                write!(f, "compilation of the matches reached its deadline")
+            }
+            DagCompileError::TooManyLevels(max_levels) => write!(
+                f,
+                "compilation of the matches requires more than {} levels",
+                max_levels
+            ),
+            DagCompileError::Blowup { level, width } => write!(
+                f,
+                "compilation of the matches blew up at level {} with {} vertices",
+                level, width
+            ),
+        }
+    }
+}
+
+impl error::Error for DagCompileError {}
+
+/// Error returned when an offset given to `IndexedDag::iter_from` doesn't fall
+/// on a char boundary of the text.
+#[derive(Debug, Eq, PartialEq)]
+pub struct CharBoundaryError(pub usize);
+
+impl fmt::Display for CharBoundaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "offset {} is not on a char boundary of the text", self.0)
+    }
+}
+
+impl error::Error for CharBoundaryError {}
+
+/// Statistics over the spans assigned to a group along all matches, see
+/// `IndexedDag::group_stats`. Lengths are given in bytes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GroupStat {
+    /// Number of matches assigning the group.
+    pub count:     usize,
+    pub min_len:   usize,
+    pub max_len:   usize,
+    /// Sum of the lengths of all assigned spans.
+    pub total_len: usize,
+}
+
+impl GroupStat {
+    fn new(len: usize) -> GroupStat {
+        GroupStat {
+            count:     1,
+            min_len:   len,
+            max_len:   len,
+            total_len: len,
+        }
+    }
+
+    fn add(&mut self, len: usize) {
+        self.count += 1;
+        self.min_len = cmp::min(self.min_len, len);
+        self.max_len = cmp::max(self.max_len, len);
+        self.total_len += len;
+    }
+}
+
+/// Options for the compilation of an `IndexedDag`, see `IndexedDag::builder`.
+#[derive(Clone, Copy, Debug)]
+pub struct CompileOptions {
+    toggle_progress: ToggleProgress,
+    clean_strategy:  CleanStrategy,
+    adj_cache_size:  AdjCacheSize,
+    deadline:        Option<Instant>,
+    max_levels:      Option<usize>,
+    max_width:       Option<usize>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+        CompileOptions {
+            toggle_progress: ToggleProgress::Disabled,
+            clean_strategy:  CleanStrategy::default(),
+            adj_cache_size:  AdjCacheSize::default(),
+            deadline:        None,
+            max_levels:      None,
+            max_width:       None,
+        }
+    }
+}
+
+impl CompileOptions {
+    pub fn progress(mut self, toggle_progress: ToggleProgress) -> CompileOptions {
+        self.toggle_progress = toggle_progress;
+        self
+    }
+
+    pub fn clean_strategy(mut self, clean_strategy: CleanStrategy) -> CompileOptions {
+        self.clean_strategy = match clean_strategy {
+            CleanStrategy::EveryN(0) => CleanStrategy::Never,
+            clean_strategy => clean_strategy,
+        };
+        self
+    }
+
+    /// Give up the compilation if it is not over before a deadline.
+    pub fn deadline(mut self, deadline: Instant) -> CompileOptions {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Give up the compilation if the DAG has more than `max_levels` levels
+    /// after cleaning, which bounds the memory it uses whatever the length of
+    /// the text.
+    pub fn max_levels(mut self, max_levels: usize) -> CompileOptions {
+        self.max_levels = Some(max_levels);
+        self
+    }
+
+    /// Give up the compilation if a level of the DAG has more than `max_width`
+    /// vertices, which points at the part of the text where a pattern blows
+    /// up instead of running out of memory.
+    pub fn max_width(mut self, max_width: usize) -> CompileOptions {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn adj_cache_cap(mut self, adj_cache_size: AdjCacheSize) -> CompileOptions {
+        self.adj_cache_size = adj_cache_size;
+        self
+    }
+
+    /// Compute the index of matches of an automaton over input text, which
+    /// can only fail if a deadline, a maximal number of levels or a maximal
+    /// width is set.
     pub fn compile(
-        mut automaton: Automaton,
+        self,
+        automaton: Automaton,
         text: &str,
-        toggle_progress: ToggleProgress,
-    ) -> IndexedDag {
+    ) -> Result<IndexedDag<'_>, DagCompileError> {
+        IndexedDag::compile_until(automaton, text, self)
+    }
+
+    /// Compute the index of matches of an automaton over a slice of bytes,
+    /// see `IndexedDag::compile_bytes`.
+    pub fn compile_bytes(
+        self,
+        automaton: Automaton,
+        bytes: &[u8],
+    ) -> Result<BytesIndexedDag<'_>, DagCompileError> {
+        let chars = bytes.iter().map(|&byte| char::from(byte));
+        let char_offsets = (0..=bytes.len()).collect();
+        let dag = IndexedDag::compile_stream(automaton, "", chars, Some(char_offsets), self)?;
+        Ok(BytesIndexedDag { bytes, dag })
+    }
+
+    /// Compute the index of matches of an automaton over a stream of chars,
+    /// see `IndexedDag::compile_chars`.
+    pub fn compile_chars<'t, I>(
+        self,
+        automaton: Automaton,
+        chars: I,
+        buffer: &'t mut String,
+    ) -> Result<IndexedDag<'t>, DagCompileError>
+    where
+        I: Iterator<Item = char>,
+    {
+        buffer.clear();
+        let mut char_offsets = Vec::new();
+
+        let chars = chars.inspect(|&c| {
+            char_offsets.push(buffer.len());
+            buffer.push(c);
+        });
+
+        let dag = IndexedDag::compile_stream(automaton, "", chars, None, self)?;
+        char_offsets.push(buffer.len());
+
+        Ok(IndexedDag {
+            text: buffer,
+            char_offsets,
+            ..dag
+        })
+    }
+}
+
+impl<'t> IndexedDag<'t> {
+    /// Get options to compile an `IndexedDag`, initialized with default
+    /// values: no progress bar, no deadline and an unbounded cache.
+    pub fn builder() -> CompileOptions {
+        CompileOptions::default()
+    }
+
+    /// Compute the index of matches of an automaton over input text, with
+    /// default options, see `IndexedDag::builder` to change them.
+    pub fn compile(automaton: Automaton, text: &'t str) -> IndexedDag<'t> {
+        IndexedDag::builder()
+            .compile(automaton, text)
+            .expect("Compilation without bounds can't fail")
+    }
+
+    /// Compute the index of matches of an automaton over a stream of chars,
+    /// in a single pass over the stream.
+    ///
+    /// The chars are collected into `buffer`, which is cleared first and
+    /// becomes the text of the DAG: spans are given in bytes of this text as
+    /// for `IndexedDag::compile`.
+    pub fn compile_chars<I>(automaton: Automaton, chars: I, buffer: &'t mut String) -> IndexedDag<'t>
+    where
+        I: Iterator<Item = char>,
+    {
+        IndexedDag::builder()
+            .compile_chars(automaton, chars, buffer)
+            .expect("Compilation without bounds can't fail")
+    }
+
+    /// Compute the index of matches of an automaton over a slice of bytes,
+    /// which doesn't have to be valid UTF-8, with default options.
+    ///
+    /// Each byte is read as the char with the same code point, as in Latin-1:
+    /// the pattern `\x89PNG` matches the bytes `b"\x89PNG"`, while chars
+    /// beyond `\xFF` never match. Spans are given in bytes.
+    pub fn compile_bytes(automaton: Automaton, bytes: &'t [u8]) -> BytesIndexedDag<'t> {
+        IndexedDag::builder()
+            .compile_bytes(automaton, bytes)
+            .expect("Compilation without bounds can't fail")
+    }
+
+    fn compile_until(
+        automaton: Automaton,
+        text: &'t str,
+        options: CompileOptions,
+    ) -> Result<IndexedDag<'t>, DagCompileError> {
         // Index utf8 chars, the ith char being represented by
         // `text[char_offsets[i]..char_offsets[i+1]]`
-        let char_offsets = text
+        let char_offsets: Vec<_> = text
             .char_indices()
             .map(|(index, _)| index)
             .chain(iter::once(text.len()))
             .collect();
 
+        IndexedDag::compile_stream(automaton, text, text.chars(), Some(char_offsets), options)
+    }
+
+    /// Compute the index of matches over a stream of chars. If the offsets of
+    /// chars in the text are not given, the ith char is considered to be at
+    /// offset i.
+    fn compile_stream<I>(
+        mut automaton: Automaton,
+        text: &'t str,
+        chars: I,
+        char_offsets: Option<Vec<usize>>,
+        options: CompileOptions,
+    ) -> Result<IndexedDag<'t>, DagCompileError>
+    where
+        I: Iterator<Item = char>,
+    {
+        let CompileOptions {
+            toggle_progress,
+            clean_strategy,
+            adj_cache_size,
+            deadline,
+            max_levels,
+            max_width,
+        } = options;
+
+        // Keep the cache shared with other clones of the automaton if possible
+        if automaton.adj_cache_size() != adj_cache_size {
+            automaton.set_adj_cache_size(adj_cache_size);
+        }
+
         // Compute the jump function
         let mut jump = Jump::new(
-            iter::once(automaton.get_initial()),
+            iter::once(state_id(automaton.get_initial())),
             automaton.get_closure_for_assignations(),
         );
 
         let closure_for_assignations = automaton.get_closure_for_assignations().clone();
+        let mut skipped_cleanings = Vec::new();
 
-        let chars: Vec<_> = text.chars().collect();
-        let mut progress = Progress::from_iter(chars.into_iter())
-            .auto_refresh(toggle_progress == ToggleProgress::Enabled);
+        let mut progress = match (toggle_progress, &char_offsets) {
+            (ToggleProgress::Enabled, Some(offsets)) => Progress::new(chars, offsets.len() - 1),
+            (ToggleProgress::Enabled, None) => Progress::spinner(chars),
+            (ToggleProgress::Disabled, _) => Progress::silent(chars),
+        };
         let mut curr_level = 0;
 
         while let Some(curr_char) = progress.next() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                progress.finish();
+                return Err(DagCompileError::Timeout);
+            }
+
             let adj_for_char = automaton.get_adj_for_char(curr_char);
-            jump.init_next_level(adj_for_char, &closure_for_assignations);
+            jump.init_next_level(&adj_for_char, &closure_for_assignations);
             progress.extra_msg(format!("{} levels", jump.get_nb_levels()));
 
-            // Clean levels at exponential depth
-            if curr_level > 0 {
+            // Number of levels to clean
+            let depth = match clean_strategy {
+                _ if curr_level == 0 => 0,
                 // Highest power of two that divides current level
-                let depth = {
+                CleanStrategy::PowerOfTwo => {
                     let curr_level = curr_level as i128;
                     (curr_level & -curr_level) as usize
-                };
+                }
+                CleanStrategy::EveryN(n) if n > 0 && curr_level % n == 0 => n,
+                CleanStrategy::EveryN(_) | CleanStrategy::Never => 0,
+            };
 
-                for level in ((curr_level - depth + 1)..=curr_level).rev() {
-                    jump.clean_level(level, &closure_for_assignations);
+            for level in ((curr_level + 1 - depth)..=curr_level).rev() {
+                if let Err(err) = jump.clean_level(level, &closure_for_assignations) {
+                    skipped_cleanings.push((level, err));
+                }
+            }
+
+            if let Some(max_levels) = max_levels {
+                if jump.get_nb_levels() > max_levels {
+                    progress.finish();
+                    return Err(DagCompileError::TooManyLevels(max_levels));
+                }
+            }
+
+            if let Some(max_width) = max_width {
+                // The new level is the only one that can't have been cleaned
+                let level = curr_level + 1;
+                let width = jump.level_width(level);
+
+                if width > max_width {
+                    progress.finish();
+                    return Err(DagCompileError::Blowup { level, width });
                 }
             }
 
@@ -84,22 +417,437 @@ impl<'t> IndexedDag<'t> {
             }
         }
 
-        IndexedDag {
+        // Chars left after the DAG got disconnected still count as levels
+        let char_offsets = char_offsets.unwrap_or_else(|| {
+            let nb_chars = curr_level + progress.by_ref().count();
+            (0..=nb_chars).collect()
+        });
+
+        let declared = automaton.variables().into_iter().cloned().collect();
+
+        Ok(IndexedDag {
             automaton,
             text,
             jump,
             char_offsets,
-        }
+            declared,
+            skipped_cleanings,
+        })
     }
 
-    pub fn iter<'i>(&'i self) -> impl Iterator<Item = Mapping<'t>> + 'i {
+    /// Enumerate all matches.
+    ///
+    /// Ill-formed mappings produced by the automaton are skipped, the first
+    /// one can be inspected with `IndexedDagIterator::error` once the
+    /// enumeration is over.
+    pub fn iter<'i>(&'i self) -> IndexedDagIterator<'i, 't> {
         IndexedDagIterator::init(self)
     }
 
+    /// Enumerate matches whose main span lies within a range of bytes of the
+    /// text, matches that don't assign any group are skipped.
+    ///
+    /// Branches of the enumeration that assign a marker outside of the window
+    /// are pruned as soon as they are reached, which avoids enumerating the
+    /// matches that extend them.
+    pub fn iter_window<'i>(&'i self, byte_range: Range<usize>) -> IndexedDagIterator<'i, 't> {
+        // Levels whose position is within the window, a marker assigned at
+        // level `l` being at position `char_offsets[l]`.
+        let first_level = self.char_offsets.partition_point(|&pos| pos < byte_range.start);
+        let end_level = self.char_offsets.partition_point(|&pos| pos <= byte_range.end);
+
+        let mut iterator = IndexedDagIterator::init(self);
+        iterator.window = first_level..end_level;
+        iterator.skip_empty = true;
+        iterator
+    }
+
+    /// Enumerate matches whose main span starts at or after a byte offset of
+    /// the text, which must fall on a char boundary.
+    ///
+    /// As for `iter_window`, levels before the offset are skipped by pruning
+    /// branches that assign a marker there, and matches that don't assign any
+    /// group are skipped.
+    pub fn iter_from<'i>(
+        &'i self,
+        start_byte: usize,
+    ) -> Result<IndexedDagIterator<'i, 't>, CharBoundaryError> {
+        let first_level = self
+            .char_offsets
+            .binary_search(&start_byte)
+            .map_err(|_| CharBoundaryError(start_byte))?;
+
+        let mut iterator = IndexedDagIterator::init(self);
+        iterator.window = first_level..self.char_offsets.len();
+        iterator.skip_empty = true;
+        Ok(iterator)
+    }
+
+    /// Call a function on each match, as enumerated by `iter`, without building
+    /// an owned `Mapping` for it.
+    ///
+    /// This saves the map of groups and the copy of markers of each mapping,
+    /// but not the allocations of the enumeration itself: the assignations of
+    /// each run are still collected into a vector which the view borrows.
+    ///
+    /// Views are only valid during the call, assignations are not checked to
+    /// be well-formed as `Mapping::try_from_markers` does.
+    pub fn for_each_match<F>(&self, mut f: F)
+    where
+        F: FnMut(&MappingView<'_, 't>),
+    {
+        let mut iterator = IndexedDagIterator::init(self);
+
+        while let Some(markers) = iterator.next_markers() {
+            f(&MappingView::new(self.text, &markers, &self.char_offsets));
+        }
+    }
+
+    /// Compute statistics over the spans assigned to each group along all
+    /// matches, in a single traversal through `for_each_match` which doesn't
+    /// build any mapping.
+    ///
+    /// Groups that are never assigned are not listed, while the group *match*
+    /// is listed as any other group, its count is thus the number of matches.
+    pub fn group_stats(&self) -> HashMap<String, GroupStat> {
+        let mut stats: HashMap<String, GroupStat> = HashMap::new();
+
+        self.for_each_match(|view| {
+            for (name, span) in view.iter_groups() {
+                match stats.get_mut(name) {
+                    Some(stat) => stat.add(span.len()),
+                    None => {
+                        stats.insert(name.to_string(), GroupStat::new(span.len()));
+                    }
+                }
+            }
+        });
+
+        stats
+    }
+
+    /// Enumerate distinct main spans of matches, regardless of the assignation
+    /// of other groups.
+    ///
+    /// Spans already seen are stored, thus the memory used grows with the
+    /// number of distinct spans.
+    pub fn iter_spans<'i>(&'i self) -> impl Iterator<Item = Range<usize>> + 'i {
+        distinct_spans(self.iter())
+    }
+
+    /// Enumerate the text of the main span of each match, as owned strings.
+    pub fn iter_texts<'i>(&'i self) -> impl Iterator<Item = String> + 'i {
+        self.iter().map(move |mapping| {
+            let span = mapping.main_span();
+            self.text[span].to_string()
+        })
+    }
+
+    /// Enumerate the text assigned to a group in each match, as owned strings.
+    /// Matches that don't assign the group are skipped.
+    pub fn iter_group_texts<'i>(&'i self, name: &'i str) -> impl Iterator<Item = String> + 'i {
+        self.iter()
+            .filter_map(move |mapping| mapping.group_text(name).map(str::to_string))
+    }
+
+    /// Enumerate all matches sorted by increasing main span.
+    ///
+    /// Unlike `iter`, all matches need to be computed and sorted before the
+    /// first one is returned, which takes O(k log k) time and O(k) memory for
+    /// k matches.
+    ///
+    /// Fails with the first ill-formed mapping produced by the automaton, see
+    /// `iter`.
+    pub fn iter_sorted(
+        &self,
+    ) -> Result<impl DoubleEndedIterator<Item = Mapping<'t>>, MappingError> {
+        let mut mappings = self.try_collect()?;
+        mappings.sort();
+        Ok(mappings.into_iter())
+    }
+
+    /// Enumerate all matches sorted by decreasing main span, thus starting with
+    /// the last match of the text.
+    ///
+    /// This has the same complexity and failure cases as `iter_sorted`.
+    pub fn iter_rev(&self) -> Result<impl Iterator<Item = Mapping<'t>>, MappingError> {
+        Ok(self.iter_sorted()?.rev())
+    }
+
+    /// Enumerate leftmost-longest matches, as most regex engines would: the
+    /// longest match starting first is selected, then the process is repeated
+    /// from the end of this match, see `replace::select_leftmost`.
+    ///
+    /// Unlike `iter`, which enumerates every span of the text that matches the
+    /// pattern, along with every possible assignation of its groups, selected
+    /// matches never overlap and at most one assignation is given for each of
+    /// them. All matches need to be computed before the first one is returned,
+    /// which has the same complexity and failure cases as `iter_sorted`.
+    pub fn iter_leftmost_longest(
+        &self,
+    ) -> Result<impl Iterator<Item = Mapping<'t>>, MappingError> {
+        let mappings = self.try_collect()?;
+        Ok(select_leftmost(mappings.into_iter(), Preference::Longest).into_iter())
+    }
+
+    /// Collect all matches, or the first ill-formed mapping if any.
+    fn try_collect(&self) -> Result<Vec<Mapping<'t>>, MappingError> {
+        let mut iterator = self.iter();
+        let mappings = iterator.by_ref().collect();
+
+        match iterator.error.take() {
+            Some(err) => Err(err),
+            None => Ok(mappings),
+        }
+    }
+
+    /// Enumerate all matches using a thread pool, the output order is not
+    /// specified.
+    ///
+    /// The enumeration tree is first explored breadth-first until there are
+    /// enough independent partial runs to feed all threads, then each partial
+    /// run is enumerated on its own. Two distinct partial runs never share an
+    /// assignation, thus the matches stay distinct.
+    ///
+    /// Fails with an ill-formed mapping produced by the automaton, see `iter`.
+    #[cfg(feature = "parallel")]
+    pub fn par_iter(&self) -> Result<Vec<Mapping<'t>>, MappingError> {
+        let nb_runs = 4 * rayon::current_num_threads();
+        let mut results = Vec::new();
+        let mut runs = vec![self.start_run()];
+
+        while !runs.is_empty() && runs.len() < nb_runs {
+            let mut next_runs = Vec::new();
+
+            for (level, gamma, mapping) in runs {
+                for (s_p, new_gamma) in self.next_level(gamma) {
+                    match self.follow_branch(level, &mapping, s_p, new_gamma) {
+                        Some(Branch::Match(markers)) => results.push(self.build_mapping(markers)?),
+                        Some(Branch::Run(run)) => next_runs.push(run),
+                        None => {}
+                    }
+                }
+            }
+
+            runs = next_runs;
+        }
+
+        let sub_results: Vec<_> = runs
+            .into_par_iter()
+            .map(|run| {
+                let mut iterator = IndexedDagIterator::from_stack(self, vec![run]);
+                let mappings: Vec<_> = iterator.by_ref().collect();
+
+                match iterator.error.take() {
+                    Some(err) => Err(err),
+                    None => Ok(mappings),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        results.extend(sub_results.into_iter().flatten());
+        Ok(results)
+    }
+
+    /// Count distinct matches over the whole text, level by level, which
+    /// takes time independent of the number of matches.
+    ///
+    /// The automaton is determinized on the fly: runs reaching a level are
+    /// grouped by the set of states they reach, and each set of markers that
+    /// can be assigned from there leads to a single set of states. Distinct
+    /// mappings assign distinct sets of markers in some level, thus each of
+    /// them is given by exactly one path of sets of states, whose number is
+    /// propagated from a level to the next one by an integer matrix product.
+    ///
+    /// This equals `iter().count()`, except that ill-formed mappings skipped
+    /// by the enumeration are also counted. The time taken depends on the
+    /// number of sets of states reached in each level, which is small unless
+    /// the pattern is pathological. The count saturates at `u64::MAX`.
+    pub fn count_matches_algebraic(&self) -> u64 {
+        debug_assert_eq!(self.char_offsets.last(), Some(&self.text.len()));
+        let assignations = self.automaton.get_assignations();
+
+        let marker_index: HashMap<&Marker, usize> = assignations
+            .iter()
+            .flatten()
+            .map(|(label, _)| label.get_marker().unwrap())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .enumerate()
+            .map(|(index, marker)| (marker, index))
+            .collect();
+
+        // Sets of states reached from a set of states by following
+        // assignations, one for each set of markers that can be assigned.
+        let follow_assignations = |gamma: &[usize]| -> Vec<Vec<usize>> {
+            let mut visited: HashSet<(usize, Vec<usize>)> =
+                gamma.iter().map(|&state| (state, Vec::new())).collect();
+            let mut stack: Vec<_> = visited.iter().cloned().collect();
+
+            while let Some((source, markers)) = stack.pop() {
+                for (label, target) in &assignations[source] {
+                    let marker = marker_index[label.get_marker().unwrap()];
+                    let mut markers = markers.clone();
+
+                    if let Err(pos) = markers.binary_search(&marker) {
+                        markers.insert(pos, marker);
+                    }
+
+                    if visited.insert((*target, markers.clone())) {
+                        stack.push((*target, markers));
+                    }
+                }
+            }
+
+            let mut reached: HashMap<Vec<usize>, Vec<usize>> = HashMap::new();
+
+            for (state, markers) in visited {
+                reached.entry(markers).or_default().push(state);
+            }
+
+            reached
+                .into_values()
+                .map(|mut states| {
+                    states.sort_unstable();
+                    states
+                })
+                .collect()
+        };
+
+        let mut assigned_cache: HashMap<Vec<usize>, Vec<Vec<usize>>> = HashMap::new();
+        let mut assigned = |gamma: &Vec<usize>| -> Vec<Vec<usize>> {
+            assigned_cache
+                .entry(gamma.clone())
+                .or_insert_with(|| follow_assignations(gamma))
+                .clone()
+        };
+
+        // Sets of states reached in current level, with the number of paths
+        // leading to each of them.
+        let mut sets = vec![vec![self.automaton.get_initial()]];
+        let mut paths = vec![1_u64];
+
+        for curr_char in self.text.chars() {
+            let adj = self.automaton.get_adj_for_char(curr_char);
+            let mut next_sets = Vec::new();
+            let mut next_index = HashMap::new();
+            let mut transitions = Vec::new();
+
+            for (col, gamma) in sets.iter().enumerate() {
+                for gamma in assigned(gamma) {
+                    let mut next: Vec<_> = gamma
+                        .iter()
+                        .flat_map(|&source| adj[source].iter().copied().map(state_index))
+                        .collect();
+                    next.sort_unstable();
+                    next.dedup();
+
+                    if next.is_empty() {
+                        continue;
+                    }
+
+                    let row = *next_index.entry(next.clone()).or_insert_with(|| {
+                        next_sets.push(next);
+                        next_sets.len() - 1
+                    });
+
+                    transitions.push((row, col));
+                }
+            }
+
+            // `step[j, i]` is the number of sets of markers leading from the
+            // ith set of states to the jth set of states of next level.
+            let mut step = Matrix::new(next_sets.len(), sets.len(), 0_u64);
+
+            for (row, col) in transitions {
+                *step.at(row, col) += 1;
+            }
+
+            paths = step.col_mul(&paths);
+            sets = next_sets;
+        }
+
+        let finals = &self.automaton.finals;
+
+        sets.iter().zip(paths).fold(0, |acc: u64, (gamma, count)| {
+            let nb_accepting = assigned(gamma)
+                .iter()
+                .filter(|gamma| gamma.iter().any(|state| finals.contains(state)))
+                .count();
+
+            acc.saturating_add(count.saturating_mul(nb_accepting as u64))
+        })
+    }
+
+    /// Check if there is a match spanning the whole text.
+    ///
+    /// For a fully anchored pattern, any match spans the whole text, thus only
+    /// the first match is built.
+    pub fn is_full_match(&self) -> bool {
+        let text_span = 0..*self.char_offsets.last().unwrap();
+        !self.is_empty() && self.iter().any(|mapping| mapping.main_span() == text_span)
+    }
+
+    /// Check if no match can be found, without starting the enumeration.
+    pub fn is_empty(&self) -> bool {
+        let (_, gamma, _) = self.start_run();
+        self.jump.is_disconnected() || gamma.is_empty()
+    }
+
+    /// Summarize the set of characters that can be part of a match.
+    pub fn alphabet(&self) -> CharClassSummary {
+        self.automaton.alphabet()
+    }
+
+    /// List distinct variables that can be assigned by matches, sorted by id.
+    pub fn variables(&self) -> Vec<&Variable> {
+        self.automaton.variables()
+    }
+
+    /// Number of vertices of each level remaining after cleaning, as pairs
+    /// `(level, count)` sorted by level.
+    pub fn level_sizes(&self) -> Vec<(usize, usize)> {
+        self.jump.level_sizes()
+    }
+
     pub fn get_nb_levels(&self) -> usize {
         self.jump.get_nb_levels()
     }
 
+    /// Levels that were left uncleaned after an inconsistency was found in the
+    /// structure, along with this inconsistency. Matches are not affected.
+    pub fn skipped_cleanings(&self) -> &[(usize, CleanError)] {
+        &self.skipped_cleanings
+    }
+
+    /// Write a readable trace of the jump function, see `Jump::dump_trace`.
+    /// This can be slow and large for long inputs.
+    pub fn dump_jump<W: Write>(&self, w: W) -> io::Result<()> {
+        self.jump.dump_trace(w)
+    }
+
+    /// Copy the text into an `OwnedIndexedDag`, which doesn't borrow it
+    /// anymore.
+    pub fn into_owned(self) -> OwnedIndexedDag {
+        OwnedIndexedDag {
+            text: self.text.to_string(),
+            dag:  self.detach(),
+        }
+    }
+
+    /// Forget the text, levels are still aligned on the bytes of the text.
+    fn detach(self) -> IndexedDag<'static> {
+        IndexedDag {
+            automaton:    self.automaton,
+            text:         "",
+            jump:         self.jump,
+            char_offsets: self.char_offsets,
+            declared:     self.declared,
+
+            skipped_cleanings: self.skipped_cleanings,
+        }
+    }
+
     fn next_level<'a>(&'a self, gamma: Vec<usize>) -> NextLevelIterator<'a> {
         let adj = self.automaton.get_rev_assignations();
 
@@ -127,6 +875,206 @@ impl<'t> IndexedDag<'t> {
 
         NextLevelIterator::explore(&self.automaton, k, gamma)
     }
+
+    /// The partial run the enumeration starts from: the last level, with
+    /// reachable final states and no assignation.
+    fn start_run(&self) -> PartialRun<'_> {
+        let start = self
+            .jump
+            .finals()
+            .into_iter()
+            .map(state_index)
+            .filter(|state| self.automaton.finals.contains(state))
+            .collect();
+
+        (self.char_offsets.len() - 1, start, Vec::new())
+    }
+
+    /// Extend a partial run at given level with the markers `s_p`, leading to
+    /// the set of states `new_gamma`. Returns `None` if this branch doesn't
+    /// lead to any match.
+    fn follow_branch<'i>(
+        &'i self,
+        level: usize,
+        mapping: &[(&'i Marker, usize)],
+        s_p: Vec<&'i Marker>,
+        new_gamma: Vec<usize>,
+    ) -> Option<Branch<'i>> {
+        if new_gamma.is_empty() {
+            return None;
+        }
+
+        let mut new_mapping = mapping.to_vec();
+        for marker in s_p {
+            new_mapping.push((marker, level));
+        }
+
+        if level == 0 && new_gamma.contains(&self.automaton.get_initial()) {
+            Some(Branch::Match(new_mapping))
+        } else {
+            match self.jump.jump(level, new_gamma.into_iter().map(state_id)) {
+                Some((jump_level, jump_gamma)) if !jump_gamma.is_empty() => {
+                    let jump_gamma = jump_gamma.into_iter().map(state_index).collect();
+                    Some(Branch::Run((jump_level, jump_gamma, new_mapping)))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    /// Build the mapping of a complete run, given its assignations.
+    fn build_mapping(&self, markers: Vec<(&Marker, usize)>) -> Result<Mapping<'t>, MappingError> {
+        // Re-align level indexes with utf8 coding
+        let aligned_markers = markers
+            .into_iter()
+            .map(|(marker, pos)| (marker.clone(), self.char_offsets[pos]));
+
+        let mapping = Mapping::try_from_markers(self.text, aligned_markers)?;
+        Ok(mapping.with_declared_variables(self.declared.clone()))
+    }
+}
+
+/// An `IndexedDag` that owns its text, which allows to return it from a
+/// function or to store it without keeping the text alive.
+///
+/// The inner DAG doesn't refer to the text, and mappings are attached to the
+/// owned text as they are enumerated.
+pub struct OwnedIndexedDag {
+    text: String,
+    dag:  IndexedDag<'static>,
+}
+
+impl OwnedIndexedDag {
+    /// Compute the index of matches of an automaton over a text, which is
+    /// moved into the DAG, see `IndexedDag::compile`.
+    pub fn compile(automaton: Automaton, text: String) -> OwnedIndexedDag {
+        let dag = IndexedDag::builder()
+            .compile(automaton, &text)
+            .expect("Compilation without bounds can't fail")
+            .detach();
+
+        OwnedIndexedDag { text, dag }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+
+    /// Enumerate all matches, as `IndexedDag::iter` does.
+    pub fn iter(&self) -> OwnedIndexedDagIterator<'_> {
+        OwnedIndexedDagIterator {
+            text:  &self.text,
+            inner: self.dag.iter(),
+        }
+    }
+
+    /// Enumerate distinct main spans of matches, see `IndexedDag::iter_spans`.
+    pub fn iter_spans(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        distinct_spans(self.iter())
+    }
+
+    /// Count all matches, without building them.
+    pub fn count(&self) -> usize {
+        let mut count = 0;
+        self.dag.for_each_match(|_| count += 1);
+        count
+    }
+}
+
+impl<'i> IntoIterator for &'i OwnedIndexedDag {
+    type Item = Mapping<'i>;
+    type IntoIter = OwnedIndexedDagIterator<'i>;
+
+    fn into_iter(self) -> OwnedIndexedDagIterator<'i> {
+        self.iter()
+    }
+}
+
+/// Iterator over the matches of an `OwnedIndexedDag`, see
+/// `OwnedIndexedDag::iter`.
+pub struct OwnedIndexedDagIterator<'i> {
+    text:  &'i str,
+    inner: IndexedDagIterator<'i, 'static>,
+}
+
+impl<'i> OwnedIndexedDagIterator<'i> {
+    /// Get the first ill-formed mapping met during the enumeration, if any.
+    pub fn error(&self) -> Option<&MappingError> {
+        self.inner.error()
+    }
+}
+
+impl<'i> Iterator for OwnedIndexedDagIterator<'i> {
+    type Item = Mapping<'i>;
+
+    fn next(&mut self) -> Option<Mapping<'i>> {
+        Some(self.inner.next()?.rebase(self.text, 0))
+    }
+}
+
+/// An `IndexedDag` over a slice of bytes, see `IndexedDag::compile_bytes`.
+///
+/// The inner DAG doesn't refer to any text, and mappings are attached to the
+/// bytes as they are enumerated.
+pub struct BytesIndexedDag<'t> {
+    bytes: &'t [u8],
+    dag:   IndexedDag<'static>,
+}
+
+impl<'t> BytesIndexedDag<'t> {
+    pub fn bytes(&self) -> &'t [u8] {
+        self.bytes
+    }
+
+    /// Enumerate all matches, as `IndexedDag::iter` does.
+    pub fn iter(&self) -> BytesIndexedDagIterator<'_, 't> {
+        BytesIndexedDagIterator {
+            bytes: self.bytes,
+            inner: self.dag.iter(),
+        }
+    }
+
+    /// Count all matches, without building them.
+    pub fn count(&self) -> usize {
+        let mut count = 0;
+        self.dag.for_each_match(|_| count += 1);
+        count
+    }
+}
+
+impl<'i, 't> IntoIterator for &'i BytesIndexedDag<'t> {
+    type Item = BytesMapping<'t>;
+    type IntoIter = BytesIndexedDagIterator<'i, 't>;
+
+    fn into_iter(self) -> BytesIndexedDagIterator<'i, 't> {
+        self.iter()
+    }
+}
+
+/// Iterator over the matches of a `BytesIndexedDag`, see
+/// `BytesIndexedDag::iter`.
+pub struct BytesIndexedDagIterator<'i, 't> {
+    bytes: &'t [u8],
+    inner: IndexedDagIterator<'i, 'static>,
+}
+
+impl<'i, 't> BytesIndexedDagIterator<'i, 't> {
+    /// Get the first ill-formed mapping met during the enumeration, if any.
+    pub fn error(&self) -> Option<&MappingError> {
+        self.inner.error()
+    }
+}
+
+impl<'i, 't> Iterator for BytesIndexedDagIterator<'i, 't> {
+    type Item = BytesMapping<'t>;
+
+    fn next(&mut self) -> Option<BytesMapping<'t>> {
+        Some(BytesMapping::new(self.bytes, self.inner.next()?))
+    }
 }
 
 //  ___           _                   _
@@ -141,74 +1089,116 @@ impl<'t> IndexedDag<'t> {
 // |____/ \__,_|\__, |
 //              |___/
 
-struct IndexedDagIterator<'i, 't> {
+/// A partial run of the enumeration: a level, the set of states reached in this
+/// level and the assignations done so far.
+type PartialRun<'i> = (usize, Vec<usize>, Vec<(&'i Marker, usize)>);
+
+/// Outcome of following a branch of the enumeration.
+enum Branch<'i> {
+    /// The branch reached the begining of the text, with given assignations.
+    Match(Vec<(&'i Marker, usize)>),
+    /// The branch still has to be explored.
+    Run(PartialRun<'i>),
+}
+
+/// Iterator over the matches of an `IndexedDag`, see `IndexedDag::iter`.
+///
+/// Ill-formed mappings produced by the automaton, such as a group assigned
+/// twice by a repetition, are skipped and the first one can be inspected with
+/// `IndexedDagIterator::error`.
+pub struct IndexedDagIterator<'i, 't> {
     indexed_dag: &'i IndexedDag<'t>,
-    stack:       Vec<(usize, Vec<usize>, Vec<(&'i Marker, usize)>)>,
+    stack:       Vec<PartialRun<'i>>,
+
+    /// Levels where markers can be assigned, branches assigning markers at
+    /// other levels are skipped.
+    window: Range<usize>,
 
     curr_level:      usize,
     curr_mapping:    Vec<(&'i Marker, usize)>,
     curr_next_level: NextLevelIterator<'i>,
+
+    /// Skip matches that don't assign any group.
+    skip_empty: bool,
+
+    /// First ill-formed mapping met during the enumeration.
+    error: Option<MappingError>,
 }
 
 impl<'i, 't> IndexedDagIterator<'i, 't> {
     fn init(indexed_dag: &'i IndexedDag<'t>) -> IndexedDagIterator<'i, 't> {
-        let start = indexed_dag
-            .jump
-            .finals()
-            .intersection(&indexed_dag.automaton.finals.iter().map(|x| *x).collect())
-            .map(|x| *x)
-            .collect();
+        IndexedDagIterator::from_stack(indexed_dag, vec![indexed_dag.start_run()])
+    }
 
+    /// Enumerate all matches that extend one of the partial runs of a stack.
+    fn from_stack(
+        indexed_dag: &'i IndexedDag<'t>,
+        stack: Vec<PartialRun<'i>>,
+    ) -> IndexedDagIterator<'i, 't> {
         IndexedDagIterator {
             indexed_dag,
-            stack: vec![(indexed_dag.text.chars().count(), start, Vec::new())],
+            stack,
+            window: 0..indexed_dag.char_offsets.len(),
 
             // `curr_next_level` is initialized empty, thus theses values will
             // be replaced before the first iteration.
             curr_next_level: NextLevelIterator::empty(&indexed_dag.automaton),
             curr_level: usize::default(),
             curr_mapping: Vec::default(),
+            skip_empty: false,
+            error: None,
         }
     }
+
+    /// Get the first ill-formed mapping met during the enumeration, if any.
+    pub fn error(&self) -> Option<&MappingError> {
+        self.error.as_ref()
+    }
+
+    /// Enumerate remaining matches, while displaying a spinner with the number
+    /// of matches found so far if progress is enabled.
+    pub fn with_progress(
+        &mut self,
+        toggle_progress: ToggleProgress,
+    ) -> Box<dyn Iterator<Item = Mapping<'t>> + '_> {
+        let progress = match toggle_progress {
+            ToggleProgress::Enabled => Progress::spinner(self.by_ref()).auto_refresh(false),
+            ToggleProgress::Disabled => Progress::silent(self.by_ref()),
+        };
+
+        Box::new(count_progress(progress))
+    }
 }
 
-impl<'i, 't> Iterator for IndexedDagIterator<'i, 't> {
+impl<'i, 't> IntoIterator for &'i IndexedDag<'t> {
     type Item = Mapping<'t>;
+    type IntoIter = IndexedDagIterator<'i, 't>;
 
-    fn next(&mut self) -> Option<Mapping<'t>> {
+    fn into_iter(self) -> IndexedDagIterator<'i, 't> {
+        IndexedDagIterator::init(self)
+    }
+}
+
+impl<'i, 't> IndexedDagIterator<'i, 't> {
+    /// Enumerate the assignations of the next complete run, with positions
+    /// given as levels.
+    fn next_markers(&mut self) -> Option<Vec<(&'i Marker, usize)>> {
         loop {
             // First, consume curr_next_level.
-            while let Some((s_p, new_gamma)) = self.curr_next_level.next() {
-                if new_gamma.is_empty() {
+            for (s_p, new_gamma) in self.curr_next_level.by_ref() {
+                if !s_p.is_empty() && !self.window.contains(&self.curr_level) {
                     continue;
                 }
 
-                let mut new_mapping = self.curr_mapping.clone();
-                for marker in s_p {
-                    new_mapping.push((marker, self.curr_level));
-                }
-
-                if self.curr_level == 0
-                    && new_gamma.contains(&self.indexed_dag.automaton.get_initial())
-                {
-                    // Re-align level indexes with utf8 coding
-                    let aligned_markers = new_mapping
-                        .into_iter()
-                        .map(|(marker, pos)| (marker.clone(), self.indexed_dag.char_offsets[pos]));
-
-                    // Create the new mapping
-                    return Some(Mapping::from_markers(
-                        self.indexed_dag.text,
-                        aligned_markers,
-                    ));
-                } else if let Some((jump_level, jump_gamma)) = self
-                    .indexed_dag
-                    .jump
-                    .jump(self.curr_level, new_gamma.into_iter())
-                {
-                    if !jump_gamma.is_empty() {
-                        self.stack.push((jump_level, jump_gamma, new_mapping));
-                    }
+                match self.indexed_dag.follow_branch(
+                    self.curr_level,
+                    &self.curr_mapping,
+                    s_p,
+                    new_gamma,
+                ) {
+                    Some(Branch::Match(markers)) => return Some(markers),
+                    Some(Branch::Run(run)) => self.stack.push(run),
+                    None => {}
                 }
             }
 
@@ -226,6 +1216,58 @@ impl<'i, 't> Iterator for IndexedDagIterator<'i, 't> {
     }
 }
 
+impl<'i, 't> Iterator for IndexedDagIterator<'i, 't> {
+    type Item = Mapping<'t>;
+
+    fn next(&mut self) -> Option<Mapping<'t>> {
+        loop {
+            match self.indexed_dag.build_mapping(self.next_markers()?) {
+                Ok(mapping) if self.skip_empty && mapping.is_empty() => {}
+                Ok(mapping) => return Some(mapping),
+                Err(err) => {
+                    self.error.get_or_insert(err);
+                }
+            }
+        }
+    }
+}
+
+/// Number of items enumerated between two checks for a refresh in
+/// `count_progress`.
+const PROGRESS_CHUNK: usize = 1024;
+
+/// Drive a progress bar which is not automatically refreshed, displaying the
+/// number of matches found so far.
+///
+/// As formatting the count and checking the clock for each match would slow
+/// the enumeration down, the count is only updated by chunks of
+/// `PROGRESS_CHUNK` matches when a refresh is due. The count displayed once
+/// the iterator is exhausted is exact.
+pub(super) fn count_progress<T, U>(mut progress: Progress<T, U>) -> impl Iterator<Item = U>
+where
+    T: Iterator<Item = U>,
+{
+    let mut count = 0;
+
+    iter::from_fn(move || match progress.next() {
+        Some(item) => {
+            count += 1;
+
+            if count % PROGRESS_CHUNK == 0 && progress.refresh_due() {
+                progress.extra_msg(format!("{} matches", count));
+                progress.refresh();
+            }
+
+            Some(item)
+        }
+        None => {
+            progress.extra_msg(format!("{} matches", count));
+            progress.finish();
+            None
+        }
+    })
+}
+
 //  _   _           _   _                   _
 // | \ | | _____  _| |_| |    _____   _____| |
 // |  \| |/ _ \ \/ / __| |   / _ \ \ / / _ \ |
@@ -245,12 +1287,95 @@ struct NextLevelIterator<'a> {
 
     /// Set of markers that can be reached in this level.
     expected_markers: Vec<&'a Marker>,
+    /// Index of markers in `expected_markers`.
+    marker_index: HashMap<&'a Marker, usize>,
 
     /// Set of states we start the run from.
     gamma: Vec<usize>,
 
     /// The current state of the iterator
-    stack: Vec<(HashSet<&'a Marker>, HashSet<&'a Marker>)>,
+    stack: Vec<(MarkerSet, MarkerSet)>,
+
+    /// Buffers reused by successive calls to `follow_sp_sm`, `path_sets` is
+    /// indexed by states and reset to `Unvisited` after each call.
+    path_sets: Vec<PathSet>,
+    visited:   Vec<usize>,
+    queue:     VecDeque<usize>,
+}
+
+/// Set of markers of `Sp` that are read along paths leading to a state while
+/// following a level, see `NextLevelIterator::follow_sp_sm`.
+#[derive(Clone)]
+enum PathSet {
+    Unvisited,
+    Set(MarkerSet),
+    /// Paths with incomparable sets lead to the state.
+    Incomparable,
+}
+
+/// Set of markers, represented by their indices in `expected_markers`.
+///
+/// The first 64 indices are stored inline, thus sets of levels with less than
+/// 64 markers are cloned without any allocation.
+#[derive(Clone, Default)]
+struct MarkerSet {
+    low:  u64,
+    high: Vec<u64>,
+}
+
+impl MarkerSet {
+    fn word(&self, word: usize) -> u64 {
+        match word {
+            0 => self.low,
+            _ => self.high.get(word - 1).copied().unwrap_or(0),
+        }
+    }
+
+    fn nb_words(&self) -> usize {
+        1 + self.high.len()
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.word(index / 64) & (1 << (index % 64)) != 0
+    }
+
+    fn insert(&mut self, index: usize) {
+        let bit = 1 << (index % 64);
+
+        match index / 64 {
+            0 => self.low |= bit,
+            word => {
+                if self.high.len() < word {
+                    self.high.resize(word, 0);
+                }
+
+                self.high[word - 1] |= bit;
+            }
+        }
+    }
+
+    fn remove(&mut self, index: usize) {
+        let mask = !(1 << (index % 64));
+
+        match index / 64 {
+            0 => self.low &= mask,
+            word => {
+                if let Some(high) = self.high.get_mut(word - 1) {
+                    *high &= mask;
+                }
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        let high: u32 = self.high.iter().map(|word| word.count_ones()).sum();
+        (self.low.count_ones() + high) as usize
+    }
+
+    fn is_subset(&self, other: &MarkerSet) -> bool {
+        (0..cmp::max(self.nb_words(), other.nb_words()))
+            .all(|word| self.word(word) & !other.word(word) == 0)
+    }
 }
 
 impl<'a> NextLevelIterator<'a> {
@@ -260,7 +1385,11 @@ impl<'a> NextLevelIterator<'a> {
             stack: Vec::new(), // Initialized with an empty stack to stop iteration instantly.
             automaton,
             expected_markers: Vec::default(),
+            marker_index: HashMap::default(),
             gamma: Vec::default(),
+            path_sets: Vec::new(),
+            visited: Vec::new(),
+            queue: VecDeque::new(),
         }
     }
 
@@ -270,83 +1399,116 @@ impl<'a> NextLevelIterator<'a> {
         expected_markers: HashSet<&'a Marker>,
         gamma: Vec<usize>,
     ) -> NextLevelIterator<'a> {
+        let expected_markers: Vec<_> = expected_markers.into_iter().collect();
+        let marker_index = expected_markers
+            .iter()
+            .enumerate()
+            .map(|(index, &marker)| (marker, index))
+            .collect();
+
         NextLevelIterator {
             automaton,
-            expected_markers: expected_markers.into_iter().collect(),
+            expected_markers,
+            marker_index,
             gamma,
-            stack: vec![(HashSet::new(), HashSet::new())],
+            stack: vec![(MarkerSet::default(), MarkerSet::default())],
+            path_sets: Vec::new(),
+            visited: Vec::new(),
+            queue: VecDeque::new(),
         }
     }
 
-    fn follow_sp_sm(
-        &self,
-        gamma: &Vec<usize>,
-        s_p: &HashSet<&Marker>,
-        s_m: &HashSet<&Marker>,
-    ) -> Vec<usize> {
-        let adj = self.automaton.get_rev_assignations();
-        let mut path_set: HashMap<usize, Option<HashSet<_>>> = HashMap::new();
+    fn follow_sp_sm(&mut self, s_p: &MarkerSet, s_m: &MarkerSet) -> Vec<usize> {
+        let automaton: &'a Automaton = self.automaton;
+        let adj = automaton.get_rev_assignations();
+
+        // Buffers are only allocated once, the first time they are used.
+        let path_sets = &mut self.path_sets;
+        path_sets.resize(automaton.get_nb_states(), PathSet::Unvisited);
+
+        let visited = &mut self.visited;
+        let queue = &mut self.queue;
 
-        for &state in gamma {
-            path_set.insert(state, Some(HashSet::new()));
+        for &state in &self.gamma {
+            if let PathSet::Unvisited = path_sets[state] {
+                visited.push(state);
+            }
+
+            path_sets[state] = PathSet::Set(MarkerSet::default());
+            queue.push_back(state);
         }
 
         // Check if two sets are incomparable
         let are_incomparable =
-            |set1: &HashSet<_>, set2: &HashSet<_>| !set1.is_subset(&set2) && !set2.is_subset(&set1);
-
-        // TODO: Consider writing this as a recursive function?
-        let mut queue: VecDeque<_> = gamma.iter().cloned().collect();
+            |set1: &MarkerSet, set2: &MarkerSet| !set1.is_subset(set2) && !set2.is_subset(set1);
 
         while let Some(source) = queue.pop_front() {
             for (label, target) in &adj[source] {
-                let label = label.get_marker().unwrap();
+                // Any marker reachable from gamma is expected in this level.
+                let label = self.marker_index[label.get_marker().unwrap()];
 
                 if s_m.contains(label) {
                     continue;
                 }
 
-                if !path_set.contains_key(target) {
-                    queue.push_back(*target);
-                }
-
-                let mut new_ps = path_set[&source].clone().unwrap();
+                let mut new_ps = match &path_sets[source] {
+                    PathSet::Set(ps) => ps.clone(),
+                    _ => panic!("Explored states should have a path set"),
+                };
 
                 if s_p.contains(label) {
                     new_ps.insert(label);
                 }
 
-                path_set
-                    .entry(*target)
-                    .and_modify(|entry| {
-                        if let Some(old_ps) = entry {
-                            if are_incomparable(&new_ps, old_ps) {
-                                *entry = None;
-                            } else {
-                                *entry = Some(new_ps.clone());
-                            }
-                        }
-                    })
-                    .or_insert(Some(new_ps));
+                path_sets[*target] = match &path_sets[*target] {
+                    PathSet::Unvisited => {
+                        visited.push(*target);
+                        queue.push_back(*target);
+                        PathSet::Set(new_ps)
+                    }
+                    PathSet::Set(old_ps) if are_incomparable(&new_ps, old_ps) => {
+                        PathSet::Incomparable
+                    }
+                    PathSet::Set(_) => PathSet::Set(new_ps),
+                    PathSet::Incomparable => PathSet::Incomparable,
+                };
             }
         }
 
-        path_set
+        let result = visited
             .iter()
-            .filter_map(|(vertex, vertex_ps)| match vertex_ps {
-                Some(vertex_ps) if vertex_ps.len() == s_p.len() => Some(*vertex),
-                _ => None,
+            .copied()
+            .filter(|&vertex| match &path_sets[vertex] {
+                PathSet::Set(vertex_ps) => vertex_ps.len() == s_p.len(),
+                _ => false,
             })
-            .collect()
+            .collect();
+
+        for vertex in visited.drain(..) {
+            path_sets[vertex] = PathSet::Unvisited;
+        }
+
+        result
     }
 }
 
 impl<'a> Iterator for NextLevelIterator<'a> {
-    type Item = (HashSet<&'a Marker>, Vec<usize>);
+    type Item = (Vec<&'a Marker>, Vec<usize>);
+
+    fn next(&mut self) -> Option<(Vec<&'a Marker>, Vec<usize>)> {
+        // No assignation can be followed from gamma, which is thus the only
+        // set of states that can be reached. This is the case for most levels.
+        if self.expected_markers.is_empty() {
+            return match self.stack.pop() {
+                Some(_) if !self.gamma.is_empty() => {
+                    Some((Vec::new(), mem::take(&mut self.gamma)))
+                }
+                _ => None,
+            };
+        }
 
-    fn next(&mut self) -> Option<(HashSet<&'a Marker>, Vec<usize>)> {
         while let Some((mut s_p, mut s_m)) = self.stack.pop() {
-            let mut gamma2 = Some(self.follow_sp_sm(&self.gamma, &s_p, &s_m));
+            let mut gamma2 = Some(self.follow_sp_sm(&s_p, &s_m));
 
             if gamma2.as_ref().unwrap().is_empty() {
                 continue;
@@ -354,30 +1516,38 @@ impl<'a> Iterator for NextLevelIterator<'a> {
 
             while s_p.len() + s_m.len() < self.expected_markers.len() {
                 let depth = s_p.len() + s_m.len();
-                s_p.insert(self.expected_markers[depth]);
-                gamma2 = Some(self.follow_sp_sm(&self.gamma, &s_p, &s_m));
+                s_p.insert(depth);
+                gamma2 = Some(self.follow_sp_sm(&s_p, &s_m));
 
                 if !gamma2.as_ref().unwrap().is_empty() {
                     // If current pair Sp/Sm is feasible, add the other branch
                     // to the stack.
                     let mut new_s_p = s_p.clone();
                     let mut new_s_m = s_m.clone();
-                    new_s_m.insert(self.expected_markers[depth]);
-                    new_s_p.remove(self.expected_markers[depth]);
+                    new_s_m.insert(depth);
+                    new_s_p.remove(depth);
                     self.stack.push((new_s_p, new_s_m));
                 } else {
                     // Overwise, the other branch has to be feasible.
-                    s_p.remove(self.expected_markers[depth]);
-                    s_m.insert(self.expected_markers[depth]);
+                    s_p.remove(depth);
+                    s_m.insert(depth);
                     gamma2 = None;
                 }
             }
 
             let gamma2 = match gamma2 {
-                None => self.follow_sp_sm(&self.gamma, &s_p, &s_m),
+                None => self.follow_sp_sm(&s_p, &s_m),
                 Some(val) => val,
             };
 
+            let s_p = self
+                .expected_markers
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| s_p.contains(index))
+                .map(|(_, &marker)| marker)
+                .collect();
+
             return Some((s_p, gamma2));
         }
 
@@ -1,8 +1,11 @@
 use std::cmp::max;
 use std::collections::{HashMap, HashSet};
+use std::error;
 use std::fmt;
+use std::io::{self, Write};
 use std::iter;
 
+use super::super::automaton::{state_index, StateId};
 use super::super::matrix::Matrix;
 use super::levelset::LevelSet;
 
@@ -50,12 +53,12 @@ pub struct Jump {
     /// Set of vertices that can't be jumped since it has an ingoing
     /// non-jumpable edge. NOTE: it may only be required to store it for the
     /// last level.
-    nonjump_vertices: HashSet<(usize, usize)>,
+    nonjump_vertices: HashSet<(usize, StateId)>,
     /// Keep track of number of jumps to a given vertex.
-    count_ingoing_jumps: HashMap<(usize, usize), usize>,
+    count_ingoing_jumps: HashMap<(usize, StateId), usize>,
 
     /// Closest level where an assignation is done accessible from any node.
-    jl: HashMap<(usize, usize), usize>,
+    jl: HashMap<(usize, StateId), usize>,
 
     /// Set of levels accessible from any level using `jl`.
     rlevel: HashMap<usize, HashSet<usize>>,
@@ -72,9 +75,9 @@ pub struct Jump {
 }
 
 impl Jump {
-    pub fn new<T>(initial_level: T, nonjump_adj: &Vec<Vec<usize>>) -> Jump
+    pub fn new<T>(initial_level: T, nonjump_adj: &[Vec<StateId>]) -> Jump
     where
-        T: Iterator<Item = usize>,
+        T: Iterator<Item = StateId>,
     {
         let mut jump = Jump {
             levelset:            LevelSet::new(),
@@ -89,9 +92,8 @@ impl Jump {
             clean_policy:        CleanPolicy::Clean,
         };
 
-        // TODO: implement cleaning without matrices
+        // TODO: implement cleaning without matrices, which are required for now
         if jump.matrix_policy == MatrixPolicy::Lazy && jump.clean_policy == CleanPolicy::Clean {
-            eprintln!(r"/!\ Can't clean jump levels without precomputed matrices.");
             jump.clean_policy = CleanPolicy::Skip;
         }
 
@@ -99,7 +101,7 @@ impl Jump {
         jump.rev_rlevel.insert(0, HashSet::new());
 
         for state in initial_level {
-            jump.levelset.register(state, 0);
+            jump.levelset.register(0, state);
             jump.jl.insert((0, state), 0);
 
             if jump.clean_policy == CleanPolicy::Clean {
@@ -122,7 +124,7 @@ impl Jump {
     /// Compute next level given the adjacency list of jumpable edges from
     /// current level to the next one and adjacency list of non-jumpable
     /// edges inside the next level.
-    pub fn init_next_level(&mut self, jump_adj: &Vec<Vec<usize>>, nonjump_adj: &Vec<Vec<usize>>) {
+    pub fn init_next_level(&mut self, jump_adj: &[Vec<StateId>], nonjump_adj: &[Vec<StateId>]) {
         let nonjump_vertices = &self.nonjump_vertices;
         let levelset = &mut self.levelset;
         let jl = &mut self.jl;
@@ -140,7 +142,7 @@ impl Jump {
             // necessary added by following an atomic transition.
             let source_jl = jl.get(&(last_level, source)).cloned();
 
-            for &target in &jump_adj[source] {
+            for &target in &jump_adj[state_index(source)] {
                 // Compute the level target will jump to, depending if there is already an
                 // assigned jump level for target or not.
                 let cmpt_jump_level = |previous_jl| {
@@ -168,7 +170,7 @@ impl Jump {
 
         // If at some point the next level is not reached, the output will be empty
         // anyway.
-        if levelset.get_level(next_level) == None {
+        if levelset.get_level(next_level).is_none() {
             return;
         }
 
@@ -192,9 +194,9 @@ impl Jump {
     ///
     /// NOTE: It may be possible to return an iterator to refs of usize, but the
     /// autoref seems to not do the work.
-    pub fn jump<T>(&self, level: usize, gamma: T) -> Option<(usize, Vec<usize>)>
+    pub fn jump<T>(&self, level: usize, gamma: T) -> Option<(usize, Vec<StateId>)>
     where
-        T: Clone + Iterator<Item = usize>,
+        T: Clone + Iterator<Item = StateId>,
     {
         let jump_level = gamma
             .clone()
@@ -231,7 +233,7 @@ impl Jump {
     }
 
     /// Get the vertices that are in the final layer
-    pub fn finals(&self) -> HashSet<usize> {
+    pub fn finals(&self) -> HashSet<StateId> {
         if self.is_disconnected() {
             return HashSet::new();
         }
@@ -244,19 +246,91 @@ impl Jump {
             .collect()
     }
 
+    /// Number of vertices of each level that is still stored, as pairs
+    /// `(level, count)` sorted by level.
+    pub fn level_sizes(&self) -> Vec<(usize, usize)> {
+        (0..=self.last_level)
+            .filter_map(|level| Some((level, self.levelset.get_level(level)?.len())))
+            .collect()
+    }
+
+    /// Number of vertices of a level, which is zero if it was removed.
+    pub fn level_width(&self, level: usize) -> usize {
+        self.levelset.get_level(level).map_or(0, Vec::len)
+    }
+
     pub fn get_nb_levels(&self) -> usize {
         self.levelset.get_nb_levels()
     }
 
+    /// Write a readable dump of the structure, level by level: vertices of
+    /// each level with their jump level `jl` and number of ingoing jumps,
+    /// followed by levels of `rlevel` and the matrices of `reach` from this
+    /// level.
+    pub fn dump_trace<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for level in 0..=self.last_level {
+            let vertices = match self.levelset.get_level(level) {
+                Some(vertices) => vertices,
+                None => {
+                    writeln!(w, "Level {}: removed", level)?;
+                    continue;
+                }
+            };
+
+            writeln!(w, "Level {}: {} vertices", level, vertices.len())?;
+
+            for &vertex in vertices {
+                let jl = self.jl.get(&(level, vertex));
+                let count = self.count_ingoing_jumps.get(&(level, vertex));
+                write!(w, "  q{}: jl = ", state_index(vertex))?;
+
+                match jl {
+                    Some(jl) => write!(w, "{}", jl)?,
+                    None => write!(w, "-")?,
+                }
+
+                write!(w, ", {} ingoing jumps", count.cloned().unwrap_or(0))?;
+
+                if self.nonjump_vertices.contains(&(level, vertex)) {
+                    write!(w, ", non-jumpable")?;
+                }
+
+                writeln!(w)?;
+            }
+
+            let mut rlevel: Vec<_> = self
+                .rlevel
+                .get(&level)
+                .map(|sublevels| sublevels.iter().cloned().collect())
+                .unwrap_or_default();
+            rlevel.sort_unstable();
+
+            let rlevel_str: Vec<_> = rlevel.iter().map(ToString::to_string).collect();
+            writeln!(w, "  rlevel: {{{}}}", rlevel_str.join(", "))?;
+
+            for sublevel in rlevel {
+                if let Some(reach) = self.reach.get(&(sublevel, level)) {
+                    writeln!(w, "  reach[{}, {}]:", sublevel, level)?;
+
+                    for line in format!("{:?}", reach).lines() {
+                        writeln!(w, "    {}", line)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Extend current level by reading non-jumpable edges inside the given
     /// level.
-    fn extend_level(&mut self, level: usize, nonjump_adj: &Vec<Vec<usize>>) {
+    fn extend_level(&mut self, level: usize, nonjump_adj: &[Vec<StateId>]) {
         let levelset = &mut self.levelset;
         let nonjump_vertices = &mut self.nonjump_vertices;
         let old_level = levelset.get_level(level).unwrap().clone();
 
         for source in old_level {
-            for &target in &nonjump_adj[source] {
+            for &target in &nonjump_adj[state_index(source)] {
                 levelset.register(level, target);
                 nonjump_vertices.insert((level, target));
             }
@@ -265,7 +339,7 @@ impl Jump {
 
     // Compute reach and rlevel, that is the effective jump points to all levels
     // reachable from the current level.
-    fn init_reach(&mut self, level: usize, jump_adj: &Vec<Vec<usize>>) {
+    fn init_reach(&mut self, level: usize, jump_adj: &[Vec<StateId>]) {
         let reach = &mut self.reach;
         let rlevel = &mut self.rlevel;
         let rev_rlevel = &mut self.rev_rlevel;
@@ -279,7 +353,7 @@ impl Jump {
             level,
             curr_level
                 .iter()
-                .filter_map(|&source| jl.get(&(level, source)).map(|&target| target))
+                .filter_map(|&source| jl.get(&(level, source)).copied())
                 .collect(),
         );
 
@@ -297,7 +371,7 @@ impl Jump {
         for &source in prev_level {
             let id_source = self.levelset.get_vertex_index(level - 1, source).unwrap();
 
-            for &target in &jump_adj[source] {
+            for &target in &jump_adj[state_index(source)] {
                 let id_target = self.levelset.get_vertex_index(level, target).unwrap();
                 *new_reach.at(id_source, id_target) = true;
             }
@@ -351,18 +425,26 @@ impl Jump {
     /// Remove all useless nodes inside current level. A useless node is a node
     /// from which there is no path of assignation to a node which can be jumped
     /// to.
-    pub fn clean_level(&mut self, level: usize, jump_adj: &Vec<Vec<usize>>) -> bool {
+    ///
+    /// Returns whether some nodes were removed. If an inconsistency is found in
+    /// the structure, it is returned and the level is left untouched, as if it
+    /// was never cleaned.
+    pub fn clean_level(
+        &mut self,
+        level: usize,
+        jump_adj: &[Vec<StateId>],
+    ) -> Result<bool, CleanError> {
         if self.clean_policy == CleanPolicy::Skip {
-            return false;
+            return Ok(false);
         }
 
         if level == 0 {
             // TODO: fix the reach[(0, 0)] exception
-            return false;
+            return Ok(false);
         }
 
         let curr_level = match self.levelset.get_level(level) {
-            None => return false,
+            None => return Ok(false),
             Some(vertices) => vertices,
         };
 
@@ -382,10 +464,15 @@ impl Jump {
             while let Some((source, mut path)) = heap.pop() {
                 seen.insert(source);
 
+                let count = match self.count_ingoing_jumps.get(&(level, source)) {
+                    Some(&count) => count,
+                    None => return Err(CleanError::MissingCounter(source)),
+                };
+
                 // If the path can be identified as usefull, remove it from the set of vertices
                 // to delete.
-                let usefull_path = self.count_ingoing_jumps[&(level, source)] > 0
-                    || jump_adj[source].iter().any(|&vertex| {
+                let usefull_path = count > 0
+                    || jump_adj[state_index(source)].iter().any(|&vertex| {
                         lvl_vertices.contains(&vertex) && !del_vertices.contains(&vertex)
                     });
 
@@ -397,9 +484,12 @@ impl Jump {
                     path.clear();
                 }
 
-                for target in &jump_adj[source] {
+                for target in &jump_adj[state_index(source)] {
                     if lvl_vertices.contains(target) && !seen.contains(target) {
-                        debug_assert!(del_vertices.contains(target));
+                        if !del_vertices.contains(target) {
+                            return Err(CleanError::KeptUnseen(*target));
+                        }
+
                         let mut target_path = path.to_vec();
                         target_path.push(*target);
                         heap.push((*target, target_path));
@@ -409,67 +499,28 @@ impl Jump {
         }
 
         if del_vertices.is_empty() {
-            return false;
+            return Ok(false);
         }
 
-        let removed_columns: Vec<_> = del_vertices
-            .iter()
-            .map(|&vertex| self.levelset.get_vertex_index(level, vertex).unwrap())
-            .collect();
+        // Check that the removal can be applied before updating anything, this way an
+        // inconsistency in the structure only prevents this level from being cleaned.
+        let CleanUpdate {
+            removed_columns,
+            new_rlevel,
+            removed_jumps,
+        } = self.clean_update(level, &del_vertices)?;
 
         // Update the levelset and update borrowed value
         self.levelset.remove_from_level(level, &del_vertices);
-        let default_level = Vec::new();
-        let curr_level = self.levelset.get_level(level).unwrap_or(&default_level);
 
         for &vertex in &del_vertices {
             self.jl.remove(&(level, vertex));
             self.count_ingoing_jumps.remove(&(level, vertex));
         }
 
-        // Update jump counters to sublevels, if a sublevel is removed from rlevel, then
-        // we need to remove jump pointers from any vertex of the level, overwise only
-        // from removed vertices.
-
-        let new_rlevel: HashSet<_> = curr_level
-            .iter()
-            .filter_map(|&vertex| self.jl.get(&(level, vertex)).cloned())
-            .collect();
-
-        for &sublevel in self.rlevel[&level].difference(&new_rlevel) {
-            for &vertex in self.levelset.get_level(sublevel).unwrap() {
-                let adjacency = &self.reach[&(sublevel, level)];
-                let vertex_index = self.levelset.get_vertex_index(sublevel, vertex).unwrap();
-                let nb_removed: usize = adjacency
-                    .iter_row(vertex_index)
-                    .map(|&val| if val { 1 } else { 0 })
-                    .sum();
-
-                if nb_removed > 0 {
-                    *self
-                        .count_ingoing_jumps
-                        .get_mut(&(sublevel, vertex))
-                        .unwrap() -= nb_removed;
-                }
-            }
-        }
-
-        for &sublevel in &new_rlevel {
-            for &vertex in self.levelset.get_level(sublevel).unwrap() {
-                let adjacency = &self.reach[&(sublevel, level)];
-                let vertex_index = self.levelset.get_vertex_index(sublevel, vertex).unwrap();
-                let nb_removed: usize = removed_columns
-                    .iter()
-                    .map(|&col| if adjacency[(vertex_index, col)] { 1 } else { 0 })
-                    .sum();
-
-                if nb_removed > 0 {
-                    *self
-                        .count_ingoing_jumps
-                        .get_mut(&(sublevel, vertex))
-                        .unwrap() -= nb_removed;
-                }
-            }
+        // Update jump counters to sublevels.
+        for (key, nb_removed) in removed_jumps {
+            *self.count_ingoing_jumps.get_mut(&key).unwrap() -= nb_removed;
         }
 
         if !self.levelset.has_level(level) {
@@ -495,10 +546,6 @@ impl Jump {
             self.rlevel.insert(level, new_rlevel);
 
             // Update reach
-            for &vertex in &del_vertices {
-                self.count_ingoing_jumps.remove(&(level, vertex));
-            }
-
             for &uplevel in &self.rev_rlevel[&level] {
                 self.reach.insert(
                     (level, uplevel),
@@ -516,16 +563,204 @@ impl Jump {
             }
         }
 
-        true
+        Ok(true)
+    }
+
+    /// Compute the changes implied by the removal of a set of vertices from a
+    /// level, checking that the structure is consistent for these vertices.
+    fn clean_update(
+        &self,
+        level: usize,
+        del_vertices: &HashSet<StateId>,
+    ) -> Result<CleanUpdate, CleanError> {
+        let removed_columns = del_vertices
+            .iter()
+            .map(|&vertex| {
+                self.levelset
+                    .get_vertex_index(level, vertex)
+                    .ok_or(CleanError::MissingVertex(level, vertex))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let new_rlevel: HashSet<_> = self
+            .levelset
+            .get_level(level)
+            .into_iter()
+            .flatten()
+            .filter(|vertex| !del_vertices.contains(vertex))
+            .filter_map(|&vertex| self.jl.get(&(level, vertex)).cloned())
+            .collect();
+
+        let rlevel = self.rlevel.get(&level).ok_or(CleanError::MissingLevel)?;
+        let rev_rlevel = self.rev_rlevel.get(&level).ok_or(CleanError::MissingLevel)?;
+
+        if let Some(&uplevel) = rev_rlevel
+            .iter()
+            .find(|&&uplevel| !self.reach.contains_key(&(level, uplevel)))
+        {
+            return Err(CleanError::MissingReach {
+                from: level,
+                to:   uplevel,
+            });
+        }
+
+        // If a sublevel is removed from rlevel, then we need to remove jump pointers
+        // from any vertex of the level, overwise only from removed vertices.
+        let mut removed_jumps = Vec::new();
+
+        for &sublevel in rlevel {
+            let adjacency = self
+                .reach
+                .get(&(sublevel, level))
+                .filter(|adjacency| {
+                    let width = adjacency.get_width();
+                    removed_columns.iter().all(|&col| col < width)
+                })
+                .ok_or(CleanError::MissingReach {
+                    from: sublevel,
+                    to:   level,
+                })?;
+
+            for &vertex in self.levelset.get_level(sublevel).into_iter().flatten() {
+                let vertex_index = self
+                    .levelset
+                    .get_vertex_index(sublevel, vertex)
+                    .filter(|&index| index < adjacency.get_height())
+                    .ok_or(CleanError::MissingVertex(sublevel, vertex))?;
+
+                let nb_removed = if new_rlevel.contains(&sublevel) {
+                    removed_columns
+                        .iter()
+                        .filter(|&&col| adjacency[(vertex_index, col)])
+                        .count()
+                } else {
+                    adjacency.iter_row(vertex_index).filter(|&&val| val).count()
+                };
+
+                if nb_removed == 0 {
+                    continue;
+                }
+
+                match self.count_ingoing_jumps.get(&(sublevel, vertex)) {
+                    Some(&count) if count >= nb_removed => {
+                        removed_jumps.push(((sublevel, vertex), nb_removed))
+                    }
+                    _ => return Err(CleanError::WrongCounter(sublevel, vertex)),
+                }
+            }
+        }
+
+        Ok(CleanUpdate {
+            removed_columns,
+            new_rlevel,
+            removed_jumps,
+        })
     }
 }
 
-impl fmt::Debug for Jump {
+/// Changes to apply to a `Jump` when removing vertices from a level.
+struct CleanUpdate {
+    /// Indices of removed vertices inside of the level.
+    removed_columns: Vec<usize>,
+    /// Levels still accessible from the level using `jl`.
+    new_rlevel:      HashSet<usize>,
+    /// Number of jumps removed towards vertices of sublevels.
+    removed_jumps:   Vec<((usize, StateId), usize)>,
+}
+
+/// Inconsistency found in a `Jump` while cleaning a level.
+#[derive(Debug, Eq, PartialEq)]
+pub enum CleanError {
+    /// A vertex of the level has no jump counter.
+    MissingCounter(StateId),
+    /// A vertex was marked as usefull before being explored.
+    KeptUnseen(StateId),
+    /// A vertex is not indexed in a level.
+    MissingVertex(usize, StateId),
+    /// The level is missing from `rlevel` or `rev_rlevel`.
+    MissingLevel,
+    /// The accessibility matrix `reach[(from, to)]` is missing or too small.
+    MissingReach { from: usize, to: usize },
+    /// The jump counter of a vertex is missing or lower than the number of
+    /// jumps to remove.
+    WrongCounter(usize, StateId),
+}
+
+impl fmt::Display for CleanError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for ((level, vertex), count) in self.count_ingoing_jumps.iter() {
-            write!(f, "{} at level {}: {} ingoing jumps", vertex, level, count)?;
+        match self {
+            CleanError::MissingCounter(vertex) => write!(f, "no jump counter for {}", vertex),
+            CleanError::KeptUnseen(vertex) => {
+                write!(f, "{} was kept before being explored", vertex)
+            }
+            CleanError::MissingVertex(level, vertex) => {
+                write!(f, "{} is not indexed at level {}", vertex, level)
+            }
+            CleanError::MissingLevel => write!(f, "accessible levels are not indexed"),
+            CleanError::MissingReach { from, to } => write!(
+                f,
+                "invalid accessibility matrix from level {} to level {}",
+                from, to
+            ),
+            CleanError::WrongCounter(level, vertex) => write!(
+                f,
+                "invalid jump counter for {} at level {}",
+                vertex, level
+            ),
         }
+    }
+}
 
-        Ok(())
+impl error::Error for CleanError {}
+
+impl fmt::Debug for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buf = Vec::new();
+        self.dump_trace(&mut buf).map_err(|_| fmt::Error)?;
+        write!(f, "{}", String::from_utf8_lossy(&buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a jump function over three levels, state `1` can be reached from
+    /// state `0` with an assignation, and state `2` is a dead end only
+    /// reachable from state `1`.
+    fn three_levels(nonjump_adj: &[Vec<StateId>]) -> Jump {
+        let jump_adj = vec![vec![0], vec![2], vec![]];
+        let mut jump = Jump::new(iter::once(0), nonjump_adj);
+        jump.init_next_level(&jump_adj, nonjump_adj);
+        jump.init_next_level(&jump_adj, nonjump_adj);
+        jump
+    }
+
+    #[test]
+    fn clean_level() {
+        let nonjump_adj = vec![vec![1], vec![], vec![]];
+        let mut jump = three_levels(&nonjump_adj);
+        assert_eq!(jump.level_sizes(), vec![(0, 2), (1, 3), (2, 3)]);
+
+        // State `2` in level 1 can't reach the next level
+        assert_eq!(jump.clean_level(1, &nonjump_adj), Ok(true));
+        assert_eq!(jump.level_sizes(), vec![(0, 2), (1, 2), (2, 3)]);
+        assert_eq!(jump.count_ingoing_jumps[&(0, 1)], 0);
+    }
+
+    #[test]
+    fn inconsistent_clean_level() {
+        let nonjump_adj = vec![vec![1], vec![], vec![]];
+        let mut jump = three_levels(&nonjump_adj);
+
+        // Removing state `2` from level 1 removes a jump to state `1` in level 0
+        jump.count_ingoing_jumps.insert((0, 1), 0);
+        assert_eq!(jump.clean_level(1, &nonjump_adj), Err(CleanError::WrongCounter(0, 1)));
+        assert_eq!(jump.level_sizes(), vec![(0, 2), (1, 3), (2, 3)]);
+        assert_eq!(jump.count_ingoing_jumps[&(0, 1)], 0);
+
+        jump.count_ingoing_jumps.remove(&(1, 0));
+        assert_eq!(jump.clean_level(1, &nonjump_adj), Err(CleanError::MissingCounter(0)));
+        assert_eq!(jump.level_sizes(), vec![(0, 2), (1, 3), (2, 3)]);
     }
 }
@@ -1,6 +1,8 @@
 use std::collections::{HashMap, HashSet};
 use std::iter;
 
+use super::super::automaton::StateId;
+
 /// Represent the partitioning into levels of a product graph.
 ///
 /// A same vertex can be store in several levels, and this level hierarchy can
@@ -8,13 +10,13 @@ use std::iter;
 #[derive(Debug)]
 pub struct LevelSet {
     /// Index level contents: `level id` -> `vertex id's list`.
-    levels: HashMap<usize, Vec<usize>>,
+    levels: HashMap<usize, Vec<StateId>>,
 
     /// Index the id of a vertex iner to a level:
     ///     `(level id, vertex id)` -> `vertex position`.
     /// It can also be used to check if a pair `(level, vertex)` is already
     /// represented in the structure.
-    vertex_index: HashMap<(usize, usize), usize>,
+    vertex_index: HashMap<(usize, StateId), usize>,
 }
 
 impl LevelSet {
@@ -29,7 +31,7 @@ impl LevelSet {
         self.levels.contains_key(&level)
     }
 
-    pub fn get_level(&self, level: usize) -> Option<&Vec<usize>> {
+    pub fn get_level(&self, level: usize) -> Option<&Vec<StateId>> {
         self.levels.get(&level)
     }
 
@@ -37,12 +39,12 @@ impl LevelSet {
         self.levels.len()
     }
 
-    pub fn get_vertex_index(&self, level: usize, vertex: usize) -> Option<usize> {
+    pub fn get_vertex_index(&self, level: usize, vertex: StateId) -> Option<usize> {
         self.vertex_index.get(&(level, vertex)).cloned()
     }
 
     /// Iterate over pairs (vertex, vertex_index) of a level
-    pub fn iter_level<'a>(&'a self, level: usize) -> impl Iterator<Item = (usize, usize)> + 'a {
+    pub fn iter_level<'a>(&'a self, level: usize) -> impl Iterator<Item = (StateId, usize)> + 'a {
         let vertices = self.levels[&level].iter();
         let levels = iter::repeat(level);
 
@@ -53,14 +55,14 @@ impl LevelSet {
 
     /// Save a vertex in a level, the vertex need to be unique inside this level
     /// but can be registered in other levels.
-    pub fn register(&mut self, level: usize, vertex: usize) {
+    pub fn register(&mut self, level: usize, vertex: StateId) {
         let levels = &mut self.levels;
         let vertex_index = &mut self.vertex_index;
 
         // Insert the vertex in self.level, and return its index
         let insert_in_level = || {
             // Create the level if necessary
-            let level = levels.entry(level).or_insert(Vec::new());
+            let level = levels.entry(level).or_default();
             // Add the vertex to the level, and return its index
             level.push(vertex);
             level.len() - 1
@@ -74,7 +76,7 @@ impl LevelSet {
 
     /// Remove a set of vertices from a level, if the level is left empty, it is
     /// then removed.
-    pub fn remove_from_level(&mut self, level: usize, del_vertices: &HashSet<usize>) {
+    pub fn remove_from_level(&mut self, level: usize, del_vertices: &HashSet<StateId>) {
         // TODO: in place deletion (may be doable with unsafe rust?)
         let mut new_level = Vec::new();
         let old_level = &self.levels[&level];
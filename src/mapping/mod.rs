@@ -5,13 +5,15 @@ mod jump;
 mod levelset;
 
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::error;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::ops::Range;
-use std::rc::Rc;
+use std::sync::Arc;
 
-pub use indexed_dag::IndexedDag;
+pub use indexed_dag::{BytesIndexedDag, IndexedDag, OwnedIndexedDag};
+pub use jump::CleanError;
 
 //  __  __                   _
 // |  \/  | __ _ _ __  _ __ (_)_ __   __ _
@@ -21,34 +23,271 @@ pub use indexed_dag::IndexedDag;
 //              |_|   |_|            |___/
 
 /// Map a set of variables to spans [i, i'> over a text.
-#[derive(Clone, Debug, Eq, PartialEq)]
+///
+/// A mapping may also know the variables declared by the pattern it comes
+/// from, which allows to tell apart groups that were left unassigned. Two
+/// mappings are equal if they assign the same spans, whatever they declare.
+#[derive(Clone, Debug)]
 pub struct Mapping<'t> {
-    text: &'t str,
-    maps: HashMap<Variable, Range<usize>>,
+    text:     &'t str,
+    maps:     HashMap<Variable, Range<usize>>,
+    declared: Option<Arc<[Variable]>>,
 }
 
 impl<'t> Mapping<'t> {
-    /// Returns a span that contains the whole matching area
-    pub fn main_span(&self) -> Option<Range<usize>> {
-        self.maps.values().fold(None, |acc, range| match acc {
+    /// Returns a span that contains the whole matching area. A mapping that
+    /// doesn't assign any group, which can only come from a pattern without
+    /// the implicit group *match*, spans the whole text.
+    pub fn main_span(&self) -> Range<usize> {
+        let span = self.maps.values().fold(None, |acc, range| match acc {
             None => Some(range.clone()),
             Some(acc_range) => Some(Range {
                 start: cmp::min(range.start, acc_range.start),
                 end:   cmp::max(range.end, acc_range.end),
             }),
-        })
+        });
+
+        span.unwrap_or(0..self.text.len())
+    }
+
+    /// Check if the mapping doesn't assign any group.
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
     }
 
+    /// Iterate over assigned groups and their spans, sorted by variable id.
     pub fn iter_groups(&self) -> impl Iterator<Item = (&str, Range<usize>)> {
-        self.maps
-            .iter()
+        self.sorted_maps()
             .map(|(key, range)| (key.get_name(), range.clone()))
     }
 
+    /// Iterate over assigned groups and their texts, sorted by variable id.
     pub fn iter_groups_text(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.sorted_maps()
+            .map(move |(key, range)| (key.get_name(), &self.text[range.clone()]))
+    }
+
+    /// Iterate over all declared groups, with their span if they are
+    /// assigned, sorted by variable id.
+    ///
+    /// If declared variables are unknown, see
+    /// `Mapping::with_declared_variables`, only assigned groups are listed.
+    pub fn iter_declared_groups(&self) -> impl Iterator<Item = (&str, Option<Range<usize>>)> {
+        let variables: Vec<&Variable> = match &self.declared {
+            Some(declared) => declared.iter().collect(),
+            None => {
+                let mut assigned: Vec<_> = self.maps.keys().collect();
+                assigned.sort();
+                assigned
+            }
+        };
+
+        variables
+            .into_iter()
+            .map(move |var| (var.get_name(), self.maps.get(var).cloned()))
+    }
+
+    /// Check if a group is declared but not assigned by this mapping.
+    pub fn is_unset(&self, name: &str) -> bool {
+        self.get_group(name).is_none()
+            && self
+                .declared
+                .iter()
+                .flat_map(|declared| declared.iter())
+                .any(|var| var.get_name() == name)
+    }
+
+    /// Get the text assigned to a group, if it is assigned.
+    pub fn group_text(&self, name: &str) -> Option<&'t str> {
+        self.get_group(name).map(|range| &self.text[range.clone()])
+    }
+
+    /// Iterate over groups with their spans given as indices of unicode
+    /// characters in the text, instead of bytes.
+    ///
+    /// Offsets are computed by counting the characters preceding each group,
+    /// which takes time linear in its position.
+    pub fn iter_groups_chars(&self) -> impl Iterator<Item = (&str, Range<usize>)> {
+        self.sorted_maps().map(move |(key, range)| {
+            let start = self.text[..range.start].chars().count();
+            let end = start + self.text[range.clone()].chars().count();
+            (key.get_name(), start..end)
+        })
+    }
+
+    /// Check if two groups of the mapping share at least one position, thus
+    /// groups that are only touching don't overlap, and neither does an empty
+    /// group. Returns `None` if one of the groups is not assigned.
+    pub fn overlaps(&self, a: &str, b: &str) -> Option<bool> {
+        let range_a = self.get_group(a)?;
+        let range_b = self.get_group(b)?;
+        Some(ranges_overlap(range_a, range_b))
+    }
+
+    /// Check if any two groups of the mapping overlap, as defined in
+    /// `Mapping::overlaps`. The group *match* is ignored as it is meant to
+    /// contain the whole match.
+    pub fn has_overlapping_groups(&self) -> bool {
+        let ranges: Vec<_> = self
+            .maps
+            .iter()
+            .filter(|(var, _)| var.get_name() != "match")
+            .map(|(_, range)| range)
+            .collect();
+
+        ranges.iter().enumerate().any(|(i, range_a)| {
+            ranges[i + 1..]
+                .iter()
+                .any(|range_b| ranges_overlap(range_a, range_b))
+        })
+    }
+
+    /// Arrange assigned groups into a tree following their nesting.
+    ///
+    /// The parent of a group is the closest group containing it in the
+    /// pattern, as given by `Variable::get_parent`, if it is assigned. Groups
+    /// without a known parent are attached to the smallest other group whose
+    /// span contains theirs. The root of the tree is the group *match* or, if
+    /// it is not assigned, an unnamed node spanning the whole match.
+    pub fn group_tree(&self) -> GroupNode {
+        /// Build the nodes whose parent is `node`, sorted by position.
+        fn children(
+            groups: &[(&Variable, &Range<usize>)],
+            parents: &[Option<usize>],
+            root: Option<usize>,
+            node: Option<usize>,
+        ) -> Vec<GroupNode> {
+            let mut nodes: Vec<_> = (0..groups.len())
+                .filter(|&i| Some(i) != root && parents[i] == node)
+                .map(|i| GroupNode {
+                    name:     groups[i].0.get_name().to_string(),
+                    span:     groups[i].1.clone(),
+                    children: children(groups, parents, root, Some(i)),
+                })
+                .collect();
+
+            nodes.sort_by_key(|node| node.span.start);
+            nodes
+        }
+
+        let groups: Vec<_> = self.sorted_maps().collect();
+        let root = groups.iter().position(|(var, _)| var.get_name() == "match");
+        let contains = |a: &Range<usize>, b: &Range<usize>| a.start <= b.start && b.end <= a.end;
+
+        // Groups are ordered by length and then by id, a group without known
+        // parent can only be attached to a greater one, which avoids cycles.
+        let key = |i: usize| (groups[i].1.len(), groups[i].0.get_id());
+
+        let parents: Vec<_> = (0..groups.len())
+            .map(|i| {
+                let (var, range) = groups[i];
+
+                if let Some(parent) = var.get_parent() {
+                    if let Some(j) = groups.iter().position(|(var, _)| var.get_id() == parent) {
+                        return Some(j).filter(|&j| Some(j) != root);
+                    }
+                }
+
+                (0..groups.len())
+                    .filter(|&j| Some(j) != root && key(j) > key(i))
+                    .filter(|&j| contains(groups[j].1, range))
+                    .min_by_key(|&j| key(j))
+            })
+            .collect();
+
+        GroupNode {
+            name:     root.map(|i| groups[i].0.get_name().to_string()).unwrap_or_default(),
+            span:     root
+                .map(|i| groups[i].1.clone())
+                .unwrap_or_else(|| self.main_span()),
+            children: children(&groups, &parents, root, None),
+        }
+    }
+
+    /// Add `delta` to the bounds of every span, which converts offsets in a
+    /// chunk of a text into offsets in the whole text when the chunk starts at
+    /// offset `delta`.
+    ///
+    /// The mapping still refers to the text it was built from, thus methods
+    /// reading the text of groups, such as `Mapping::group_text`, are wrong or
+    /// panic until it is attached to the whole text with `Mapping::rebase`.
+    pub fn shift(&mut self, delta: usize) {
+        for range in self.maps.values_mut() {
+            range.start += delta;
+            range.end += delta;
+        }
+    }
+
+    /// Consume the mapping and return it shifted, see `Mapping::shift`.
+    pub fn shifted(mut self, delta: usize) -> Mapping<'t> {
+        self.shift(delta);
+        self
+    }
+
+    /// Shift the mapping and attach it to a text containing the one it was
+    /// built from at offset `delta`, which keeps texts of groups unchanged.
+    pub fn rebase<'u>(self, text: &'u str, delta: usize) -> Mapping<'u> {
+        debug_assert_eq!(
+            text.get(delta..delta + self.text.len()),
+            Some(self.text),
+            "The mapping is rebased to a text that doesn't contain its own text"
+        );
+
+        Mapping {
+            text,
+            maps: self.maps,
+            declared: self.declared,
+        }
+        .shifted(delta)
+    }
+
+    /// Check if two mappings assign the same spans to their groups, ignoring
+    /// the group *match*: mappings capturing the same groups within different
+    /// matches are thus equal for this comparison.
+    pub fn eq_captures(&self, other: &Mapping) -> bool {
+        self.text == other.text && self.captures().eq(other.captures())
+    }
+
+    /// Feed the assignations of the mapping, except for the group *match*, into
+    /// a hasher, which is consistent with `Mapping::eq_captures`.
+    pub fn captures_hash<H: Hasher>(&self, state: &mut H) {
+        self.text.hash(state);
+
+        for assignment in self.captures() {
+            assignment.hash(state);
+        }
+    }
+
+    /// Iterate over assignments sorted by variable, without the group *match*.
+    fn captures(&self) -> impl Iterator<Item = (&Variable, &Range<usize>)> {
+        self.sorted_maps().filter(|(var, _)| var.get_name() != "match")
+    }
+
+    /// Iterate over assignments sorted by variable, which gives a stable
+    /// order, unlike the underlying `HashMap`.
+    fn sorted_maps(&self) -> impl Iterator<Item = (&Variable, &Range<usize>)> {
+        let mut assignments: Vec<_> = self.maps.iter().collect();
+        assignments.sort_by_key(|&(var, _)| var);
+        assignments.into_iter()
+    }
+
+    fn get_group(&self, name: &str) -> Option<&Range<usize>> {
         self.maps
             .iter()
-            .map(move |(key, range)| (key.get_name(), &self.text[range.clone()]))
+            .find(|(var, _)| var.get_name() == name)
+            .map(|(_, range)| range)
+    }
+
+    /// Attach the list of variables declared by the pattern this mapping comes
+    /// from, such as given by `Automaton::variables`.
+    pub fn with_declared_variables(mut self, declared: Arc<[Variable]>) -> Mapping<'t> {
+        debug_assert!(
+            self.maps.keys().all(|var| declared.contains(var)),
+            "A mapping assigns variables that are not declared"
+        );
+
+        self.declared = Some(declared);
+        self
     }
 
     /// Return a canonical mapping for a classic semantic with no group, which
@@ -56,10 +295,32 @@ impl<'t> Mapping<'t> {
     pub fn from_single_match(text: &'t str, range: Range<usize>) -> Mapping<'t> {
         let mut maps = HashMap::new();
         maps.insert(Variable::new("match".to_string(), 0), range);
-        Mapping { text, maps }
+
+        Mapping {
+            text,
+            maps,
+            declared: None,
+        }
     }
 
+    /// Build a mapping from the positions where markers are assigned, see
+    /// `Mapping::try_from_markers`.
+    ///
+    /// Panics if a marker is assigned twice or if a variable is not assigned a
+    /// valid span.
     pub fn from_markers<T>(text: &'t str, marker_assigns: T) -> Mapping<'t>
+    where
+        T: Iterator<Item = (Marker, usize)>,
+    {
+        Mapping::try_from_markers(text, marker_assigns).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Build a mapping from the positions where markers are assigned, each
+    /// variable must be opened and closed exactly once, in that order.
+    pub fn try_from_markers<T>(
+        text: &'t str,
+        marker_assigns: T,
+    ) -> Result<Mapping<'t>, MappingError>
     where
         T: Iterator<Item = (Marker, usize)>,
     {
@@ -71,21 +332,22 @@ impl<'t> Mapping<'t> {
                 Some(x) => *x,
             };
 
+            let old_pos = match marker {
+                Marker::Open(_) => span.0,
+                Marker::Close(_) => span.1,
+            };
+
+            if let Some(old_pos) = old_pos {
+                return Err(MappingError::DoubleAssign {
+                    marker,
+                    pos,
+                    old_pos,
+                });
+            }
+
             let span = match marker {
-                Marker::Open(_) => match span.0 {
-                    None => (Some(pos), span.1),
-                    Some(old_pos) => panic!(
-                        "Can't assign {} at position {}, already assigned to {}",
-                        marker, pos, old_pos
-                    ),
-                },
-                Marker::Close(_) => match span.1 {
-                    None => (span.0, Some(pos)),
-                    Some(old_pos) => panic!(
-                        "Can't assign {} at position {}, already assigned to {}",
-                        marker, pos, old_pos
-                    ),
-                },
+                Marker::Open(_) => (Some(pos), span.1),
+                Marker::Close(_) => (span.0, Some(pos)),
             };
 
             dict.insert(marker.variable().clone(), span);
@@ -94,15 +356,244 @@ impl<'t> Mapping<'t> {
         let maps = dict
             .into_iter()
             .map(|(key, span)| match span {
-                (Some(i), Some(j)) if i <= j => (key, i..j),
-                _ => panic!("Invalid mapping ordering"),
+                (Some(i), Some(j)) if i <= j => Ok((key, i..j)),
+                (start, end) => Err(MappingError::InvalidOrdering {
+                    variable: key,
+                    start,
+                    end,
+                }),
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
+
+        Ok(Mapping {
+            text,
+            maps,
+            declared: None,
+        })
+    }
+}
+
+/// A group assigned by a mapping, with the groups nested inside of it, see
+/// `Mapping::group_tree`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GroupNode {
+    pub name:     String,
+    pub span:     Range<usize>,
+    pub children: Vec<GroupNode>,
+}
+
+/// Error occuring while building a mapping from ill-formed marker assignations.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MappingError {
+    /// A marker was assigned at two positions.
+    DoubleAssign {
+        marker:  Marker,
+        pos:     usize,
+        old_pos: usize,
+    },
+    /// A variable was not both opened and closed, or was closed before being
+    /// opened.
+    InvalidOrdering {
+        variable: Variable,
+        start:    Option<usize>,
+        end:      Option<usize>,
+    },
+}
 
-        Mapping { text, maps }
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MappingError::DoubleAssign {
+                marker,
+                pos,
+                old_pos,
+            } => write!(
+                f,
+                "can't assign {} at position {}, already assigned to {}",
+                marker, pos, old_pos
+            ),
+            MappingError::InvalidOrdering {
+                variable,
+                start,
+                end,
+            } => write!(
+                f,
+                "invalid mapping ordering for {}: opened at {:?} and closed at {:?}",
+                variable, start, end
+            ),
+        }
     }
 }
 
+impl error::Error for MappingError {}
+
+/// Borrowed view over the assignations of a match, see
+/// `IndexedDag::for_each_match`.
+///
+/// Spans are read from the list of assignations each time they are requested,
+/// which takes time linear in the number of groups.
+#[derive(Clone, Copy, Debug)]
+pub struct MappingView<'a, 't> {
+    text:         &'t str,
+    /// Markers with the level where they are assigned.
+    markers:      &'a [(&'a Marker, usize)],
+    /// Position in the text of each level.
+    char_offsets: &'a [usize],
+}
+
+impl<'a, 't> MappingView<'a, 't> {
+    fn new(
+        text: &'t str,
+        markers: &'a [(&'a Marker, usize)],
+        char_offsets: &'a [usize],
+    ) -> MappingView<'a, 't> {
+        MappingView {
+            text,
+            markers,
+            char_offsets,
+        }
+    }
+
+    /// Returns a span that contains the whole matching area, or the whole
+    /// text if no group is assigned, as `Mapping::main_span` does.
+    pub fn main_span(&self) -> Range<usize> {
+        let start = self.markers.iter().map(|&(_, level)| level).min();
+        let end = self.markers.iter().map(|&(_, level)| level).max();
+
+        match (start, end) {
+            (Some(start), Some(end)) => self.char_offsets[start]..self.char_offsets[end],
+            _ => 0..*self.char_offsets.last().unwrap(),
+        }
+    }
+
+    /// Get the span assigned to a group, if it is assigned.
+    pub fn get_group(&self, name: &str) -> Option<Range<usize>> {
+        let open = self.find(name, |marker| matches!(marker, Marker::Open(_)))?;
+        let close = self.find(name, |marker| matches!(marker, Marker::Close(_)))?;
+        Some(open..close)
+    }
+
+    /// Get the text assigned to a group, if it is assigned.
+    pub fn group_text(&self, name: &str) -> Option<&'t str> {
+        self.get_group(name).map(|range| &self.text[range])
+    }
+
+    /// Iterate over assigned groups and their spans, in no specific order.
+    pub fn iter_groups(&self) -> impl Iterator<Item = (&'a str, Range<usize>)> + '_ {
+        self.markers.iter().filter_map(move |&(marker, _)| match marker {
+            Marker::Open(var) => {
+                let span = self.get_group(var.get_name())?;
+                Some((var.get_name(), span))
+            }
+            Marker::Close(_) => None,
+        })
+    }
+
+    /// Position of the first marker of a group satisfying a predicate.
+    fn find<P>(&self, name: &str, predicate: P) -> Option<usize>
+    where
+        P: Fn(&Marker) -> bool,
+    {
+        self.markers
+            .iter()
+            .find(|(marker, _)| predicate(marker) && marker.variable().get_name() == name)
+            .map(|&(_, level)| self.char_offsets[level])
+    }
+}
+
+/// A mapping over a slice of bytes, see `IndexedDag::compile_bytes`.
+///
+/// Spans are given in bytes, as for a `Mapping` over a text.
+#[derive(Clone, Debug)]
+pub struct BytesMapping<'t> {
+    bytes:   &'t [u8],
+    /// Mapping with the same spans, attached to an empty text.
+    mapping: Mapping<'static>,
+}
+
+impl<'t> BytesMapping<'t> {
+    fn new(bytes: &'t [u8], mapping: Mapping<'static>) -> BytesMapping<'t> {
+        BytesMapping { bytes, mapping }
+    }
+
+    /// Returns a span that contains the whole matching area, or all the bytes
+    /// if no group is assigned, as `Mapping::main_span` does.
+    pub fn main_span(&self) -> Range<usize> {
+        match self.mapping.is_empty() {
+            true => 0..self.bytes.len(),
+            false => self.mapping.main_span(),
+        }
+    }
+
+    /// Check if the mapping doesn't assign any group.
+    pub fn is_empty(&self) -> bool {
+        self.mapping.is_empty()
+    }
+
+    /// Iterate over assigned groups and their spans, sorted by variable id.
+    pub fn iter_groups(&self) -> impl Iterator<Item = (&str, Range<usize>)> {
+        self.mapping.iter_groups()
+    }
+
+    /// Iterate over assigned groups and their bytes, sorted by variable id.
+    pub fn iter_groups_bytes(&self) -> impl Iterator<Item = (&str, &'t [u8])> {
+        let bytes = self.bytes;
+        self.iter_groups().map(move |(name, range)| (name, &bytes[range]))
+    }
+
+    /// Get the bytes assigned to a group, if it is assigned.
+    pub fn group_bytes(&self, name: &str) -> Option<&'t [u8]> {
+        let range = self.mapping.get_group(name)?;
+        Some(&self.bytes[range.clone()])
+    }
+}
+
+fn ranges_overlap(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end && !a.is_empty() && !b.is_empty()
+}
+
+/// Keep only the main span of each mapping, skipping spans that were already
+/// yielded.
+pub fn distinct_spans<T>(mappings: T) -> DistinctSpans<T> {
+    DistinctSpans {
+        mappings,
+        seen: HashSet::new(),
+    }
+}
+
+/// Iterator over distinct main spans of mappings, see `distinct_spans`.
+pub struct DistinctSpans<T> {
+    mappings: T,
+    seen:     HashSet<Range<usize>>,
+}
+
+impl<'t, T> Iterator for DistinctSpans<T>
+where
+    T: Iterator<Item = Mapping<'t>>,
+{
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        for mapping in &mut self.mappings {
+            let span = mapping.main_span();
+
+            if self.seen.insert(span.clone()) {
+                return Some(span);
+            }
+        }
+
+        None
+    }
+}
+
+impl<'t> PartialEq for Mapping<'t> {
+    fn eq(&self, other: &Mapping<'t>) -> bool {
+        self.text == other.text && self.maps == other.maps
+    }
+}
+
+impl<'t> Eq for Mapping<'t> {}
+
 impl<'t> std::hash::Hash for Mapping<'t> {
     fn hash<'m, H: Hasher>(&'m self, state: &mut H) {
         self.text.hash(state);
@@ -119,9 +610,34 @@ impl<'t> std::hash::Hash for Mapping<'t> {
     }
 }
 
+/// Mappings are ordered by their main span, then by their assignations.
+impl<'t> Ord for Mapping<'t> {
+    fn cmp(&self, other: &Mapping<'t>) -> cmp::Ordering {
+        let key = |mapping: &Mapping<'t>| {
+            let mut assignments: Vec<_> = mapping
+                .maps
+                .iter()
+                .map(|(var, range)| (var.clone(), range.start, range.end))
+                .collect();
+            assignments.sort();
+
+            let span = mapping.main_span();
+            ((span.start, span.end), assignments)
+        };
+
+        key(self).cmp(&key(other))
+    }
+}
+
+impl<'t> PartialOrd for Mapping<'t> {
+    fn partial_cmp(&self, other: &Mapping<'t>) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl<'t> fmt::Display for Mapping<'t> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for (var, range) in self.maps.iter() {
+        for (var, range) in self.sorted_maps() {
             // write!(f, "{}: {} ", var, &self.text[*start..*end]).unwrap();
             write!(f, "{}: ({}, {}) ", var, range.start, range.end)?;
         }
@@ -137,20 +653,59 @@ impl<'t> fmt::Display for Mapping<'t> {
 //    \_/ \__,_|_|  |_|\__,_|_.__/|_|\___|
 //
 
-#[derive(Clone, Debug, PartialOrd, Ord)]
+/// A named group of a pattern.
+///
+/// Variables are ordered by their capture index if they have one, which is the
+/// position of the group in the pattern, and then by id.
+#[derive(Clone, Debug)]
 pub struct Variable {
-    id:   u64,
-    name: String,
+    id:     u64,
+    name:   String,
+    /// Id of the closest group containing this one in the pattern, if known.
+    parent: Option<u64>,
+    /// Index of the group among capture groups of the pattern, if known.
+    index:  Option<u32>,
 }
 
 impl Variable {
     pub fn new(name: String, id: u64) -> Variable {
-        Variable { id, name }
+        Variable {
+            id,
+            name,
+            parent: None,
+            index: None,
+        }
+    }
+
+    /// Set the capture index of the group, as numbered by usual regex
+    /// engines: groups are numbered by the position of their opening
+    /// parenthesis, starting from 1.
+    pub fn with_index(mut self, index: u32) -> Variable {
+        self.index = Some(index);
+        self
+    }
+
+    /// Set the id of the group containing this one in the pattern.
+    pub fn with_parent(mut self, parent: u64) -> Variable {
+        self.parent = Some(parent);
+        self
     }
 
     pub fn get_name(&self) -> &str {
         &self.name
     }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_parent(&self) -> Option<u64> {
+        self.parent
+    }
+
+    pub fn get_index(&self) -> Option<u32> {
+        self.index
+    }
 }
 
 impl Hash for Variable {
@@ -166,6 +721,18 @@ impl PartialEq for Variable {
     }
 }
 
+impl Ord for Variable {
+    fn cmp(&self, other: &Variable) -> cmp::Ordering {
+        (self.index, self.id).cmp(&(other.index, other.id))
+    }
+}
+
+impl PartialOrd for Variable {
+    fn partial_cmp(&self, other: &Variable) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl fmt::Display for Variable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.name)
@@ -180,8 +747,8 @@ impl fmt::Display for Variable {
 //
 #[derive(Clone, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub enum Marker {
-    Open(Rc<Variable>),
-    Close(Rc<Variable>),
+    Open(Arc<Variable>),
+    Close(Arc<Variable>),
 }
 
 impl Marker {
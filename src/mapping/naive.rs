@@ -4,10 +4,16 @@ use std::str::CharIndices;
 use super::super::automaton::{Automaton, Label};
 use super::{Mapping, Marker};
 
+/// A run on the automaton: the current state, the current index on the word and
+/// assignations that have been done so far.
+type NaiveRun<'a, 't> = (usize, CharIndices<'t>, Vec<(&'a Marker, usize)>);
+
 /// Enumerate all the matches of a variable automata over a text.
 ///
-/// ** For this naive implementation, there is no garantee that produced matches
-/// are distincts. **
+/// Distinct runs of the automaton may produce the same mapping, produced
+/// mappings are thus kept in a set to output each of them only once. This
+/// requires memory linear in the number of matches, which can be avoided with
+/// `NaiveEnum::new_allow_duplicates`.
 pub struct NaiveEnum<'a, 't> {
     automaton: &'a Automaton,
     text:      &'t str,
@@ -16,19 +22,37 @@ pub struct NaiveEnum<'a, 't> {
     ///  - current state on the automata
     ///  - current index on the word
     ///  - assignations that have been done so far
-    curr_state: Vec<(usize, CharIndices<'t>, Vec<(&'a Marker, usize)>)>,
+    curr_state: Vec<NaiveRun<'a, 't>>,
+
+    /// Keep track of already outputed values, if duplicates are not allowed
+    curr_output: Option<HashSet<Mapping<'t>>>,
 
-    /// Keep track of already outputed values
-    curr_output: HashSet<Mapping<'t>>,
+    /// In tests, all outputed values are also compared to each new mapping,
+    /// which takes quadratic time, to check that the set doesn't miss a
+    /// duplicate.
+    #[cfg(test)]
+    all_outputs: Vec<Mapping<'t>>,
 }
 
 impl<'a, 't> NaiveEnum<'a, 't> {
     pub fn new(automaton: &'a Automaton, text: &'t str) -> NaiveEnum<'a, 't> {
+        NaiveEnum {
+            curr_output: Some(HashSet::new()),
+            ..NaiveEnum::new_allow_duplicates(automaton, text)
+        }
+    }
+
+    /// Enumerate a mapping for each accepting run of the automaton, without
+    /// keeping track of produced mappings: a mapping is output as many times
+    /// as there are runs producing it.
+    pub fn new_allow_duplicates(automaton: &'a Automaton, text: &'t str) -> NaiveEnum<'a, 't> {
         NaiveEnum {
             automaton,
             text,
             curr_state: vec![(0, text.char_indices(), Vec::new())],
-            curr_output: HashSet::new(),
+            curr_output: None,
+            #[cfg(test)]
+            all_outputs: Vec::new(),
         }
     }
 }
@@ -42,7 +66,7 @@ impl<'a, 't> Iterator for NaiveEnum<'a, 't> {
 
             for (label, target) in &self.automaton.get_adj()[state] {
                 match **label {
-                    Label::Atom(ref atom) if curr_char != None => {
+                    Label::Atom(ref atom) if curr_char.is_some() => {
                         if let Some((_, curr_char)) = curr_char {
                             if !atom.is_match(&curr_char) {
                                 continue;
@@ -66,7 +90,7 @@ impl<'a, 't> Iterator for NaiveEnum<'a, 't> {
                 }
             }
 
-            if curr_char == None && self.automaton.finals.contains(&state) {
+            if curr_char.is_none() && self.automaton.finals.contains(&state) {
                 let mapping = Mapping::from_markers(
                     self.text,
                     assigns
@@ -74,8 +98,23 @@ impl<'a, 't> Iterator for NaiveEnum<'a, 't> {
                         .map(|(marker, pos)| (marker.clone(), pos)),
                 );
 
-                if !self.curr_output.contains(&mapping) {
-                    self.curr_output.insert(mapping.clone());
+                let curr_output = match &mut self.curr_output {
+                    Some(curr_output) => curr_output,
+                    None => return Some(mapping),
+                };
+
+                if !curr_output.contains(&mapping) {
+                    #[cfg(test)]
+                    {
+                        assert!(
+                            !self.all_outputs.contains(&mapping),
+                            "Mapping was already produced: {:?}",
+                            mapping
+                        );
+                        self.all_outputs.push(mapping.clone());
+                    }
+
+                    curr_output.insert(mapping.clone());
                     return Some(mapping);
                 }
             }
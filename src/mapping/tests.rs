@@ -1,8 +1,23 @@
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use super::super::automaton::Automaton;
+use super::super::automaton::{AdjCacheSize, Automaton};
 use super::super::regex;
-use super::{naive, Mapping};
+use super::indexed_dag::{
+    CharBoundaryError, CleanStrategy, IndexedDag, OwnedIndexedDag, ToggleProgress,
+};
+use super::{naive, Mapping, Marker, Variable};
+
+/// Build a mapping from a list of named spans.
+fn synthetic_mapping<'t>(text: &'t str, groups: &[(&str, usize, usize)]) -> Mapping<'t> {
+    let markers = groups.iter().enumerate().flat_map(|(id, &(name, start, end))| {
+        let var = Arc::new(Variable::new(name.to_string(), id as u64));
+        vec![(Marker::Open(var.clone()), start), (Marker::Close(var), end)]
+    });
+
+    Mapping::from_markers(text, markers)
+}
 
 /// Build a HashSet collecting results of naive algorithm.
 fn naive_results<'t>(regex: &Automaton, text: &'t str) -> HashSet<Mapping<'t>> {
@@ -14,32 +29,55 @@ fn default_results<'t>(regex: &Automaton, text: &'t str) -> HashSet<Mapping<'t>>
     regex::compile_matches(regex.clone(), text).iter().collect()
 }
 
+#[test]
+fn naive_duplicates() {
+    let counts = |regex, text| {
+        let regex = regex::compile(regex).unwrap();
+        let distinct = naive::NaiveEnum::new(&regex, text).count();
+        let all: Vec<_> = naive::NaiveEnum::new_allow_duplicates(&regex, text).collect();
+        let all_distinct: HashSet<_> = all.iter().cloned().collect();
+        assert_eq!(all_distinct, naive_results(&regex, text));
+        assert_eq!(all_distinct.len(), distinct);
+        (distinct, all.len())
+    };
+
+    // A single run produces each span of `.*`
+    assert_eq!(counts(r".*", ""), (1, 1));
+    assert_eq!(counts(r".*", "aaa"), (10, 10));
+    assert_eq!(counts(r".*", "ab\nba"), (12, 12));
+
+    // The middle of `aa` can be matched by both repetitions
+    let (distinct, all) = counts(r"a*a*", "aa");
+    assert_eq!(distinct, 6);
+    assert!(all > distinct);
+}
+
 #[test]
 fn block_a() {
-    let regex = regex::compile(r"^(.*[^a])?(?P<block_a>a+)([^a].*)?$");
+    let regex = regex::compile(r"^(.*[^a])?(?P<block_a>a+)([^a].*)?$").unwrap();
     let texts = ["a", "aaaaaaaaaaaaa", "bbbabb", "aaaabbaaababbbb"];
 
-    for text in texts.into_iter() {
+    for text in texts.iter() {
         assert_eq!(naive_results(&regex, text), default_results(&regex, text));
     }
 }
 
 #[test]
 fn sep_email() {
-    let regex = regex::compile(r"\w+@\w+");
+    let regex = regex::compile(r"\w+@\w+").unwrap();
     let texts = ["a bba a@b b@a aaa@bab abbababaa@@@babbabb"];
 
-    for text in texts.into_iter() {
+    for text in texts.iter() {
         assert_eq!(naive_results(&regex, text), default_results(&regex, text));
     }
 }
 
 #[test]
 fn substrings() {
-    let regex = regex::compile(r".*");
+    let regex = regex::compile(r".*").unwrap();
     let texts = ["abcdefghijklmnopqrstuvwxyz"];
 
-    for text in texts.into_iter() {
+    for text in texts.iter() {
         assert_eq!(naive_results(&regex, text), default_results(&regex, text));
     }
 }
@@ -47,27 +85,45 @@ fn substrings() {
 #[test]
 fn ordered_blocks() {
     let regex =
-        regex::compile(r"^(.*[^a])?(?P<block_a>a+)([^a].*[^b]|[^ab])?(?P<block_b>b+)([^b].*)?$");
+        regex::compile(r"^(.*[^a])?(?P<block_a>a+)([^a].*[^b]|[^ab])?(?P<block_b>b+)([^b].*)?$")
+            .unwrap();
     let texts = ["ab", "aaaabbbb", "bbbaaababaaaaaabbbbabbbababbababbabb"];
 
-    for text in texts.into_iter() {
+    for text in texts.iter() {
         assert_eq!(naive_results(&regex, text), default_results(&regex, text));
     }
 }
 
+#[test]
+fn many_markers_in_level() {
+    // Markers of all groups are assigned in the same levels, which exceeds 64
+    // markers.
+    let pattern: String = (0..40).map(|i| format!("(?P<g{}>)", i)).collect();
+    let regex = regex::compile(&pattern).unwrap();
+    let matches: Vec<_> = regex::compile_matches(regex, "ab").iter().collect();
+
+    assert_eq!(matches.len(), 3);
+
+    for mapping in matches {
+        let span = mapping.main_span();
+        assert_eq!(mapping.iter_groups().count(), 41);
+        assert!(mapping.iter_groups().all(|(_, group)| group == span));
+    }
+}
+
 #[test]
 fn mixed_emails() {
-    let regex = regex::compile(r"(?P<login>\w+(\.\w+)*)@(?P<server>\w+\.\w+)");
+    let regex = regex::compile(r"(?P<login>\w+(\.\w+)*)@(?P<server>\w+\.\w+)").unwrap();
     let texts = ["aaaa@aaa.aa", "aa@aa a@a.a@a.a.a@a.a.a.a@a.a.a.a.a"];
 
-    for text in texts.into_iter() {
+    for text in texts.iter() {
         assert_eq!(naive_results(&regex, text), default_results(&regex, text));
     }
 }
 
 #[test]
 fn some_utf8() {
-    let regex = regex::compile(r"e{3}|ê{3}");
+    let regex = regex::compile(r"e{3}|ê{3}").unwrap();
     let texts = [
         "êêeeeêê",
         "êê",
@@ -77,7 +133,804 @@ fn some_utf8() {
         "eeeêêeee",
     ];
 
-    for text in texts.into_iter() {
+    for text in texts.iter() {
         assert_eq!(naive_results(&regex, text), default_results(&regex, text));
     }
 }
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_email() {
+    let regex = regex::compile(r"\w+@\w+").unwrap();
+    let text = "a bba a@b b@a aaa@bab abbababaa@@@babbabb";
+    let compiled = regex::compile_matches(regex, text);
+
+    let serial: HashSet<_> = compiled.iter().collect();
+    let parallel = compiled.par_iter().unwrap();
+    assert_eq!(parallel.len(), serial.len());
+    assert_eq!(parallel.into_iter().collect::<HashSet<_>>(), serial);
+}
+
+#[test]
+fn reverse_order() {
+    let regex = regex::compile(r"a(?P<x>a*)").unwrap();
+    let compiled = regex::compile_matches(regex, "baaba");
+
+    let mut sorted: Vec<_> = compiled.iter_sorted().unwrap().collect();
+    sorted.reverse();
+    assert_eq!(compiled.iter_rev().unwrap().collect::<Vec<_>>(), sorted);
+
+    let spans: Vec<_> = compiled
+        .iter_rev()
+        .unwrap()
+        .map(|mapping| mapping.main_span())
+        .collect();
+    assert_eq!(spans, vec![4..5, 2..3, 1..3, 1..2]);
+}
+
+#[test]
+fn overlapping_groups() {
+    let text = "abcdef";
+
+    let adjacent = synthetic_mapping(text, &[("a", 0, 2), ("b", 2, 4), ("match", 0, 4)]);
+    assert_eq!(adjacent.overlaps("a", "b"), Some(false));
+    assert_eq!(adjacent.overlaps("a", "match"), Some(true));
+    assert_eq!(adjacent.overlaps("a", "c"), None);
+    assert!(!adjacent.has_overlapping_groups());
+
+    let overlapping = synthetic_mapping(text, &[("a", 0, 3), ("b", 2, 4)]);
+    assert_eq!(overlapping.overlaps("a", "b"), Some(true));
+    assert_eq!(overlapping.overlaps("b", "a"), Some(true));
+    assert!(overlapping.has_overlapping_groups());
+
+    let empty = synthetic_mapping(text, &[("a", 0, 3), ("b", 1, 1)]);
+    assert_eq!(empty.overlaps("a", "b"), Some(false));
+    assert!(!empty.has_overlapping_groups());
+}
+
+#[test]
+fn eq_captures() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let captures_hash = |mapping: &Mapping| {
+        let mut hasher = DefaultHasher::new();
+        mapping.captures_hash(&mut hasher);
+        hasher.finish()
+    };
+
+    let text = "ab ab";
+    let short = synthetic_mapping(text, &[("a", 0, 1), ("match", 0, 2)]);
+    let long = synthetic_mapping(text, &[("a", 0, 1), ("match", 0, 5)]);
+    assert!(short != long);
+    assert!(short.eq_captures(&long));
+    assert_eq!(captures_hash(&short), captures_hash(&long));
+
+    // The same text captured at another position is another capture
+    let other = synthetic_mapping(text, &[("a", 3, 4), ("match", 0, 5)]);
+    assert!(!long.eq_captures(&other));
+
+    let without_match = synthetic_mapping(text, &[("a", 0, 1)]);
+    assert!(without_match.eq_captures(&short));
+    assert_eq!(captures_hash(&without_match), captures_hash(&short));
+}
+
+#[test]
+fn group_tree() {
+    use super::GroupNode;
+
+    let node = |name: &str, span, children| GroupNode {
+        name: name.to_string(),
+        span,
+        children,
+    };
+
+    // Without any known parent, groups are nested following their spans
+    let mapping = synthetic_mapping("abcdef", &[("a", 0, 2), ("b", 0, 4), ("c", 4, 5)]);
+    assert_eq!(
+        mapping.group_tree(),
+        node(
+            "",
+            0..5,
+            vec![node("b", 0..4, vec![node("a", 0..2, vec![])]), node("c", 4..5, vec![])]
+        )
+    );
+}
+
+#[test]
+fn shift() {
+    let text = "abcdef";
+    let mut mapping = synthetic_mapping(text, &[("a", 0, 2), ("b", 2, 4), ("match", 0, 4)]);
+    mapping.shift(100);
+    assert_eq!(
+        mapping.iter_groups().collect::<Vec<_>>(),
+        vec![("a", 100..102), ("b", 102..104), ("match", 100..104)]
+    );
+    assert_eq!(mapping.main_span(), 100..104);
+
+    let mapping = mapping.shifted(0).shifted(1);
+    assert_eq!(mapping.main_span(), 101..105);
+
+    // Matches over a chunk are given the offsets of the whole text
+    let whole = "xyz abcdef";
+    let chunk = synthetic_mapping(&whole[4..], &[("a", 0, 2), ("match", 0, 4)]);
+    let rebased = chunk.rebase(whole, 4);
+    assert_eq!(rebased.main_span(), 4..8);
+    assert_eq!(rebased.group_text("a"), Some("ab"));
+    assert_eq!(rebased, synthetic_mapping(whole, &[("a", 4, 6), ("match", 4, 8)]));
+}
+
+#[test]
+fn char_offsets() {
+    let regex = regex::compile(r"(?P<end>llo)$").unwrap();
+    let mapping = default_results(&regex, "héllo").into_iter().next().unwrap();
+
+    let bytes: Vec<_> = mapping.iter_groups().filter(|(name, _)| *name == "end").collect();
+    let chars: Vec<_> = mapping
+        .iter_groups_chars()
+        .filter(|(name, _)| *name == "end")
+        .collect();
+
+    assert_eq!(bytes, vec![("end", 3..6)]);
+    assert_eq!(chars, vec![("end", 2..5)]);
+}
+
+#[test]
+fn compile_chars() {
+    let regex = regex::compile(r"(?P<word>\w+)\s(?P<num>\d+)").unwrap();
+    let text = "héllo 42 wörld 7 ∀x 3";
+
+    let expected: HashSet<Vec<_>> = default_results(&regex, text)
+        .iter()
+        .map(|mapping| {
+            mapping
+                .iter_groups()
+                .map(|(name, range)| (name.to_string(), range))
+                .collect()
+        })
+        .collect();
+
+    let mut buffer = String::from("stale");
+    let dag = IndexedDag::compile_chars(regex.clone(), text.chars(), &mut buffer);
+    let results: HashSet<Vec<_>> = dag
+        .iter()
+        .map(|mapping| {
+            mapping
+                .iter_groups()
+                .map(|(name, range)| (name.to_string(), range))
+                .collect()
+        })
+        .collect();
+
+    assert!(!expected.is_empty());
+    assert_eq!(results, expected);
+
+    // Mappings refer to the collected text
+    let mut words: Vec<_> = dag
+        .iter()
+        .filter(|mapping| mapping.group_text("num") == Some("42"))
+        .map(|mapping| (mapping.group_text("word").unwrap().to_string(), mapping.main_span()))
+        .collect();
+    words.sort_by_key(|(_, span)| span.start);
+    let expected = [("héllo", 0..9), ("éllo", 1..9), ("llo", 3..9), ("lo", 4..9), ("o", 5..9)];
+    let expected: Vec<_> = expected.iter().map(|(w, s)| (w.to_string(), s.clone())).collect();
+    assert_eq!(words, expected);
+    assert_eq!(buffer, text);
+
+    // Chars after the DAG is disconnected are still counted
+    let mut buffer = String::new();
+    let dag = IndexedDag::compile_chars(regex::compile(r"^a").unwrap(), "ba".chars(), &mut buffer);
+    assert!(dag.is_empty());
+    let dag = IndexedDag::compile_chars(regex::compile(r"^b").unwrap(), "bxxxx".chars(), &mut buffer);
+    assert_eq!(dag.iter_texts().collect::<Vec<_>>(), vec!["b"]);
+    assert_eq!(buffer, "bxxxx");
+}
+
+#[test]
+fn compile_bytes() {
+    let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+    let regex = regex::compile(r"^(?P<magic>\x89PNG)\r\n").unwrap();
+    let dag = IndexedDag::compile_bytes(regex, png);
+    let matches: Vec<_> = dag.iter().collect();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].main_span(), 0..6);
+    assert_eq!(matches[0].group_bytes("magic"), Some(&b"\x89PNG"[..]));
+    assert_eq!(dag.count(), 1);
+
+    // Bytes are read as Latin-1 chars, spans are given in bytes
+    let bytes = b"\xFFab\xE9\xFF";
+    let dag = IndexedDag::compile_bytes(regex::compile(r"(?P<x>\xFF|é)").unwrap(), bytes);
+    let mut groups: Vec<_> = dag.iter().map(|mapping| mapping.group_bytes("x")).collect();
+    groups.sort();
+    let (latin, ff) = (&b"\xE9"[..], &b"\xFF"[..]);
+    assert_eq!(groups, vec![Some(latin), Some(ff), Some(ff)]);
+    // The implicit group *match* is assigned too
+    assert!(dag.iter().all(|mapping| mapping.iter_groups_bytes().count() == 2));
+    let mut spans: Vec<_> = dag.iter().map(|mapping| mapping.main_span()).collect();
+    spans.sort_by_key(|span| span.start);
+    assert_eq!(spans, vec![0..1, 3..4, 4..5]);
+
+    // A mapping without groups spans all the bytes
+    let dag = IndexedDag::compile_bytes(regex::compile_raw(r"\xFF+").unwrap(), b"\xFF\xFF");
+    let matches: Vec<_> = dag.iter().collect();
+    assert_eq!(matches.len(), 1);
+    assert!(matches[0].is_empty());
+    assert_eq!(matches[0].main_span(), 0..2);
+}
+
+#[test]
+fn dump_jump() {
+    let regex = regex::compile(r"a{2}").unwrap();
+    let dag = IndexedDag::builder()
+        .clean_strategy(CleanStrategy::Never)
+        .compile(regex, "aaa")
+        .unwrap();
+
+    let mut buf = Vec::new();
+    dag.dump_jump(&mut buf).unwrap();
+    let trace = String::from_utf8(buf).unwrap();
+
+    assert!(!trace.is_empty());
+
+    for level in 0..=3 {
+        assert!(trace.contains(&format!("Level {}:", level)));
+    }
+
+    assert!(!trace.contains("Level 4:"));
+    assert!(trace.contains("rlevel:"));
+}
+
+#[test]
+fn deadline() {
+    let regex = regex::compile(r"\w+@\w+").unwrap();
+    let text = "a@b ".repeat(10_000);
+
+    let compiled = IndexedDag::builder()
+        .deadline(Instant::now())
+        .compile(regex.clone(), &text);
+    assert!(compiled.is_err());
+
+    let text = "a bba a@b b@a";
+    let compiled = IndexedDag::builder()
+        .deadline(Instant::now() + Duration::from_secs(3600))
+        .compile(regex.clone(), text)
+        .unwrap();
+    assert_eq!(compiled.iter().collect::<HashSet<_>>(), default_results(&regex, text));
+}
+
+#[test]
+fn max_levels() {
+    use super::indexed_dag::DagCompileError;
+
+    let regex = regex::compile(r"a+").unwrap();
+    let text = "a".repeat(1000);
+
+    let compiled = IndexedDag::builder()
+        .max_levels(10)
+        .compile(regex.clone(), &text);
+    assert_eq!(compiled.err(), Some(DagCompileError::TooManyLevels(10)));
+
+    let compiled = IndexedDag::builder()
+        .max_levels(10_000)
+        .compile(regex.clone(), &text)
+        .unwrap();
+    assert!(compiled.get_nb_levels() <= 10_000);
+    assert!(compiled.iter().next().is_some());
+}
+
+#[test]
+fn max_width() {
+    use super::indexed_dag::DagCompileError;
+
+    // Classic blowup: the ith char can be matched by any of the first i
+    // optional atoms, thus the level i has 32 + i vertices until all atoms
+    // are reachable.
+    let regex = regex::compile(r"(?:a?){30}a{30}").unwrap();
+    let text = "a".repeat(60);
+
+    let compiled = IndexedDag::builder()
+        .max_width(40)
+        .compile(regex.clone(), &text);
+    assert_eq!(compiled.err(), Some(DagCompileError::Blowup { level: 9, width: 41 }));
+
+    let compiled = IndexedDag::builder()
+        .max_width(100)
+        .compile(regex.clone(), &text)
+        .unwrap();
+    assert!(compiled.level_sizes().iter().all(|&(_, width)| width <= 100));
+    assert!(compiled.iter().next().is_some());
+
+    // Levels of a small pattern are bounded by its few states, whatever the
+    // length of the text
+    let compiled = IndexedDag::builder()
+        .max_width(4)
+        .compile(regex::compile(r"(a|aa)*b").unwrap(), &text)
+        .unwrap();
+    assert!(compiled.level_sizes().iter().all(|&(_, width)| width <= 4));
+}
+
+#[test]
+fn full_match() {
+    let regex = regex::compile(r"^a+$").unwrap();
+    assert!(regex::compile_matches(regex.clone(), "aaa").is_full_match());
+    assert!(!regex::compile_matches(regex.clone(), "aab").is_full_match());
+    assert!(!regex::compile_matches(regex, "").is_full_match());
+
+    // Without anchors, some match must still span the whole text
+    let regex = regex::compile(r"a+").unwrap();
+    assert!(regex::compile_matches(regex.clone(), "aaa").is_full_match());
+    assert!(!regex::compile_matches(regex.clone(), "aab").is_full_match());
+    assert!(!regex::compile_matches(regex.clone(), "baa").is_full_match());
+    assert!(!regex::compile_matches(regex, "aab").is_empty());
+}
+
+#[test]
+fn is_empty() {
+    let compiled = regex::compile_matches(regex::compile("z+").unwrap(), "aaa");
+    assert!(compiled.is_empty());
+    assert_eq!(compiled.iter().count(), 0);
+
+    let compiled = regex::compile_matches(regex::compile("a+").unwrap(), "aaa");
+    assert!(!compiled.is_empty());
+    assert_eq!(compiled.iter().count(), 6);
+}
+
+#[test]
+fn distinct_spans() {
+    let regex = regex::compile(r"a(?P<x>b)?b?").unwrap();
+    let compiled = regex::compile_matches(regex, "abbab");
+
+    let nb_mappings = compiled.iter().count();
+    let spans: Vec<_> = compiled.iter_spans().collect();
+    let distinct: HashSet<_> = spans.iter().cloned().collect();
+
+    assert!(spans.len() < nb_mappings);
+    assert_eq!(spans.len(), distinct.len());
+    assert_eq!(distinct, vec![0..1, 0..2, 0..3, 3..4, 3..5].into_iter().collect());
+}
+
+#[test]
+fn clean_strategies() {
+    let regex = regex::compile(r"TTAC.{0,50}CACC").unwrap();
+    let text = &include_str!("../../benchmarks/dna.txt")[..2000];
+
+    let count = |strategy| {
+        IndexedDag::builder()
+            .clean_strategy(strategy)
+            .compile(regex.clone(), text)
+            .unwrap()
+            .iter()
+            .count()
+    };
+
+    let expected = count(CleanStrategy::PowerOfTwo);
+    assert!(expected > 0);
+    assert_eq!(count(CleanStrategy::Never), expected);
+    assert_eq!(count(CleanStrategy::EveryN(1)), expected);
+    assert_eq!(count(CleanStrategy::EveryN(7)), expected);
+    assert_eq!(count(CleanStrategy::EveryN(0)), expected);
+
+    let options = IndexedDag::builder().clean_strategy(CleanStrategy::EveryN(0));
+    assert!(format!("{:?}", options).contains("clean_strategy: Never"));
+}
+
+#[test]
+fn clean_nested_repetitions() {
+    let patterns = [
+        r"(?P<x>a(b)*)((b)?)*(?P<y>[ab])",
+        r"(?P<x>((a)*)*)b(?P<y>(a|b)?)",
+        r"(?P<x>a)((.)?)*(?P<y>b)",
+    ];
+    let text = "abbacabbbab";
+
+    for pattern in patterns.iter() {
+        let regex = regex::compile(pattern).unwrap();
+        let expected = naive_results(&regex, text);
+
+        for &strategy in &[
+            CleanStrategy::Never,
+            CleanStrategy::EveryN(1),
+            CleanStrategy::EveryN(2),
+            CleanStrategy::PowerOfTwo,
+        ] {
+            let compiled = IndexedDag::builder()
+                .clean_strategy(strategy)
+                .compile(regex.clone(), text)
+                .unwrap();
+
+            assert_eq!(compiled.iter().collect::<HashSet<_>>(), expected);
+            assert!(compiled.skipped_cleanings().is_empty());
+        }
+    }
+}
+
+#[test]
+fn leftmost_longest() {
+    let text = "aaa baa";
+    let compiled = regex::compile_matches(regex::compile("a+").unwrap(), text);
+    assert_eq!(compiled.iter().count(), 9);
+
+    let texts: Vec<_> = compiled
+        .iter_leftmost_longest()
+        .unwrap()
+        .map(|mapping| &text[mapping.main_span()])
+        .collect();
+    assert_eq!(texts, vec!["aaa", "aa"]);
+}
+
+#[test]
+fn count_matches_algebraic() {
+    let cases = [
+        (r".*", "abcdef"),
+        (r"a+", "aaa baa"),
+        (r"(?P<x>a)(?P<y>b)?", "abab ba"),
+        (r"(?P<word>\w+)\s(?P<num>\d+)", "héllo 42 wörld 7"),
+        (r"^(?P<x>a*)b", "aab"),
+        (r"x", "aaa"),
+        // Ambiguous patterns, with several runs for some matches
+        (r"a*a*", "aa"),
+        (r".*a.*", "aa"),
+        (r"(?P<x>a|ab)(?P<y>b?)", "abab"),
+        (r"(a|a)*(?P<x>a*)", "aaaa"),
+        (r"(?P<x>\w*)(?P<y>\w*)", "héllo"),
+    ];
+
+    for (pattern, text) in &cases {
+        let compiled = regex::compile_matches(regex::compile(pattern).unwrap(), text);
+        assert_eq!(
+            compiled.count_matches_algebraic(),
+            compiled.iter().count() as u64,
+            "{} over {:?}",
+            pattern,
+            text
+        );
+    }
+
+    let compiled = regex::compile_matches(regex::compile(r"a*a*").unwrap(), "aa");
+    assert_eq!(compiled.count_matches_algebraic(), 6);
+    let compiled = regex::compile_matches(regex::compile(r".*a.*").unwrap(), "aa");
+    assert_eq!(compiled.count_matches_algebraic(), 3);
+
+    // Too many matches to enumerate them
+    let text = "a".repeat(2000);
+    let compiled = regex::compile_matches(regex::compile(r".*").unwrap(), &text);
+    assert_eq!(compiled.count_matches_algebraic(), 2001 * 2002 / 2);
+
+    // The text of a stream of chars is collected
+    let mut buffer = String::new();
+    let dag = IndexedDag::compile_chars(regex::compile(r"a").unwrap(), "aa".chars(), &mut buffer);
+    assert_eq!(dag.count_matches_algebraic(), 2);
+}
+
+#[test]
+fn level_sizes() {
+    let compiled = regex::compile_matches(regex::compile("a{3}").unwrap(), "aaa");
+    let sizes = compiled.level_sizes();
+    assert_eq!(sizes.len(), compiled.get_nb_levels());
+
+    // Each level adds a state of `a{3}` that can be reached, plus the closing
+    // marker for the last one.
+    assert_eq!(sizes, vec![(0, 2), (1, 3), (2, 4), (3, 6)]);
+    assert!(sizes.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    assert_eq!(sizes.iter().map(|(_, count)| count).sum::<usize>(), 15);
+}
+
+#[test]
+fn owned_texts() {
+    let compiled = regex::compile_matches(regex::compile(r"\d+").unwrap(), "a1b22");
+    let mut texts: Vec<String> = compiled.iter_texts().collect();
+    texts.sort();
+    assert_eq!(texts, vec!["1", "2", "2", "22"]);
+
+    let compiled = regex::compile_matches(regex::compile(r"(?P<n>\d)\d|b").unwrap(), "a1b22");
+    assert_eq!(compiled.iter().count(), 2);
+    assert_eq!(compiled.iter_group_texts("n").collect::<Vec<_>>(), vec!["2"]);
+    assert_eq!(compiled.iter_group_texts("unknown").count(), 0);
+}
+
+#[test]
+fn bounded_adj_cache() {
+    let regex = regex::compile(r"(?P<x>\p{Han})[^a](?P<y>.)").unwrap();
+    let text: String = (0..400)
+        .map(|i| std::char::from_u32(0x4e00 + (i * 7) % 100).unwrap())
+        .collect();
+
+    let results = |cache_size| {
+        IndexedDag::builder()
+            .adj_cache_cap(cache_size)
+            .compile(regex.clone(), &text)
+            .unwrap()
+            .iter()
+            .collect::<HashSet<_>>()
+    };
+
+    let expected = results(AdjCacheSize::Unbounded);
+    assert_eq!(expected.len(), 398);
+    assert_eq!(results(AdjCacheSize::Bounded(16)), expected);
+    assert_eq!(results(AdjCacheSize::Bounded(0)), expected);
+}
+
+#[test]
+fn builder() {
+    let regex = regex::compile(r"\w+@\w+").unwrap();
+    let text = "a bba a@b b@a aaa@bab abbababaa@@@babbabb";
+
+    let compiled = IndexedDag::builder()
+        .progress(ToggleProgress::Disabled)
+        .clean_strategy(CleanStrategy::EveryN(3))
+        .deadline(Instant::now() + Duration::from_secs(3600))
+        .adj_cache_cap(AdjCacheSize::Bounded(2))
+        .compile(regex.clone(), text)
+        .unwrap();
+
+    let expected = default_results(&regex, text);
+    assert_eq!(expected.len(), 11);
+    assert_eq!(compiled.iter().collect::<HashSet<_>>(), expected);
+}
+
+#[test]
+fn unset_groups() {
+    let compiled = regex::compile_matches(regex::compile(r"(?P<opt>x)?y").unwrap(), "y");
+    let mapping = compiled.iter().next().unwrap();
+
+    assert_eq!(mapping.group_text("opt"), None);
+    assert!(mapping.is_unset("opt"));
+    assert!(!mapping.is_unset("match"));
+    assert!(!mapping.is_unset("unknown"));
+
+    let groups: Vec<_> = mapping.iter_declared_groups().collect();
+    assert_eq!(groups, vec![("match", Some(0..1)), ("opt", None)]);
+
+    // Declared variables don't change equality
+    let naive: HashSet<_> = naive_results(&regex::compile(r"(?P<opt>x)?y").unwrap(), "y");
+    assert_eq!(naive, vec![mapping].into_iter().collect());
+}
+
+#[test]
+fn window() {
+    let text = "abcdefghijklmnopqrstuvwxyz";
+    let compiled = regex::compile_matches(regex::compile(r".*").unwrap(), text);
+
+    let in_window = |span: &std::ops::Range<usize>| 5 <= span.start && span.end <= 10;
+    let spans: HashSet<_> = compiled
+        .iter_window(5..10)
+        .map(|mapping| mapping.main_span())
+        .collect();
+
+    assert!(spans.iter().all(in_window));
+    assert_eq!(spans.len(), 6 * 7 / 2);
+    assert_eq!(
+        spans,
+        compiled
+            .iter()
+            .map(|mapping| mapping.main_span())
+            .filter(in_window)
+            .collect()
+    );
+
+    assert_eq!(compiled.iter_window(0..text.len()).count(), compiled.iter().count());
+    assert_eq!(compiled.iter_window(30..40).count(), 0);
+}
+
+#[test]
+fn iter_from() {
+    let regex = regex::compile(r".*").unwrap();
+    let text = "abcdef";
+    let dag = IndexedDag::builder()
+        .clean_strategy(CleanStrategy::Never)
+        .compile(regex, text)
+        .unwrap();
+
+    let spans: HashSet<_> = dag.iter_from(3).unwrap().map(|m| m.main_span()).collect();
+    let expected: HashSet<_> = dag
+        .iter()
+        .map(|m| m.main_span())
+        .filter(|span| span.start >= 3)
+        .collect();
+
+    assert_eq!(spans, expected);
+    assert_eq!(spans.len(), 10);
+    assert!(spans.iter().all(|span| span.start >= 3));
+
+    // Offsets must fall on a char boundary of the text
+    let dag = IndexedDag::builder()
+        .clean_strategy(CleanStrategy::Never)
+        .compile(regex::compile(r".*").unwrap(), "héllo")
+        .unwrap();
+    assert!(dag.iter_from(3).is_ok());
+    assert_eq!(dag.iter_from(2).err(), Some(CharBoundaryError(2)));
+    assert_eq!(dag.iter_from(7).err(), Some(CharBoundaryError(7)));
+}
+
+#[test]
+fn with_progress() {
+    let compiled = regex::compile_matches(regex::compile(r"\w+@\w+").unwrap(), "a@b aa@bb");
+    let expected: Vec<_> = compiled.iter_sorted().unwrap().collect();
+
+    for toggle in [ToggleProgress::Disabled, ToggleProgress::Enabled].iter() {
+        let mut mappings: Vec<_> = compiled.iter().with_progress(*toggle).collect();
+        mappings.sort();
+        assert_eq!(mappings, expected);
+    }
+}
+
+#[test]
+fn count_progress() {
+    use super::super::progress::{Progress, SharedSink};
+    use super::indexed_dag::count_progress;
+
+    let text = "a".repeat(100);
+    let compiled = regex::compile_matches(regex::compile(r"a*").unwrap(), &text);
+    let sink = SharedSink::default();
+    let progress = Progress::spinner(compiled.iter())
+        .auto_refresh(false)
+        .refresh_delay(0)
+        .sink(sink.clone());
+
+    // 101 * 102 / 2 = 5151 spans, refreshed every 1024 matches
+    assert_eq!(count_progress(progress).count(), 5151);
+
+    let content = sink.content();
+    let refreshes: Vec<_> = content.trim_end().split('\r').skip(1).collect();
+    assert_eq!(refreshes.len(), 6);
+    assert!(refreshes[0].contains(" -- 1024 matches"));
+    assert!(refreshes[5].contains(" -- 5151 matches"));
+    assert!(content.ends_with('\n'));
+}
+
+#[test]
+fn ill_formed_markers() {
+    use super::super::automaton::LabelSpec;
+    use super::MappingError;
+
+    let var = Arc::new(Variable::new("x".to_string(), 0));
+    let open = || Marker::Open(var.clone());
+    let close = || Marker::Close(var.clone());
+
+    let markers = vec![(open(), 0), (open(), 1), (close(), 2)];
+    assert!(matches!(
+        Mapping::try_from_markers("abc", markers.into_iter()),
+        Err(MappingError::DoubleAssign { old_pos: 0, pos: 1, .. })
+    ));
+
+    let markers = vec![(open(), 2), (close(), 1)];
+    assert!(matches!(
+        Mapping::try_from_markers("abc", markers.into_iter()),
+        Err(MappingError::InvalidOrdering { .. })
+    ));
+
+    let markers = vec![(open(), 1), (close(), 2)];
+    let mapping = Mapping::try_from_markers("abc", markers.into_iter()).unwrap();
+    assert_eq!(mapping.group_text("x"), Some("b"));
+
+    // An automaton opening `x` twice produces an ill-formed mapping
+    let automaton = Automaton::from_parts(
+        4,
+        vec![
+            (0, LabelSpec::Open("x".to_string()), 1),
+            (1, LabelSpec::Literal('a'), 2),
+            (2, LabelSpec::Open("x".to_string()), 3),
+            (3, LabelSpec::Close("x".to_string()), 3),
+        ]
+        .into_iter(),
+        vec![3].into_iter(),
+    );
+
+    let compiled = regex::compile_matches(automaton, "a");
+    let mut iterator = compiled.into_iter();
+    assert!(iterator.next().is_none());
+    assert!(matches!(
+        iterator.error(),
+        Some(MappingError::DoubleAssign { .. })
+    ));
+    assert!(compiled.iter_sorted().is_err());
+
+    // Ill-formed mappings are skipped without interrupting the enumeration
+    let regex = regex::compile(r"(?P<o>(?P<x>a){1,3}b)").unwrap();
+    let compiled = regex::compile_matches(regex, "aabc");
+    let mut iterator = compiled.iter();
+    let spans: Vec<_> = iterator.by_ref().map(|mapping| mapping.main_span()).collect();
+    assert_eq!(spans, vec![1..3]);
+    assert!(iterator.error().is_some());
+}
+
+#[test]
+fn group_stats() {
+    use super::indexed_dag::GroupStat;
+
+    let text = "the quick brown fox jumps";
+    let regex = regex::compile(r"[^\w](?P<w>\w+)[^\w]").unwrap();
+    let stats = regex::compile_matches(regex, text).group_stats();
+
+    // Only the three inner words are surrounded by non-word characters
+    assert_eq!(
+        stats["w"],
+        GroupStat {
+            count:     3,
+            min_len:   3,
+            max_len:   5,
+            total_len: 13,
+        }
+    );
+    assert_eq!(stats["match"].count, 3);
+    assert_eq!(stats.len(), 2);
+
+    let regex = regex::compile(r"(?P<w>\w+)").unwrap();
+    let compiled = regex::compile_matches(regex, text);
+    let stats = compiled.group_stats();
+    assert_eq!(stats["w"].count, compiled.iter().count());
+    assert_eq!(stats["w"].min_len, 1);
+    assert_eq!(stats["w"].max_len, 5);
+}
+
+#[test]
+fn for_each_match() {
+    let regex = regex::compile(r"(?P<user>\w+)@(?P<domain>\w+)").unwrap();
+    let text = "a bba a@b b@a aaa@bab abbababaa@@@babbabb";
+    let compiled = regex::compile_matches(regex, text);
+
+    // Sorted assignations of a match, with spans as pairs
+    let sorted_groups = |groups: Vec<(&str, std::ops::Range<usize>)>| {
+        let mut groups: Vec<_> = groups
+            .into_iter()
+            .map(|(name, range)| (name.to_string(), range.start, range.end))
+            .collect();
+        groups.sort();
+        groups
+    };
+
+    let mut expected: Vec<_> = compiled
+        .iter()
+        .map(|mapping| sorted_groups(mapping.iter_groups().collect()))
+        .collect();
+
+    let mut visited = Vec::new();
+    compiled.for_each_match(|view| {
+        assert_eq!(Some(view.main_span()), view.get_group("match"));
+        visited.push(sorted_groups(view.iter_groups().collect()));
+    });
+
+    expected.sort();
+    visited.sort();
+    assert_eq!(visited.len(), 11);
+    assert_eq!(visited, expected);
+
+    let mut users = Vec::new();
+    compiled.for_each_match(|view| users.extend(view.group_text("user")));
+    assert_eq!(users.len(), 11);
+    assert!(users.contains(&"aaa"));
+}
+
+#[test]
+fn owned_indexed_dag() {
+    // The DAG is returned from the helper along with the text it owns
+    fn index(pattern: &str) -> OwnedIndexedDag {
+        let text = String::from("héllo wörld");
+        OwnedIndexedDag::compile(regex::compile(pattern).unwrap(), text)
+    }
+
+    let dag = index(r"\w+");
+    let borrowed_text = "héllo wörld";
+    let borrowed = regex::find_all(r"\w+", borrowed_text).unwrap();
+
+    let texts: HashSet<_> = dag
+        .iter()
+        .filter_map(|m| m.group_text("match").map(str::to_string))
+        .collect();
+    let expected: HashSet<_> = borrowed.iter_texts().collect();
+    assert_eq!(texts, expected);
+    assert_eq!(dag.count(), borrowed.iter().count());
+    assert_eq!(dag.iter_spans().count(), borrowed.iter_spans().count());
+
+    // Converting a borrowing DAG gives the same matches
+    let owned = {
+        let text = String::from(borrowed_text);
+        regex::find_all(r"\w+", &text).unwrap().into_owned()
+    };
+    let mut spans: Vec<_> = owned.iter().map(|m| m.main_span()).collect();
+    let mut expected: Vec<_> = borrowed.iter().map(|m| m.main_span()).collect();
+    spans.sort_by_key(|span| (span.start, span.end));
+    expected.sort_by_key(|span| (span.start, span.end));
+    assert_eq!(spans, expected);
+    assert_eq!(owned.text(), borrowed_text);
+}
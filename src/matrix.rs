@@ -1,3 +1,4 @@
+use std::error;
 use std::fmt;
 use std::iter;
 use std::ops::{Index, Mul};
@@ -5,18 +6,43 @@ use std::ops::{Index, Mul};
 use super::tools::iter_complement;
 
 /// Naive representation of a matrix as a single consecutive chunk of memory.
+///
+/// Two matrices are equal if they have the same dimensions and content.
+#[derive(Eq, PartialEq)]
 pub struct Matrix<T> {
     height: usize,
     width:  usize,
     data:   Vec<T>,
 }
 
+/// Error returned when building a matrix from rows of different widths, see
+/// `Matrix::from_rows`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct DimMismatch {
+    /// Index of the first row whose width differs from the first one.
+    pub row:      usize,
+    pub expected: usize,
+    pub found:    usize,
+}
+
+impl fmt::Display for DimMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "row {} has {} columns instead of {}",
+            self.row, self.found, self.expected
+        )
+    }
+}
+
+impl error::Error for DimMismatch {}
+
 // Custom trait for matrices that can be right-multiplied by a column vector.
 pub trait ColMul<U> {
-    fn col_mul(&self, column: &Vec<U>) -> Vec<U>;
+    fn col_mul(&self, column: &[U]) -> Vec<U>;
 }
 
-impl<'a, T> Matrix<T>
+impl<T> Matrix<T>
 where
     T: Copy + Default,
 {
@@ -29,6 +55,32 @@ where
         }
     }
 
+    /// Create a matrix from a list of rows, which must all have the same
+    /// width. An empty list gives a matrix of size 0×0.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Matrix<T>, DimMismatch> {
+        let height = rows.len();
+        let width = rows.first().map(Vec::len).unwrap_or(0);
+
+        if let Some((row, found)) = rows
+            .iter()
+            .map(Vec::len)
+            .enumerate()
+            .find(|&(_, len)| len != width)
+        {
+            return Err(DimMismatch {
+                row,
+                expected: width,
+                found,
+            });
+        }
+
+        Ok(Matrix {
+            width,
+            height,
+            data: rows.into_iter().flatten().collect(),
+        })
+    }
+
     pub fn get_height(&self) -> usize {
         self.height
     }
@@ -59,6 +111,19 @@ where
         self.data.iter().skip(row * self.width).take(self.width)
     }
 
+    /// Get the transposed matrix, where rows are swapped with columns.
+    pub fn transpose(&self) -> Matrix<T> {
+        let data = (0..self.width)
+            .flat_map(|col| self.iter_col(col).copied())
+            .collect();
+
+        Matrix {
+            width: self.height,
+            height: self.width,
+            data,
+        }
+    }
+
     /// Truncate rows and columns from a matrix.
     pub fn truncate<U, V>(&self, del_rows: U, del_cols: V) -> Matrix<T>
     where
@@ -96,8 +161,7 @@ where
         let mut all_data_iter = self.data.iter();
         let indices = rows
             .clone()
-            .map(|row| cols.clone().map(move |col| self.data_index(row, col)))
-            .flatten();
+            .flat_map(|row| cols.clone().map(move |col| self.data_index(row, col)));
         let data: Vec<_> = indices
             .scan(0, |expected_index, index| {
                 let val = all_data_iter.nth(index - *expected_index);
@@ -137,19 +201,42 @@ where
 // |_|  |_|\__,_|\__|_|  |_/_/\_\
 //
 
+impl Matrix<bool> {
+    /// Create the identity matrix of size n×n.
+    pub fn identity(n: usize) -> Matrix<bool> {
+        let mut matrix = Matrix::new(n, n, false);
+
+        for i in 0..n {
+            *matrix.at(i, i) = true;
+        }
+
+        matrix
+    }
+
+    /// Check if a matrix is a square matrix with only `true` on its diagonal.
+    pub fn is_identity(&self) -> bool {
+        self.height == self.width
+            && (0..self.height).all(|row| {
+                self.iter_row(row)
+                    .enumerate()
+                    .all(|(col, &x)| x == (row == col))
+            })
+    }
+}
+
 impl Mul for &Matrix<bool> {
     type Output = Matrix<bool>;
 
     fn mul(self, other: &Matrix<bool>) -> Matrix<bool> {
+        debug_assert_eq!(self.width, other.height, "Multiplied matrices have incompatible sizes");
         let data = (0..self.height)
-            .map(|row| {
+            .flat_map(|row| {
                 (0..other.width).map(move |col| {
                     let row_iter = self.iter_row(row);
                     let col_iter = other.iter_col(col);
                     row_iter.zip(col_iter).any(|(&x, &y)| x && y)
                 })
             })
-            .flatten()
             .collect();
 
         Matrix {
@@ -161,7 +248,8 @@ impl Mul for &Matrix<bool> {
 }
 
 impl ColMul<bool> for Matrix<bool> {
-    fn col_mul(&self, column: &Vec<bool>) -> Vec<bool> {
+    fn col_mul(&self, column: &[bool]) -> Vec<bool> {
+        debug_assert_eq!(self.width, column.len(), "Multiplied column has an incompatible size");
         (0..self.height)
             .map(|row| {
                 let row_iter = self.iter_row(row);
@@ -172,6 +260,59 @@ impl ColMul<bool> for Matrix<bool> {
     }
 }
 
+//  ___       _
+// |_ _|_ __ | |_ ___  __ _  ___ _ __
+//  | || '_ \| __/ _ \/ _` |/ _ \ '__|
+//  | || | | | ||  __/ (_| |  __/ |
+// |___|_| |_|\__\___|\__, |\___|_|
+//  __  __       _    |___/_
+// |  \/  | __ _| |_ _ __(_)_  __
+// | |\/| |/ _` | __| '__| \ \/ /
+// | |  | | (_| | |_| |  | |>  <
+// |_|  |_|\__,_|\__|_|  |_/_/\_\
+//
+
+/// Product of integer matrices, sums saturate instead of overflowing.
+impl Mul for &Matrix<u64> {
+    type Output = Matrix<u64>;
+
+    fn mul(self, other: &Matrix<u64>) -> Matrix<u64> {
+        debug_assert_eq!(self.width, other.height, "Multiplied matrices have incompatible sizes");
+        let data = (0..self.height)
+            .flat_map(|row| {
+                (0..other.width).map(move |col| {
+                    let row_iter = self.iter_row(row);
+                    let col_iter = other.iter_col(col);
+                    row_iter.zip(col_iter).fold(0, |acc: u64, (&x, &y)| {
+                        acc.saturating_add(x.saturating_mul(y))
+                    })
+                })
+            })
+            .collect();
+
+        Matrix {
+            width: other.width,
+            height: self.height,
+            data,
+        }
+    }
+}
+
+impl ColMul<u64> for Matrix<u64> {
+    fn col_mul(&self, column: &[u64]) -> Vec<u64> {
+        debug_assert_eq!(self.width, column.len(), "Multiplied column has an incompatible size");
+        (0..self.height)
+            .map(|row| {
+                let row_iter = self.iter_row(row);
+                let col_iter = column.iter();
+                row_iter.zip(col_iter).fold(0, |acc: u64, (&x, &y)| {
+                    acc.saturating_add(x.saturating_mul(y))
+                })
+            })
+            .collect()
+    }
+}
+
 //  ____       _
 // |  _ \  ___| |__  _   _  __ _
 // | | | |/ _ \ '_ \| | | |/ _` |
@@ -196,6 +337,128 @@ impl fmt::Debug for Matrix<bool> {
             .collect::<Vec<_>>()
             .join(",\n ");
 
-        write!(f, "[{}]\n", content)
+        writeln!(f, "[{}]", content)
+    }
+}
+
+//  _____         _
+// |_   _|__  ___| |_ ___
+//   | |/ _ \/ __| __/ __|
+//   | |  __/\__ \ |_\__ \
+//   |_|\___||___/\__|___/
+//
+
+#[cfg(test)]
+mod tests {
+    use super::{DimMismatch, Matrix};
+
+    /// Build a pseudo-random boolean matrix from a seed.
+    fn random_matrix(height: usize, width: usize, seed: u64) -> Matrix<bool> {
+        let mut state = seed;
+        let mut matrix = Matrix::new(height, width, false);
+
+        for row in 0..height {
+            for col in 0..width {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                *matrix.at(row, col) = (state >> 33) & 1 == 0;
+            }
+        }
+
+        matrix
+    }
+
+    #[test]
+    fn identity() {
+        for seed in 0..10 {
+            let matrix = random_matrix(5, 7, seed);
+            assert_eq!(&Matrix::identity(5) * &matrix, matrix);
+            assert_eq!(&matrix * &Matrix::identity(7), matrix);
+        }
+    }
+
+    #[test]
+    fn transpose() {
+        let matrix = random_matrix(3, 5, 42);
+        let transposed = matrix.transpose();
+        assert_eq!(transposed.get_height(), 5);
+        assert_eq!(transposed.get_width(), 3);
+        assert_eq!(transposed[(4, 1)], matrix[(1, 4)]);
+        assert_eq!(transposed.transpose(), matrix);
+
+        for seed in 0..10 {
+            let a = random_matrix(4, 6, seed);
+            let b = random_matrix(6, 3, seed + 100);
+            assert_eq!((&a * &b).transpose(), &b.transpose() * &a.transpose());
+        }
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(random_matrix(4, 6, 7), random_matrix(4, 6, 7));
+        assert_ne!(random_matrix(4, 6, 7), random_matrix(4, 6, 8));
+
+        let mut changed = random_matrix(4, 6, 7);
+        let value = changed[(3, 5)];
+        *changed.at(3, 5) = !value;
+        assert_ne!(changed, random_matrix(4, 6, 7));
+
+        // Same content but different dimensions
+        assert_ne!(Matrix::new(2, 3, false), Matrix::new(3, 2, false));
+        assert_ne!(Matrix::new(0, 3, false), Matrix::new(0, 2, false));
+    }
+
+    #[test]
+    fn integer_product() {
+        use super::ColMul;
+
+        let a = Matrix::from_rows(vec![vec![1_u64, 2, 0], vec![3, 1, 4]]).unwrap();
+        let b = Matrix::from_rows(vec![vec![2, 1], vec![0, 5], vec![1, 1]]).unwrap();
+        assert_eq!((&a * &b).data, vec![2, 11, 10, 12]);
+        assert_eq!(a.col_mul(&[1, 1, 1]), vec![3, 8]);
+
+        // Overflows saturate
+        let big = Matrix::from_rows(vec![vec![u64::MAX, 1]]).unwrap();
+        let col = Matrix::from_rows(vec![vec![2], vec![3]]).unwrap();
+        assert_eq!((&big * &col).data, vec![u64::MAX]);
+        assert_eq!(big.col_mul(&[1, 1]), vec![u64::MAX]);
+    }
+
+    #[test]
+    fn from_rows() {
+        let matrix = Matrix::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]]).unwrap();
+        assert_eq!(matrix.get_height(), 2);
+        assert_eq!(matrix.get_width(), 3);
+        assert_eq!(matrix.data, vec![1, 2, 3, 4, 5, 6]);
+
+        let empty = Matrix::<u64>::from_rows(Vec::new()).unwrap();
+        assert_eq!((empty.get_height(), empty.get_width()), (0, 0));
+
+        let ragged = Matrix::from_rows(vec![vec![1, 2, 3], vec![4, 5], vec![6]]);
+        assert_eq!(
+            ragged.err(),
+            Some(DimMismatch {
+                row:      1,
+                expected: 3,
+                found:    2,
+            })
+        );
+    }
+
+    #[test]
+    fn is_identity() {
+        assert!(Matrix::identity(0).is_identity());
+        assert!(Matrix::identity(4).is_identity());
+
+        let mut missing_one = Matrix::identity(4);
+        *missing_one.at(2, 2) = false;
+        assert!(!missing_one.is_identity());
+
+        let mut extra_one = Matrix::identity(4);
+        *extra_one.at(0, 3) = true;
+        assert!(!extra_one.is_identity());
+
+        assert!(!Matrix::new(3, 4, false).is_identity());
     }
 }
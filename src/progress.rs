@@ -1,5 +1,4 @@
 use std::cmp;
-use std::convert::TryInto;
 use std::io;
 use std::io::Write;
 use std::iter;
@@ -33,16 +32,23 @@ where
 {
     /// Iterator that it actualy extracts from
     iterator: T,
-    /// Hypotetical size of the iterator
-    max_iterations: usize,
+    /// Hypotetical size of the iterator, if it is unknown only a spinner is
+    /// displayed
+    max_iterations: Option<usize>,
     /// Number of elements already extracted
     count_iterations: usize,
 
     /// Creation instant of the progress bar
     start_time: time::Instant,
 
+    /// Wether the bar should never be displayed
+    silent: bool,
     /// Wether the bar should automaticaly refresh while iterating it
     auto_refresh: bool,
+    /// Minimal delay between two automatic refreshes, in milliseconds
+    refresh_delay: u128,
+    /// Number of characters inside of the bar
+    bar_size: usize,
 
     /// Last refresh instant
     last_refresh: time::Instant,
@@ -54,29 +60,129 @@ where
 
     /// Purely estetic looping animation
     spinner: iter::Cycle<str::Chars<'static>>,
+
+    /// Where the bar is displayed, stderr by default
+    sink:     Box<dyn Write>,
+    /// Wether the bar was finished, overwise it is cleared when dropped
+    finished: bool,
 }
 
 impl<T, U> Progress<T, U>
 where
     T: Iterator<Item = U>,
 {
+    /// Create a progress bar over an iterator which is expected to yield
+    /// `max_iterations` elements.
+    pub fn new(iterator: T, max_iterations: usize) -> Progress<T, U> {
+        Progress {
+            iterator,
+            max_iterations: Some(max_iterations),
+            count_iterations: 0,
+            start_time: time::Instant::now(),
+            silent: false,
+            auto_refresh: true,
+            refresh_delay: REFRESH_DELAY,
+            bar_size: BAR_SIZE,
+            last_refresh: time::Instant::now(),
+            last_width: 0,
+            extra_msg: None,
+            spinner: SPINNER.chars().cycle(),
+            sink: Box::new(io::stderr()),
+            finished: false,
+        }
+    }
+
+    /// Create a progress bar that is never displayed, the iterator doesn't
+    /// need to know its size.
+    pub fn silent(iterator: T) -> Progress<T, U> {
+        let mut progress = Progress::new(iterator, 0);
+        progress.silent = true;
+        progress.auto_refresh = false;
+        progress
+    }
+
+    /// Create a progress bar over an iterator which size is unknown, only the
+    /// elapsed time and the speed are displayed along with a spinner.
+    pub fn spinner(iterator: T) -> Progress<T, U> {
+        let mut progress = Progress::new(iterator, 0);
+        progress.max_iterations = None;
+        progress
+    }
+
     pub fn auto_refresh(mut self, toggle: bool) -> Progress<T, U> {
         self.auto_refresh = toggle;
         self
     }
 
+    pub fn refresh_delay(mut self, delay: u128) -> Progress<T, U> {
+        self.refresh_delay = delay;
+        self
+    }
+
+    pub fn bar_size(mut self, size: usize) -> Progress<T, U> {
+        self.bar_size = size;
+        self
+    }
+
+    pub fn sink<W: Write + 'static>(mut self, sink: W) -> Progress<T, U> {
+        self.sink = Box::new(sink);
+        self
+    }
+
     pub fn extra_msg(&mut self, msg: String) {
         self.extra_msg = Some(msg);
     }
 
+    /// Check if the refresh delay has elapsed since the last refresh.
+    pub fn refresh_due(&self) -> bool {
+        self.last_refresh.elapsed().as_millis() >= self.refresh_delay
+    }
+
     pub fn refresh(&mut self) {
+        if self.silent {
+            return;
+        }
+
+        let display = self.render();
+        let padding = self.last_width.saturating_sub(display.chars().count());
+
+        write!(self.sink, "\r{}{}", display, " ".repeat(padding))
+            .and_then(|_| self.sink.flush())
+            .expect("Can't display the progress bar");
+
+        // Update informations about last refresh
+        self.last_refresh = time::Instant::now();
+        self.last_width = display.chars().count();
+    }
+
+    /// Display the bar one last time and end its line, so that further output
+    /// is not mixed with it.
+    pub fn finish(&mut self) {
+        if !self.silent && !self.finished {
+            self.refresh();
+            writeln!(self.sink).expect("Can't display the progress bar");
+        }
+
+        self.finished = true;
+    }
+
+    /// Build the line describing current state of the progress bar.
+    fn render(&mut self) -> String {
+        let max_iterations = match self.max_iterations {
+            None => return self.render_spinner(),
+            Some(max) => max,
+        };
+
         // Compute bar shape
-        let proportion = self.count_iterations as f64 / self.max_iterations as f64;
+        let proportion = match max_iterations {
+            0 => 1.,
+            max => self.count_iterations as f64 / max as f64,
+        };
         let body_length = cmp::min(
-            BAR_SIZE + 1,
-            (proportion * (BAR_SIZE + 1) as f64).round() as usize,
+            self.bar_size + 1,
+            (proportion * (self.bar_size + 1) as f64).round() as usize,
         );
-        let mut void_length = (BAR_SIZE + 1) - body_length;
+        let mut void_length = (self.bar_size + 1) - body_length;
         let mut has_head = false;
 
         if void_length > 0 {
@@ -87,47 +193,84 @@ where
         let body = "=".repeat(body_length);
         let void = " ".repeat(void_length);
         let head = ">".repeat(has_head.into());
+        let percentage = cmp::min(100, (100. * proportion) as usize);
 
-        // Compute speed
-        let mut speed = 1_000_000. * self.count_iterations as f64
-            / self.start_time.elapsed().as_micros() as f64;
-        let mut prefix_index = 0;
+        let (speed, display_speed, prefix) = self.speed();
 
-        while speed > 1_024. && prefix_index + 1 < PREFIXES.len() {
-            speed /= 1_024.;
-            prefix_index += 1;
-        }
+        // Estimate remaining time, which is unknown until some iterations are
+        // done.
+        let eta = if self.count_iterations == 0 || speed <= 0. {
+            String::from("--:--")
+        } else {
+            let remaining = max_iterations.saturating_sub(self.count_iterations);
+            let eta = (remaining as f64 / speed).round() as u64;
+            format!("{:02}:{:02}", eta / 60, eta % 60)
+        };
 
         // Display
         let elapsed = self.start_time.elapsed().as_secs();
 
         let mut display = format!(
-            "{} [{}{}{}]  {:02}:{:02}  {:.2} {}/s",
+            "{} [{}{}{}] {:>3}%  {:02}:{:02}  ETA {}  {:.2} {}/s",
             self.spinner.next().unwrap(),
             body,
             head,
             void,
+            percentage,
             elapsed / 60,
             elapsed % 60,
-            speed,
-            PREFIXES[prefix_index],
+            eta,
+            display_speed,
+            prefix,
         );
 
         if let Some(msg) = &self.extra_msg {
             display = format!("{} -- {}", display, msg);
         }
 
-        eprint!("\r{}", display);
+        display
+    }
+
+    /// Build the line describing current state when the size of the iterator
+    /// is unknown, which has no bar.
+    fn render_spinner(&mut self) -> String {
+        let (_, display_speed, prefix) = self.speed();
+        let elapsed = self.start_time.elapsed().as_secs();
 
-        if display.chars().count() < self.last_width {
-            eprint!("{}", " ".repeat(self.last_width - display.chars().count()))
+        let mut display = format!(
+            "{} {:02}:{:02}  {:.2} {}/s",
+            self.spinner.next().unwrap(),
+            elapsed / 60,
+            elapsed % 60,
+            display_speed,
+            prefix,
+        );
+
+        if let Some(msg) = &self.extra_msg {
+            display = format!("{} -- {}", display, msg);
         }
 
-        io::stdout().flush().expect("Can't flush stdout");
+        display
+    }
 
-        // Update informations about last refresh
-        self.last_refresh = time::Instant::now();
-        self.last_width = display.chars().count();
+    /// Compute the number of iterations per second, also given as a scaled
+    /// value with its binary prefix.
+    fn speed(&self) -> (f64, f64, &'static str) {
+        let elapsed_micros = self.start_time.elapsed().as_micros();
+        let speed = match elapsed_micros {
+            0 => 0.,
+            micros => 1_000_000. * self.count_iterations as f64 / micros as f64,
+        };
+
+        let mut display_speed = speed;
+        let mut prefix_index = 0;
+
+        while display_speed > 1_024. && prefix_index + 1 < PREFIXES.len() {
+            display_speed /= 1_024.;
+            prefix_index += 1;
+        }
+
+        (speed, display_speed, PREFIXES[prefix_index])
     }
 }
 
@@ -136,21 +279,22 @@ where
     T: std::iter::ExactSizeIterator + Iterator<Item = U>,
 {
     pub fn from_iter(iterator: T) -> Progress<T, U> {
-        let max_iterations = iterator
-            .len()
-            .try_into()
-            .expect("Impossible to init progress bar for objects larger than a 64 bits integer");
+        let max_iterations = iterator.len();
+        Progress::new(iterator, max_iterations)
+    }
+}
 
-        Progress {
-            iterator,
-            max_iterations,
-            count_iterations: 0,
-            start_time: time::Instant::now(),
-            auto_refresh: true,
-            last_refresh: time::Instant::now(),
-            last_width: 0,
-            extra_msg: None,
-            spinner: SPINNER.chars().cycle(),
+/// If the iteration was interrupted, the bar is erased from its line so that
+/// further output doesn't start after it. This also applies to bars refreshed
+/// manually.
+impl<T, U> Drop for Progress<T, U>
+where
+    T: Iterator<Item = U>,
+{
+    fn drop(&mut self) {
+        if !self.finished && self.last_width > 0 {
+            let blank = " ".repeat(self.last_width);
+            let _ = write!(self.sink, "\r{}\r", blank).and_then(|_| self.sink.flush());
         }
     }
 }
@@ -164,21 +308,177 @@ where
     fn next(&mut self) -> Option<U> {
         let ret = self.iterator.next();
 
+        if ret.is_some() {
+            self.count_iterations += 1;
+        }
+
         if self.auto_refresh {
             match ret {
-                None => {
-                    self.refresh();
-                    print!("\n");
-                }
+                None => self.finish(),
                 Some(_) => {
-                    if self.last_refresh.elapsed().as_millis() > REFRESH_DELAY {
+                    if self.refresh_due() {
                         self.refresh();
                     }
                 }
             }
         }
 
-        self.count_iterations += 1;
         ret
     }
 }
+
+//  _____         _
+// |_   _|__  ___| |_ ___
+//   | |/ _ \/ __| __/ __|
+//   | |  __/\__ \ |_\__ \
+//   |_|\___||___/\__|___/
+//
+
+/// A sink which content can be read after the bar is dropped, for tests.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedSink(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl io::Write for SharedSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl SharedSink {
+    pub(crate) fn content(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{Progress, SharedSink};
+
+    #[test]
+    fn display_eta() {
+        let mut progress = Progress::from_iter(0..100).auto_refresh(false);
+        progress.by_ref().take(50).for_each(drop);
+
+        let display = progress.render();
+        assert!(display.contains("ETA"));
+        assert!(display.contains(" 50%"));
+    }
+
+    #[test]
+    fn display_eta_before_start() {
+        let mut progress = Progress::from_iter(0..100).auto_refresh(false);
+        let display = progress.render();
+        assert!(display.contains("ETA --:--"));
+        assert!(display.contains("  0%"));
+    }
+
+    #[test]
+    fn count_iterations() {
+        let mut progress = Progress::from_iter(0..42).auto_refresh(false);
+        progress.by_ref().for_each(drop);
+        assert_eq!(progress.count_iterations, 42);
+
+        // Calling the iterator after it is exhausted should not count
+        assert_eq!(progress.next(), None);
+        assert_eq!(progress.count_iterations, 42);
+        assert!(progress.render().contains("100%"));
+    }
+
+    #[test]
+    fn silent() {
+        // Chars doesn't know its exact size
+        let mut progress = Progress::silent("héllo".chars()).auto_refresh(true);
+        assert_eq!(progress.by_ref().collect::<String>(), "héllo");
+        assert_eq!(progress.count_iterations, 5);
+        assert_eq!(progress.last_width, 0);
+    }
+
+    #[test]
+    fn bar_size() {
+        let mut progress = Progress::from_iter(0..100)
+            .auto_refresh(false)
+            .bar_size(10);
+
+        for _ in 0..3 {
+            let display = progress.render();
+            let bar_start = display.find('[').unwrap();
+            let bar_end = display.find(']').unwrap();
+            assert_eq!(display[bar_start + 1..bar_end].chars().count(), 11);
+            progress.by_ref().take(50).for_each(drop);
+        }
+    }
+
+    #[test]
+    fn clear_on_drop() {
+        let sink = SharedSink::default();
+        let mut progress = Progress::from_iter(0..100).sink(sink.clone());
+        progress.by_ref().take(3).for_each(drop);
+        progress.refresh();
+        let width = progress.last_width;
+        assert!(width > 0);
+
+        drop(progress);
+        let content = sink.content();
+        assert!(content.ends_with(&format!("\r{}\r", " ".repeat(width))));
+        assert!(!content.contains('\n'));
+    }
+
+    #[test]
+    fn no_clear_when_finished() {
+        let sink = SharedSink::default();
+        let mut progress = Progress::from_iter(0..10).sink(sink.clone());
+        progress.by_ref().take(3).for_each(drop);
+        progress.refresh();
+        progress.by_ref().for_each(drop);
+        drop(progress);
+
+        let content = sink.content();
+        assert!(content.ends_with("\n"));
+        assert_eq!(content.matches('\n').count(), 1);
+    }
+
+    #[test]
+    fn spinner() {
+        let sink = SharedSink::default();
+        let mut progress = Progress::spinner("abc".chars()).sink(sink.clone());
+        progress.extra_msg(String::from("3 letters"));
+        assert_eq!(progress.by_ref().collect::<String>(), "abc");
+
+        let content = sink.content();
+        assert!(content.ends_with(" -- 3 letters\n"));
+        assert!(!content.contains('[') && !content.contains("ETA"));
+    }
+
+    #[test]
+    fn spinner_unsized() {
+        // Filtering an iterator makes its size unknown
+        let sink = SharedSink::default();
+        let mut progress = Progress::spinner((0..).filter(|x| x % 3 == 0))
+            .refresh_delay(u128::MAX)
+            .sink(sink.clone());
+
+        for _ in 0..4 {
+            progress.next();
+            progress.refresh();
+        }
+
+        let content = sink.content();
+        let frames: HashSet<_> = content
+            .split('\r')
+            .filter_map(|line| line.chars().next())
+            .collect();
+
+        assert!(!content.contains('[') && !content.contains(']'));
+        assert!(frames.len() > 1);
+        assert_eq!(progress.count_iterations, 4);
+    }
+}
@@ -2,7 +2,7 @@
 /// linearized language out of a regexp's HIR, and finaly convert this
 /// expression to a variable NFA.
 use std::collections::LinkedList;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use super::super::automaton::Automaton;
 use super::super::automaton::Label;
@@ -26,7 +26,7 @@ pub struct GlushkovFactors {
 #[derive(Clone, Debug)]
 pub struct GlushkovTerm {
     id: usize,
-    label: Rc<Label>,
+    label: Arc<Label>,
 }
 
 #[derive(Clone, Debug)]
@@ -84,7 +84,7 @@ impl LocalLang {
 
     /// Register a new atom in the local language and return the associated
     /// term.
-    fn register_label(&mut self, label: Rc<Label>, id_offset: usize) -> GlushkovTerm {
+    fn register_label(&mut self, label: Arc<Label>, id_offset: usize) -> GlushkovTerm {
         self.nb_terms += 1;
         GlushkovTerm {
             id: self.nb_terms + id_offset - 1,
@@ -94,7 +94,14 @@ impl LocalLang {
 
     /// Return a local language representing an expression containing a single
     /// term.
-    fn label(label: Rc<Label>, id_offset: usize) -> LocalLang {
+    ///
+    /// Each term reads a single character, even for literal strings: the
+    /// levels of `IndexedDag` are built one character at a time, thus a term
+    /// reading several characters would need to keep track of its progress
+    /// across levels, which is what the states of a chain of literals already
+    /// do. Merging them would make the automaton smaller without saving any
+    /// vertex in the DAG.
+    fn label(label: Arc<Label>, id_offset: usize) -> LocalLang {
         let mut lang = LocalLang::empty();
         let term = lang.register_label(label, id_offset);
         lang.factors.p.push_back(term.clone());
@@ -0,0 +1,62 @@
+//< Fast path for patterns that are a plain literal, such as `abc`, which
+//< matches are found with a substring search instead of building the DAG of
+//< the automaton.
+//<
+//< Matches are the same as given by `IndexedDag::iter`: all occurrences of the
+//< literal are enumerated, including overlapping ones, and each of them only
+//< assigns the group *match*.
+
+use std::ops;
+
+use super::mapping::Mapping;
+
+//  _     _ _                 _ __  __       _       _
+// | |   (_) |_ ___ _ __ __ _| |  \/  | __ _| |_ ___| |__   ___ _ __
+// | |   | | __/ _ \ '__/ _` | | |\/| |/ _` | __/ __| '_ \ / _ \ '__|
+// | |___| | ||  __/ | | (_| | | |  | | (_| | || (__| | | |  __/ |
+// |_____|_|\__\___|_|  \__,_|_|_|  |_|\__,_|\__\___|_| |_|\___|_|
+//
+
+/// Enumerate occurrences of a literal in a text, see `regex::as_literal` to
+/// check if a pattern is a literal.
+pub struct LiteralMatcher<'t> {
+    literal: String,
+    text:    &'t str,
+
+    /// Offset where the next occurrence is looked for, if any
+    start: Option<usize>,
+}
+
+impl<'t> LiteralMatcher<'t> {
+    pub fn new(literal: &str, text: &'t str) -> LiteralMatcher<'t> {
+        LiteralMatcher {
+            literal: literal.to_string(),
+            text,
+            start: Some(0),
+        }
+    }
+}
+
+impl<'t> Iterator for LiteralMatcher<'t> {
+    type Item = Mapping<'t>;
+
+    fn next(&mut self) -> Option<Mapping<'t>> {
+        let start = self.start?;
+        let pos = start + self.text[start..].find(&self.literal)?;
+
+        // Occurrences may overlap, the next one starts at least one character
+        // further.
+        self.start = self.text[pos..]
+            .chars()
+            .next()
+            .map(|c| pos + c.len_utf8());
+
+        Some(Mapping::from_single_match(
+            self.text,
+            ops::Range {
+                start: pos,
+                end:   pos + self.literal.len(),
+            },
+        ))
+    }
+}
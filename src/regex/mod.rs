@@ -1,84 +1,277 @@
+pub mod literal;
 pub mod naive;
+pub mod parse;
 
 mod glushkov;
-mod parse;
+
+use std::error;
+use std::fmt;
 
 use super::automaton::Automaton;
 use super::mapping;
 
-pub fn compile(regex: &str) -> Automaton {
-    let regex = reformat(regex);
-    let hir = parse::Hir::from_regex(&regex);
-    glushkov::LocalLang::from_hir(hir, 0).into_automaton()
+/// Error occuring during the compilation of a regex.
+#[derive(Debug)]
+pub enum CompileError {
+    /// The regex is not syntactically valid.
+    Syntax(Box<regex_syntax::Error>),
+    /// The regex uses a construct that is not supported yet.
+    Unsupported(String),
+    /// A group of the regex has a name reserved for the implicit group, see
+    /// `Flags::no_match_group`.
+    ReservedName(String),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Syntax(err) => write!(f, "invalid regex: {}", err),
+            CompileError::Unsupported(construct) => {
+                write!(f, "unsupported construct: {}", construct)
+            }
+            CompileError::ReservedName(name) => write!(
+                f,
+                "the group name `{}` is reserved for the whole match, unless the implicit \
+                 group is disabled",
+                name
+            ),
+        }
+    }
+}
+
+impl error::Error for CompileError {}
+
+/// Flags changing the meaning of a regex, they can also be enabled inside of
+/// the regex itself.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Flags {
+    /// Allow `.` to match `\n`, as the inline flag `(?s)` does. This is the
+    /// default for all dots of the pattern, which can still be overridden
+    /// inside of it with `(?-s)`.
+    pub dotall:         bool,
+    /// Only match at the start of the text, as a leading `^` does.
+    pub anchor_start:   bool,
+    /// Only match at the end of the text, as a trailing `\z` does.
+    pub anchor_end:     bool,
+    /// Don't wrap the pattern in the implicit group *match*, which allows to
+    /// use this name for a group of the pattern. Matches then only assign the
+    /// groups of the pattern.
+    pub no_match_group: bool,
+}
+
+pub fn compile(regex: &str) -> Result<Automaton, CompileError> {
+    compile_with_flags(regex, Flags::default())
+}
+
+pub fn compile_with_flags(regex: &str, flags: Flags) -> Result<Automaton, CompileError> {
+    let hir = compile_hir(regex, flags)?;
+    Ok(glushkov::LocalLang::from_hir(hir, 0).into_automaton())
+}
+
+/// Get the simplified Hir used by `compile` to build the automaton, which
+/// includes the implicit group *match* unless it is disabled by the flags.
+pub fn compile_hir(regex: &str, flags: Flags) -> Result<parse::Hir, CompileError> {
+    let regex = prepare(regex, flags)?;
+    Ok(parse::Hir::from_regex_with_flags(&regex, flags)?.factor_alternations())
+}
+
+/// Check that a pattern would be accepted by `compile`, giving the same error
+/// otherwise. The pattern is only parsed, which is much cheaper than building
+/// its automaton.
+pub fn validate(regex: &str) -> Result<(), CompileError> {
+    validate_with_flags(regex, Flags::default())
+}
+
+/// Check that a pattern would be accepted by `compile_with_flags`, see
+/// `validate`.
+pub fn validate_with_flags(regex: &str, flags: Flags) -> Result<(), CompileError> {
+    parse::check_supported(&prepare(regex, flags)?, flags)
+}
+
+/// Get the regex actually parsed by `compile`, with the implicit group and
+/// any prefix or suffix, see `reformat`.
+fn prepare(regex: &str, flags: Flags) -> Result<String, CompileError> {
+    let (anchor_start, regex, anchor_end) = strip_anchors(regex);
+
+    if !flags.no_match_group && parse::group_names(regex, flags)?.iter().any(|n| n == "match") {
+        return Err(CompileError::ReservedName(String::from("match")));
+    }
+
+    let anchor_end = match anchor_end {
+        EndAnchor::None if flags.anchor_end => EndAnchor::Text,
+        anchor_end => anchor_end,
+    };
+
+    Ok(reformat(
+        regex,
+        anchor_start || flags.anchor_start,
+        anchor_end,
+        !flags.no_match_group,
+    ))
+}
+
+pub fn compile_raw(regex: &str) -> Result<Automaton, CompileError> {
+    let hir = parse::Hir::from_regex(regex)?.factor_alternations();
+    Ok(glushkov::LocalLang::from_hir(hir, 0).into_automaton())
+}
+
+/// Get the text matched by a pattern if it is a plain literal, in which case
+/// its matches can be enumerated by `literal::LiteralMatcher` instead of
+/// compiling an automaton. A pattern anchored by the flags or without the
+/// implicit group is not considered as a literal.
+pub fn as_literal(regex: &str, flags: Flags) -> Option<String> {
+    if flags.anchor_start || flags.anchor_end || flags.no_match_group {
+        return None;
+    }
+
+    parse::literal(regex).ok().flatten()
+}
+
+/// Read patterns from the content of a pattern file, one per line. Empty lines
+/// and comments, which are lines starting with `#`, are skipped.
+pub fn read_patterns(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
 }
 
-pub fn compile_raw(regex: &str) -> Automaton {
-    let hir = parse::Hir::from_regex(&regex);
-    glushkov::LocalLang::from_hir(hir, 0).into_automaton()
+/// Combine several patterns into a single one matching the union of their
+/// matches. Each pattern is wrapped into a group *patternN*, where N is its
+/// position starting from 1, which tells which alternative a mapping comes
+/// from. A single pattern is kept as is.
+///
+/// As anchors are only supported at the ends of a pattern, combined patterns
+/// can't use anchors, `Flags` should be used instead.
+pub fn union<S: AsRef<str>>(patterns: &[S]) -> String {
+    match patterns {
+        [pattern] => pattern.as_ref().to_string(),
+        _ => patterns
+            .iter()
+            .enumerate()
+            .map(|(index, pattern)| format!("(?P<pattern{}>{})", index + 1, pattern.as_ref()))
+            .collect::<Vec<_>>()
+            .join("|"),
+    }
 }
 
 #[cfg(test)]
 pub fn is_match(regex: &str, text: &str) -> bool {
-    let automaton = compile(&regex);
+    let automaton = compile(regex).unwrap();
     let matches = compile_matches(automaton, text);
 
     let ret = matches.iter().next().is_some();
     ret
 }
+
+/// Compile a pattern and index its matches over a text, as `compile` and
+/// `compile_matches` would do.
+///
+/// Matches borrow the returned `IndexedDag`, which must thus be kept alive
+/// while they are enumerated, for example with `for mapping in &matches`.
+pub fn find_all<'t>(pattern: &str, text: &'t str) -> Result<mapping::IndexedDag<'t>, CompileError> {
+    Ok(compile_matches(compile(pattern)?, text))
+}
+
 pub fn compile_matches<'t>(automaton: Automaton, text: &'t str) -> mapping::IndexedDag<'t> {
-    mapping::IndexedDag::compile(
-        automaton,
-        text,
-        mapping::indexed_dag::ToggleProgress::Disabled,
-    )
+    mapping::IndexedDag::compile(automaton, text)
 }
 
 pub fn compile_matches_progress<'t>(
     automaton: Automaton,
     text: &'t str,
 ) -> mapping::IndexedDag<'t> {
-    mapping::IndexedDag::compile(
-        automaton,
-        text,
-        mapping::indexed_dag::ToggleProgress::Enabled,
-    )
+    mapping::IndexedDag::builder()
+        .progress(mapping::indexed_dag::ToggleProgress::Enabled)
+        .compile(automaton, text)
+        .expect("Compilation without bounds can't fail")
 }
 
-/// Reformat the regex to get a regex matching the whole regex in a group called
-/// *match*. The new regex will allow any prefix or suffix to be matched before
-/// the old regex, except if the input regex contains anchors at its begining or
-/// end.
-fn reformat(regex: &str) -> String {
-    let mut regex = String::from(regex);
-
-    let anchor_begin = Some(&b'^') == regex.as_bytes().first();
-    let anchor_end = Some(&b'$') == regex.as_bytes().last();
-
-    // Remove anchor characters
-    if anchor_begin {
-        regex.remove(0);
-    }
+/// How the end of a pattern is anchored.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EndAnchor {
+    /// Any suffix may follow the pattern.
+    None,
+    /// The pattern must end with the text, as with `\z`.
+    Text,
+    /// The pattern must end with the text or right before a final `\n`, as
+    /// with `$`.
+    TextOrNewline,
+}
 
-    if anchor_end {
-        regex.remove(regex.len() - 1);
-    }
+/// Remove anchors (`^`, `\A`, `$` or `\z`) at the begining and the end of a
+/// regex, and tell which ones were found.
+fn strip_anchors(regex: &str) -> (bool, &str, EndAnchor) {
+    let (anchor_start, regex) = match regex.strip_prefix('^') {
+        Some(stripped) => (true, stripped),
+        None => match regex.strip_prefix(r"\A") {
+            Some(stripped) => (true, stripped),
+            None => (false, regex),
+        },
+    };
+
+    let (anchor_end, regex) = match strip_end_anchor(regex) {
+        Some((stripped, anchor_end)) => (anchor_end, stripped),
+        None => (EndAnchor::None, regex),
+    };
+
+    (anchor_start, regex, anchor_end)
+}
 
+/// Reformat the regex to get a regex matching the whole regex in a group called
+/// *match*, if `match_group` is set. The new regex will allow any prefix or
+/// suffix to be matched before the old regex, except if it is anchored at its
+/// begining or end.
+///
+/// Prefixes and suffixes may contain line breaks whatever the flag `dotall`,
+/// which only applies to the pattern: they are matched with `(?s:.)*`, which
+/// sets the flag `s` locally. As most tools do, an end anchor `$` also matches
+/// before a final line break, which is left out of the group.
+fn reformat(regex: &str, anchor_start: bool, anchor_end: EndAnchor, match_group: bool) -> String {
     // TODO: add a group only when necessary.
     //       The simplest way may still be to properly handle anchors and add the
     //       group to the regex's AST.
-    regex = format!(r"(?P<match>{})", regex);
+    let mut regex = match match_group {
+        true => format!(r"(?P<match>{})", regex),
+        false => format!(r"(?:{})", regex),
+    };
+    let any_text = r"(?s:.)*";
 
     // If there is no prefix anchor, allow any prefix and suffix
-    if !anchor_begin {
-        regex = format!(r"(.|\s)*{}", regex);
+    if !anchor_start {
+        regex = format!(r"{}{}", any_text, regex);
     }
 
-    if !anchor_end {
-        regex = format!(r"{}(.|\s)*", regex);
+    match anchor_end {
+        EndAnchor::None => regex = format!(r"{}{}", regex, any_text),
+        EndAnchor::Text => {}
+        EndAnchor::TextOrNewline => regex = format!(r"{}\n?", regex),
     }
 
     regex
 }
 
+/// Remove the anchor `$` or `\z` at the end of a regex, if it is not escaped,
+/// and tell which one it was.
+fn strip_end_anchor(regex: &str) -> Option<(&str, EndAnchor)> {
+    let stripped = regex
+        .strip_suffix('$')
+        .or_else(|| regex.strip_suffix(r"\z"))?;
+
+    // The anchor is escaped if it follows an odd number of backslashes
+    let anchor_len = regex.len() - stripped.len();
+    let nb_backslashes = regex[..regex.len() - 1]
+        .bytes()
+        .rev()
+        .take_while(|&c| c == b'\\')
+        .count();
+
+    match (anchor_len, nb_backslashes % 2) {
+        (1, 0) => Some((stripped, EndAnchor::TextOrNewline)),
+        (2, 1) => Some((stripped, EndAnchor::Text)),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -8,8 +8,9 @@ use lib_regex;
 
 use std::ops;
 
-use super::super::automaton::Automaton;
+use super::super::automaton::{state_index, Automaton};
 use super::super::regex;
+use super::CompileError;
 use super::mapping::Mapping;
 
 //  _   _       _              ____      _     _
@@ -47,7 +48,7 @@ impl<'t> Iterator for NaiveEnumCubic<'t> {
 
     fn next(&mut self) -> Option<Mapping<'t>> {
         while let Some((curr_start, _)) = self.char_iterator_start.next() {
-            while let Some((curr_end, _)) = self.char_iterator_end.next() {
+            for (curr_end, _) in self.char_iterator_end.by_ref() {
                 let is_match = self.regex.is_match(&self.text[curr_start..curr_end]);
 
                 if is_match {
@@ -93,20 +94,20 @@ pub struct NaiveEnumQuadratic<'t> {
 }
 
 impl<'t> NaiveEnumQuadratic<'t> {
-    pub fn new(regex_str: &str, text: &'t str) -> NaiveEnumQuadratic<'t> {
-        let automaton = regex::compile_raw(regex_str);
+    pub fn new(regex_str: &str, text: &'t str) -> Result<NaiveEnumQuadratic<'t>, CompileError> {
+        let automaton = regex::compile_raw(regex_str)?;
 
         // Init automata states
         let mut initial_states = vec![false; automaton.nb_states];
         initial_states[automaton.get_initial()] = true;
 
-        NaiveEnumQuadratic {
+        Ok(NaiveEnumQuadratic {
             automaton,
             text,
             curr_states: initial_states,
             char_iterator_end: text.char_indices(),
             char_iterator_start: text.char_indices(),
-        }
+        })
     }
 }
 
@@ -133,10 +134,10 @@ impl<'t> Iterator for NaiveEnumQuadratic<'t> {
 
                 let mut new_states = vec![false; nb_states];
 
-                for i in 0..nb_states {
+                for (i, adj_i) in adj.iter().enumerate() {
                     if self.curr_states[i] {
-                        for &j in &adj[i] {
-                            new_states[j] = true;
+                        for &j in adj_i {
+                            new_states[state_index(j)] = true;
                         }
                     }
                 }
@@ -1,14 +1,20 @@
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter;
+use std::sync::Arc;
 
-use regex_syntax;
+use regex_syntax::ast::ErrorKind as AstErrorKind;
+use regex_syntax::hir::Class as LibClass;
 use regex_syntax::hir::GroupKind as LibGroup;
 use regex_syntax::hir::HirKind as LibHir;
+use regex_syntax::hir::Literal as LibLiteral;
 use regex_syntax::hir::RepetitionKind as LibRepKind;
 use regex_syntax::hir::RepetitionRange as LibRepRange;
 
 use super::super::automaton::atom::Atom;
 use super::super::automaton::Label;
 use super::super::mapping::{Marker, Variable};
+use super::{CompileError, Flags};
 
 /// A simple Hir, with branchements of arity at most 2 and at least redondancy
 /// as possible.
@@ -17,7 +23,7 @@ pub enum Hir {
     /// Empty langage
     Empty,
     /// Langage of words of length 1
-    Label(Rc<Label>), // embeded into an Rc to avoid duplicating heavy complex literals
+    Label(Arc<Label>), // embeded into an Arc to avoid duplicating heavy complex literals
     /// Concatenation of two langages
     Concat(Box<Hir>, Box<Hir>),
     /// Union of two langages
@@ -29,80 +35,189 @@ pub enum Hir {
 }
 
 impl Hir {
-    pub fn from_regex(regex: &str) -> Hir {
-        let lib_hir = regex_syntax::Parser::new()
-            .parse(regex)
-            .expect("Invalid regexp syntax");
-        let (_, hir) = Hir::from_lib_hir(lib_hir, 0);
-        hir
+    /// Parse a regex into a simplified Hir, without the implicit group *match*
+    /// added by `regex::compile`.
+    pub fn from_regex(regex: &str) -> Result<Hir, CompileError> {
+        Hir::from_regex_with_flags(regex, Flags::default())
+    }
+
+    /// Parse a regex into a simplified Hir, as `Hir::from_regex`, with some
+    /// flags enabled for the whole regex.
+    pub fn from_regex_with_flags(regex: &str, flags: Flags) -> Result<Hir, CompileError> {
+        let hir = parse_lib_hir(regex, flags)?;
+        Hir::from_lib_hir(
+            hir,
+            &mut VariableAllocator::default(),
+            &mut AtomInterner::default(),
+        )
     }
 
     /// Construct an Hir from regex_syntax's Hir format.
     ///
-    /// It also takes as an input the counter of already created variables and
-    /// return the count of variables that have been created in the generated
-    /// Hir.
-    fn from_lib_hir(hir: regex_syntax::hir::Hir, nb_ext_vars: u64) -> (u64, Hir) {
-        match hir.into_kind() {
-            LibHir::Empty => (0, Hir::epsilon()),
+    /// Variables are created through an allocator shared by the whole regex,
+    /// inner groups are given smaller ids than the groups containing them.
+    /// Equal atoms share the same label.
+    fn from_lib_hir(
+        hir: regex_syntax::hir::Hir,
+        variables: &mut VariableAllocator,
+        atoms: &mut AtomInterner,
+    ) -> Result<Hir, CompileError> {
+        if let Some(err) = unsupported(hir.kind()) {
+            return Err(err);
+        }
+
+        Ok(match hir.into_kind() {
+            LibHir::Empty => Hir::epsilon(),
 
-            LibHir::Literal(lit) => (0, Hir::label(Label::Atom(Atom::Literal(lit)))),
+            LibHir::Literal(LibLiteral::Unicode(x)) => Hir::Label(atoms.intern(Atom::Literal(x))),
 
-            LibHir::Class(class) => (0, Hir::label(Label::Atom(Atom::Class(class)))),
+            LibHir::Class(LibClass::Unicode(class)) => Hir::Label(atoms.intern(Atom::Class(class))),
 
+            // Greediness doesn't change the set of matches, see `is_lazy`.
             LibHir::Repetition(rep) => {
-                let (nb_in_vars, hir) = Hir::from_lib_hir(*rep.hir, nb_ext_vars);
-                let new_hir = match rep.kind {
+                let hir = Hir::from_lib_hir(*rep.hir, variables, atoms)?;
+                match rep.kind {
                     LibRepKind::ZeroOrOne => Hir::option(hir),
                     LibRepKind::ZeroOrMore => Hir::option(Hir::closure(hir)),
                     LibRepKind::OneOrMore => Hir::closure(hir),
                     LibRepKind::Range(range) => Hir::repetition(hir, range),
-                };
-                (nb_in_vars, new_hir)
+                }
             }
 
-            LibHir::Group(group) => {
-                let (mut nb_in_vars, subtree) = Hir::from_lib_hir(*group.hir, nb_ext_vars);
-                let new_hir = match group.kind {
-                    LibGroup::NonCapturing | LibGroup::CaptureIndex(_) => subtree,
-                    LibGroup::CaptureName { name, index: _ } => {
-                        let var = Rc::new(Variable::new(name, nb_ext_vars + nb_in_vars));
-                        let marker_open = Label::Assignation(Marker::Open(var.clone()));
-                        let marker_close = Label::Assignation(Marker::Close(var));
-                        nb_in_vars += 1;
-
-                        Hir::concat(
-                            Hir::Concat(Box::new(Hir::label(marker_open)), Box::new(subtree)),
-                            Hir::label(marker_close),
-                        )
-                    }
-                };
+            LibHir::Group(group) => match group.kind {
+                LibGroup::NonCapturing | LibGroup::CaptureIndex(_) => {
+                    Hir::from_lib_hir(*group.hir, variables, atoms)?
+                }
+                LibGroup::CaptureName { name, index } => {
+                    // The id of the group is reserved before its inner groups
+                    // are allocated, which refer to it as their parent.
+                    variables.enter(&group.hir)?;
+                    let subtree = Hir::from_lib_hir(*group.hir, variables, atoms)?;
+                    let var = variables.allocate(name, index)?;
+                    let marker_open = Label::Assignation(Marker::Open(var.clone()));
+                    let marker_close = Label::Assignation(Marker::Close(var));
+
+                    Hir::concat(
+                        Hir::Concat(Box::new(Hir::label(marker_open)), Box::new(subtree)),
+                        Hir::label(marker_close),
+                    )
+                }
+            },
+
+            // The concatenation is left-associated, which keeps the order of
+            // branches and thus of the variables created while parsing them.
+            LibHir::Concat(sub) => sub
+                .into_iter()
+                .try_fold(Hir::epsilon(), |acc_hir, branch| {
+                    Ok(Hir::concat(acc_hir, Hir::from_lib_hir(branch, variables, atoms)?))
+                })?,
+
+            // The empty language is neutral for the union, an empty branch
+            // is parsed as `LibHir::Empty` which becomes epsilon.
+            LibHir::Alternation(sub) => sub
+                .into_iter()
+                .try_fold(Hir::Empty, |acc_hir, branch| {
+                    let branch = Hir::from_lib_hir(branch, variables, atoms)?;
+                    Ok(Hir::alternation(acc_hir, branch))
+                })?,
+
+            LibHir::Literal(_) | LibHir::Class(_) | LibHir::Anchor(_) | LibHir::WordBoundary(_) => {
+                unreachable!("unsupported constructs are rejected before")
+            }
+        })
+    }
+
+    /// Factor common prefixes and suffixes of the branches of alternations,
+    /// such that `foobar|foobaz` becomes `fooba(r|z)`, which saves the states
+    /// of the shared atoms in the automaton.
+    ///
+    /// Only atoms are factored, equal atoms being identified by their shared
+    /// label. Factoring stops at the first other node, thus never crosses the
+    /// border of a group, and the language of the tree is unchanged.
+    pub fn factor_alternations(self) -> Hir {
+        match self {
+            Hir::Empty | Hir::Label(_) => self,
+            Hir::Concat(hir1, hir2) => {
+                Hir::concat(hir1.factor_alternations(), hir2.factor_alternations())
+            }
+            Hir::Option(hir) => Hir::option(hir.factor_alternations()),
+            Hir::Closure(hir) => Hir::closure(hir.factor_alternations()),
+            Hir::Alternation(hir1, hir2) => {
+                match (hir1.factor_alternations(), hir2.factor_alternations()) {
+                    // The empty language is neutral for the union
+                    (Hir::Empty, hir) | (hir, Hir::Empty) => hir,
+                    (hir1, hir2) => Hir::factor_branches(hir1, hir2),
+                }
+            }
+        }
+    }
 
-                (nb_in_vars, new_hir)
+    /// Build the alternation of two trees, where common atoms at the start and
+    /// the end of both branches are moved out of the alternation.
+    fn factor_branches(hir1: Hir, hir2: Hir) -> Hir {
+        let is_same_atom = |&(x, y): &(&Hir, &Hir)| match (x, y) {
+            (Hir::Label(label1), Hir::Label(label2)) => {
+                matches!(**label1, Label::Atom(_)) && Arc::ptr_eq(label1, label2)
             }
+            _ => false,
+        };
 
-            LibHir::Concat(sub) => sub.into_iter().fold((0, Hir::epsilon()), |acc, branch| {
-                let (acc_vars, acc_hir) = acc;
-                let (add_vars, add_hir) = Hir::from_lib_hir(branch, nb_ext_vars + acc_vars);
-                (acc_vars + add_vars, Hir::concat(acc_hir, add_hir))
-            }),
+        let mut branch1 = Vec::new();
+        let mut branch2 = Vec::new();
+        hir1.flatten_concat(&mut branch1);
+        hir2.flatten_concat(&mut branch2);
 
-            LibHir::Alternation(sub) => sub.into_iter().fold((0, Hir::Empty), |acc, branch| {
-                let (acc_vars, acc_hir) = acc;
-                let (add_vars, add_hir) = Hir::from_lib_hir(branch, nb_ext_vars + acc_vars);
-                (acc_vars + add_vars, Hir::alternation(acc_hir, add_hir))
-            }),
+        let prefix_len = branch1.iter().zip(&branch2).take_while(is_same_atom).count();
+        let suffix_len = branch1[prefix_len..]
+            .iter()
+            .rev()
+            .zip(branch2[prefix_len..].iter().rev())
+            .take_while(is_same_atom)
+            .count();
+
+        let suffix = branch1.split_off(branch1.len() - suffix_len);
+        branch2.truncate(branch2.len() - suffix_len);
+        let rest1 = branch1.split_off(prefix_len);
+        let rest2 = branch2.split_off(prefix_len);
+
+        let alternation = Hir::alternation(Hir::concat_all(rest1), Hir::concat_all(rest2));
+
+        Hir::concat_all(
+            branch1
+                .into_iter()
+                .chain(iter::once(alternation))
+                .chain(suffix)
+                .collect(),
+        )
+    }
 
-            other => panic!("Not implemented: {:?}", other),
+    /// List the factors of a concatenation, epsilons are skipped.
+    fn flatten_concat(self, factors: &mut Vec<Hir>) {
+        match self {
+            Hir::Concat(hir1, hir2) => {
+                hir1.flatten_concat(factors);
+                hir2.flatten_concat(factors);
+            }
+            Hir::Option(hir) if matches!(*hir, Hir::Empty) => {}
+            hir => factors.push(hir),
         }
     }
 
+    /// Build the left-associated concatenation of a list of factors, which is
+    /// epsilon if the list is empty.
+    fn concat_all(factors: Vec<Hir>) -> Hir {
+        factors
+            .into_iter()
+            .reduce(Hir::concat)
+            .unwrap_or_else(Hir::epsilon)
+    }
+
     fn epsilon() -> Hir {
         Hir::option(Hir::Empty)
     }
 
     fn label(label: Label) -> Hir {
-        Hir::Label(Rc::new(label))
+        Hir::Label(Arc::new(label))
     }
 
     fn option(hir: Hir) -> Hir {
@@ -131,7 +246,7 @@ impl Hir {
         let mut result = Hir::epsilon();
 
         for i in 0..min {
-            if i == min - 1 && max == None {
+            if i == min - 1 && max.is_none() {
                 // If the repetition has no upper bound, the last repetition
                 // of the input langage is replaced with a closure. It avoids
                 // a few states to do it here.
@@ -154,3 +269,339 @@ impl Hir {
         result
     }
 }
+
+/// Give ids to the variables of a regex, in order of creation. Two variables
+/// are equal iff they share the same id, thus ids must never be reused.
+///
+/// As inner groups are created first, the id of a group is reserved when the
+/// parser enters it, so that the variables created inside of it can refer to
+/// it as their parent.
+#[derive(Debug, Default)]
+struct VariableAllocator {
+    next_id:   u64,
+    names:     HashMap<u64, String>,
+    /// Ids reserved for the groups being parsed, the innermost one last.
+    enclosing: Vec<u64>,
+}
+
+impl VariableAllocator {
+    /// Reserve the id of a named group containing given tree, which will be
+    /// the next id allocated once all of its inner groups are allocated.
+    fn enter(&mut self, hir: &regex_syntax::hir::Hir) -> Result<(), CompileError> {
+        /// Count the named groups in a tree.
+        fn count_named_groups(hir: &regex_syntax::hir::Hir) -> u64 {
+            match hir.kind() {
+                LibHir::Group(group) => {
+                    let is_named = matches!(group.kind, LibGroup::CaptureName { .. });
+                    u64::from(is_named) + count_named_groups(&group.hir)
+                }
+                LibHir::Repetition(rep) => count_named_groups(&rep.hir),
+                LibHir::Concat(sub) | LibHir::Alternation(sub) => {
+                    sub.iter().map(count_named_groups).sum()
+                }
+                _ => 0,
+            }
+        }
+
+        let id = self
+            .next_id
+            .checked_add(count_named_groups(hir))
+            .ok_or_else(|| CompileError::Unsupported(String::from("too many named groups")))?;
+
+        self.enclosing.push(id);
+        Ok(())
+    }
+
+    /// Create the variable of the last group entered with its reserved id,
+    /// which is a child of the group entered before.
+    fn allocate(&mut self, name: String, index: u32) -> Result<Arc<Variable>, CompileError> {
+        let id = self.next_id;
+        self.next_id = id
+            .checked_add(1)
+            .ok_or_else(|| CompileError::Unsupported(String::from("too many named groups")))?;
+
+        let reserved = self.enclosing.pop();
+        debug_assert_eq!(reserved, Some(id), "group {:?} was allocated out of order", name);
+
+        let previous = self.names.insert(id, name.clone());
+        debug_assert!(
+            previous.is_none(),
+            "variables {:?} and {:?} share id {}",
+            previous,
+            name,
+            id
+        );
+
+        let var = Variable::new(name, id).with_index(index);
+        let var = match self.enclosing.last() {
+            Some(&parent) => var.with_parent(parent),
+            None => var,
+        };
+
+        Ok(Arc::new(var))
+    }
+}
+
+/// Share a single label between equal atoms of a regex, which avoids matching
+/// the same atom against a char for each of its occurrences.
+#[derive(Debug, Default)]
+struct AtomInterner {
+    labels: HashMap<AtomKey, Arc<Label>>,
+}
+
+/// Identify an atom by its kind and the ranges of characters it matches, a
+/// literal is never merged with a class to keep its display.
+#[derive(Debug, Eq, Hash, PartialEq)]
+enum AtomKey {
+    Literal(Vec<(char, char)>),
+    Class(Vec<(char, char)>),
+}
+
+impl AtomInterner {
+    /// Get the label reading an atom, which is created if no equal atom was
+    /// seen before.
+    fn intern(&mut self, atom: Atom) -> Arc<Label> {
+        let key = match atom {
+            Atom::Literal(_) => AtomKey::Literal(atom.ranges()),
+            Atom::Class(_) => AtomKey::Class(atom.ranges()),
+        };
+
+        self.labels
+            .entry(key)
+            .or_insert_with(|| Arc::new(Label::Atom(atom)))
+            .clone()
+    }
+}
+
+/// Parse a regex with regex_syntax's parser.
+fn parse_lib_hir(regex: &str, flags: Flags) -> Result<regex_syntax::hir::Hir, CompileError> {
+    // Atoms are matched against unicode characters, which requires unicode
+    // mode (also enabling classes such as `\p{Greek}`).
+    regex_syntax::ParserBuilder::new()
+        .unicode(true)
+        .allow_invalid_utf8(false)
+        .dot_matches_new_line(flags.dotall)
+        .build()
+        .parse(regex)
+        .map_err(|err| match &err {
+            regex_syntax::Error::Parse(parse_err)
+                if *parse_err.kind() == AstErrorKind::UnsupportedLookAround =>
+            {
+                CompileError::Unsupported(String::from("look-around"))
+            }
+            _ => CompileError::Syntax(Box::new(err)),
+        })
+}
+
+/// Check if all repetitions of a regex are non-greedy, such as `a+?`, and there
+/// is at least one of them.
+///
+/// As all matches are enumerated, greediness has no effect on the set of
+/// matches. It is only used to prefer the shortest match over the longest one
+/// when a single match must be picked among overlapping ones.
+pub fn is_lazy(regex: &str) -> Result<bool, CompileError> {
+    /// Count greedy and non-greedy repetitions of a tree.
+    fn count_repetitions(hir: &regex_syntax::hir::Hir) -> (usize, usize) {
+        match hir.kind() {
+            LibHir::Repetition(rep) => {
+                let (greedy, lazy) = count_repetitions(&rep.hir);
+                match rep.greedy {
+                    true => (greedy + 1, lazy),
+                    false => (greedy, lazy + 1),
+                }
+            }
+            LibHir::Group(group) => count_repetitions(&group.hir),
+            LibHir::Concat(sub) | LibHir::Alternation(sub) => {
+                sub.iter().map(count_repetitions).fold((0, 0), |acc, count| {
+                    (acc.0 + count.0, acc.1 + count.1)
+                })
+            }
+            _ => (0, 0),
+        }
+    }
+
+    let (greedy, lazy) = count_repetitions(&parse_lib_hir(regex, Flags::default())?);
+    Ok(greedy == 0 && lazy > 0)
+}
+
+/// Get the error given for a node of regex_syntax's Hir that can't be
+/// translated, its children are not checked.
+fn unsupported(kind: &LibHir) -> Option<CompileError> {
+    match kind {
+        LibHir::Literal(LibLiteral::Byte(_)) | LibHir::Class(LibClass::Bytes(_)) => {
+            Some(CompileError::Unsupported(String::from("byte atoms")))
+        }
+        // Anchors at the borders of the regex are removed before it is parsed,
+        // see `regex::strip_anchors`, others can't be expressed.
+        LibHir::Anchor(anchor) => Some(CompileError::Unsupported(format!("anchor {:?}", anchor))),
+        LibHir::WordBoundary(boundary) => Some(CompileError::Unsupported(format!(
+            "word boundary {:?}",
+            boundary
+        ))),
+        _ => None,
+    }
+}
+
+/// Check that a regex can be parsed into an Hir, without building it.
+pub fn check_supported(regex: &str, flags: Flags) -> Result<(), CompileError> {
+    /// Find the first unsupported construct of a tree, in the order the Hir
+    /// would be built.
+    fn check(hir: &regex_syntax::hir::Hir) -> Result<(), CompileError> {
+        match hir.kind() {
+            LibHir::Group(group) => check(&group.hir),
+            LibHir::Repetition(rep) => check(&rep.hir),
+            LibHir::Concat(sub) | LibHir::Alternation(sub) => sub.iter().try_for_each(check),
+            kind => unsupported(kind).map_or(Ok(()), Err),
+        }
+    }
+
+    check(&parse_lib_hir(regex, flags)?)
+}
+
+/// List the names of the named groups of a regex, in order of appearance.
+pub fn group_names(regex: &str, flags: Flags) -> Result<Vec<String>, CompileError> {
+    /// Append the names of the groups of a tree.
+    fn push_names(hir: &regex_syntax::hir::Hir, names: &mut Vec<String>) {
+        match hir.kind() {
+            LibHir::Group(group) => {
+                if let LibGroup::CaptureName { name, .. } = &group.kind {
+                    names.push(name.clone());
+                }
+
+                push_names(&group.hir, names)
+            }
+            LibHir::Repetition(rep) => push_names(&rep.hir, names),
+            LibHir::Concat(sub) | LibHir::Alternation(sub) => {
+                sub.iter().for_each(|hir| push_names(hir, names))
+            }
+            _ => {}
+        }
+    }
+
+    let mut names = Vec::new();
+    push_names(&parse_lib_hir(regex, flags)?, &mut names);
+    Ok(names)
+}
+
+/// Get the text matched by a regex if it only matches a single literal text,
+/// such as `abc` or `a\.(b)`. Regexes with named groups or anchors are never
+/// considered as literals.
+pub fn literal(regex: &str) -> Result<Option<String>, CompileError> {
+    /// Append the text matched by a tree, or return false if it is not a
+    /// literal.
+    fn push_literal(hir: &regex_syntax::hir::Hir, text: &mut String) -> bool {
+        match hir.kind() {
+            LibHir::Empty => true,
+            LibHir::Literal(LibLiteral::Unicode(c)) => {
+                text.push(*c);
+                true
+            }
+            LibHir::Group(group) => match group.kind {
+                LibGroup::NonCapturing | LibGroup::CaptureIndex(_) => {
+                    push_literal(&group.hir, text)
+                }
+                LibGroup::CaptureName { .. } => false,
+            },
+            LibHir::Concat(sub) => sub.iter().all(|hir| push_literal(hir, text)),
+            _ => false,
+        }
+    }
+
+    let mut text = String::new();
+
+    match push_literal(&parse_lib_hir(regex, Flags::default())?, &mut text) {
+        true => Ok(Some(text)),
+        false => Ok(None),
+    }
+}
+
+/// Display the tree with one node per line, where children are indented.
+/// Labels that are shared by several nodes, which happens for repetitions and
+/// equal atoms, are given an identifier of the form `#n`.
+impl fmt::Display for Hir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut occurrences = HashMap::new();
+        self.count_labels(&mut occurrences);
+
+        let mut shared_ids = HashMap::new();
+
+        for (ptr, count) in occurrences {
+            if count > 1 {
+                shared_ids.insert(ptr, 0);
+            }
+        }
+
+        self.fmt_indented(f, 0, &mut shared_ids, &mut 0)
+    }
+}
+
+impl Hir {
+    /// Count occurrences of each label, identified by its address.
+    fn count_labels(&self, occurrences: &mut HashMap<*const Label, usize>) {
+        match self {
+            Hir::Empty => {}
+            Hir::Label(label) => *occurrences.entry(Arc::as_ptr(label)).or_insert(0) += 1,
+            Hir::Concat(hir1, hir2) | Hir::Alternation(hir1, hir2) => {
+                hir1.count_labels(occurrences);
+                hir2.count_labels(occurrences);
+            }
+            Hir::Option(hir) | Hir::Closure(hir) => hir.count_labels(occurrences),
+        }
+    }
+
+    /// Display a subtree, shared labels are given identifiers in order of
+    /// first appearance, `0` meaning that no identifier is given yet.
+    fn fmt_indented(
+        &self,
+        f: &mut fmt::Formatter,
+        depth: usize,
+        shared_ids: &mut HashMap<*const Label, usize>,
+        nb_ids: &mut usize,
+    ) -> fmt::Result {
+        write!(f, "{}", "  ".repeat(depth))?;
+
+        let children = match self {
+            Hir::Empty => {
+                writeln!(f, "Empty")?;
+                vec![]
+            }
+            Hir::Label(label) => {
+                write!(f, "Label {}", label)?;
+
+                if let Some(id) = shared_ids.get_mut(&Arc::as_ptr(label)) {
+                    if *id == 0 {
+                        *nb_ids += 1;
+                        *id = *nb_ids;
+                    }
+
+                    write!(f, " #{}", id)?;
+                }
+
+                writeln!(f)?;
+                vec![]
+            }
+            Hir::Concat(hir1, hir2) => {
+                writeln!(f, "Concat")?;
+                vec![hir1, hir2]
+            }
+            Hir::Alternation(hir1, hir2) => {
+                writeln!(f, "Alternation")?;
+                vec![hir1, hir2]
+            }
+            Hir::Option(hir) => {
+                writeln!(f, "Option")?;
+                vec![hir]
+            }
+            Hir::Closure(hir) => {
+                writeln!(f, "Closure")?;
+                vec![hir]
+            }
+        };
+
+        for child in children {
+            child.fmt_indented(f, depth + 1, shared_ids, nb_ids)?;
+        }
+
+        Ok(())
+    }
+}
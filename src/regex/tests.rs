@@ -97,6 +97,56 @@ fn repetition() {
     assert!(!is_match(r"^(ab){4,5}$", &"ab".repeat(6)));
 }
 
+#[test]
+fn empty_branch() {
+    assert!(is_match(r"^(a|)$", "a"));
+    assert!(is_match(r"^(a|)$", ""));
+    assert!(is_match(r"^(|a)$", ""));
+    assert!(is_match(r"^(a||b)$", "b"));
+    assert!(!is_match(r"^(a|)$", "aa"));
+    assert!(is_match(r"^x(a|)y$", "xy"));
+}
+
+#[test]
+fn empty_group() {
+    use super::{compile, compile_matches};
+
+    let groups = |regex, text| -> Vec<Vec<_>> {
+        compile_matches(compile(regex).unwrap(), text)
+            .iter()
+            .map(|mapping| {
+                let mut groups: Vec<_> = mapping
+                    .iter_groups()
+                    .map(|(name, range)| (name.to_string(), range))
+                    .collect();
+                groups.sort_by(|a, b| a.0.cmp(&b.0));
+                groups
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        groups(r"a(?P<x>)b", "cab"),
+        vec![vec![("match".to_string(), 1..3), ("x".to_string(), 2..2)]]
+    );
+    assert_eq!(
+        groups(r"^(?P<x>)$", ""),
+        vec![vec![("match".to_string(), 0..0), ("x".to_string(), 0..0)]]
+    );
+}
+
+#[test]
+fn lazy() {
+    use super::parse::is_lazy;
+
+    assert!(is_lazy(r"a+?").unwrap());
+    assert!(is_lazy(r"(?P<x>a*?)b{2,}?").unwrap());
+    assert!(!is_lazy(r"a+").unwrap());
+    assert!(!is_lazy(r"a+?b*").unwrap());
+    assert!(!is_lazy(r"ab").unwrap());
+    assert!(is_lazy(r"a(").is_err());
+}
+
 #[test]
 fn begin_token() {
     assert!(is_match(r"^foo", "foobar"));
@@ -110,3 +160,522 @@ fn end_token() {
     assert!(is_match(r"foo", "foobar"));
     assert!(!is_match(r"foo$", "foobar"));
 }
+
+#[test]
+fn end_token_final_newline() {
+    use super::find_all;
+
+    let spans = |regex, text| find_all(regex, text).unwrap().iter_spans().collect::<Vec<_>>();
+
+    assert_eq!(spans(r"foo$", "foo"), vec![0..3]);
+    assert_eq!(spans(r"foo$", "foo\n"), vec![0..3]);
+    assert!(spans(r"foo$", "foobar").is_empty());
+
+    // Only a single final line break is allowed
+    assert!(spans(r"foo$", "foo\n\n").is_empty());
+    assert!(spans(r"foo$", "foo\nbar").is_empty());
+    assert!(!is_match(r"foo\z", "foo\n"));
+}
+
+#[test]
+fn text_anchors() {
+    assert!(is_match(r"\Afoo\z", "foo"));
+    assert!(!is_match(r"\Afoo\z", "xfoo"));
+    assert!(!is_match(r"\Afoo\z", "foox"));
+    assert!(is_match(r"\Afoo", "foobar"));
+    assert!(is_match(r"bar\z", "foobar"));
+
+    // Escaped anchors are regular characters
+    assert!(is_match(r"foo\$", "foo$bar"));
+    assert!(is_match(r"foo\\z", "foo\\zbar"));
+}
+
+#[test]
+fn unicode_properties() {
+    assert!(is_match(r"^\p{Greek}+$", "αλφα"));
+    assert!(!is_match(r"^\p{Greek}+$", "alpha"));
+    assert!(is_match(r"^\p{L}+$", "héllo"));
+    assert!(!is_match(r"\p{Greek}", "alpha"));
+}
+
+#[test]
+fn unsupported() {
+    use super::{compile, CompileError};
+
+    match compile(r"foo\b") {
+        Err(CompileError::Unsupported(construct)) => assert!(construct.contains("word boundary")),
+        _ => panic!("Word boundaries should be unsupported"),
+    }
+
+    match compile(r"foo(?=bar)") {
+        Err(CompileError::Unsupported(construct)) => assert_eq!(construct, "look-around"),
+        _ => panic!("Lookaheads should be unsupported"),
+    }
+
+    match compile(r"(?m)a^b") {
+        Err(CompileError::Unsupported(construct)) => assert!(construct.contains("anchor")),
+        _ => panic!("Line anchors should be unsupported"),
+    }
+
+    // Text anchors are only supported at the borders of the pattern
+    for pattern in &[r"a\Ab", r"a$b", r"foo|^bar", r"(?:a\z)b"] {
+        match compile(pattern) {
+            Err(CompileError::Unsupported(construct)) => assert!(construct.contains("anchor")),
+            _ => panic!("{}: inner anchors should be unsupported", pattern),
+        }
+    }
+
+    assert!(matches!(compile(r"a("), Err(CompileError::Syntax(_))));
+
+    // Empty classes, which would make the language empty, are already rejected
+    assert!(matches!(compile(r"[^\s\S]"), Err(CompileError::Syntax(_))));
+}
+
+#[test]
+fn validate() {
+    use super::{compile, validate, CompileError};
+
+    let patterns = [
+        r"(?P<x>a+)b|c",
+        r"^\w+@\w+$",
+        r"(?s).(?-s:.)",
+        r"a(",
+        r"a{2,1}",
+        r"foo\b",
+        r"foo(?=bar)",
+        r"(?m)a^b",
+        r"(?-u:\xFF)",
+        r"(?P<match>a)",
+        r"a$b",
+        r"foo|^bar",
+    ];
+
+    // Same result as a compilation, with the same error
+    for pattern in &patterns {
+        match (validate(pattern), compile(pattern)) {
+            (Ok(()), Ok(_)) => {}
+            (Err(err), Err(expected)) => assert_eq!(err.to_string(), expected.to_string()),
+            (result, expected) => panic!(
+                "{}: validated as {:?}, compiled as {:?}",
+                pattern,
+                result,
+                expected.map(|_| ())
+            ),
+        }
+    }
+
+    assert!(validate(r"(?P<x>a+)b|c").is_ok());
+    assert!(matches!(validate(r"a("), Err(CompileError::Syntax(_))));
+    assert!(matches!(validate(r"foo\b"), Err(CompileError::Unsupported(_))));
+    assert!(matches!(validate(r"a\Ab"), Err(CompileError::Unsupported(_))));
+    assert!(validate(r"(?-u:\xFF)").is_err());
+
+    // Large repetitions are not expanded
+    assert!(validate(r"(?:(?:a{1000}){1000}){1000}").is_ok());
+}
+
+#[test]
+fn display_hir() {
+    use super::parse::Hir;
+
+    let expected = [
+        "Concat",
+        "  Concat",
+        "    Option",
+        "      Empty",
+        "    Label 'a'",
+        "  Option",
+        "    Closure",
+        "      Alternation",
+        "        Alternation",
+        "          Empty",
+        "          Label 'b'",
+        "        Label 'c'",
+    ];
+
+    let hir = Hir::from_regex(r"a(b|c)*").unwrap();
+    assert_eq!(hir.to_string(), expected.join("\n") + "\n");
+
+    // Repetitions share their labels
+    let hir = Hir::from_regex(r"ab{2}").unwrap().to_string();
+    assert_eq!(hir.matches("Label 'a'\n").count(), 1);
+    assert_eq!(hir.matches("Label 'b' #1\n").count(), 2);
+}
+
+#[test]
+fn distinct_variable_ids() {
+    use super::compile_raw;
+
+    let automaton = compile_raw(r"(?P<a>x(?P<b>y))|(?P<c>z)*(?P<d>(?P<e>w)+)").unwrap();
+    let mut ids: Vec<_> = automaton.variables().iter().map(|var| var.get_id()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 5);
+}
+
+#[test]
+fn concat_order() {
+    use super::parse::Hir;
+    use super::{compile, compile_matches};
+
+    assert!(is_match(r"^abc$", "abc"));
+
+    for text in ["cba", "bac", "acb", "ab", "bc"].iter() {
+        assert!(!is_match(r"^abc$", text));
+    }
+
+    // Concatenations are folded into a left-associated tree
+    let hir = Hir::from_regex(r"abc").unwrap().to_string();
+    let labels: Vec<_> = hir.lines().filter(|line| line.contains("Label")).collect();
+    assert_eq!(labels, vec!["      Label 'a'", "    Label 'b'", "  Label 'c'"]);
+
+    // Groups are listed in the order they appear, the implicit group *match*
+    // coming first as it contains the whole pattern
+    let compiled = compile_matches(compile(r"(?P<a>.)(?P<b>.)").unwrap(), "xy");
+    let mapping = compiled.iter().next().unwrap();
+    assert_eq!(
+        mapping.iter_groups().collect::<Vec<_>>(),
+        vec![("match", 0..2), ("a", 0..1), ("b", 1..2)]
+    );
+}
+
+#[test]
+fn capture_index() {
+    use super::{compile, compile_matches, compile_with_flags, Flags};
+
+    // Groups are sorted by position, whatever their names
+    let compiled = compile_matches(compile(r"(?P<b>.)(?P<a>.)").unwrap(), "xy");
+    let mapping = compiled.iter().next().unwrap();
+    assert_eq!(
+        mapping.iter_groups().collect::<Vec<_>>(),
+        vec![("match", 0..2), ("b", 0..1), ("a", 1..2)]
+    );
+
+    // Groups are numbered as usual regex engines do, including unnamed ones
+    let flags = Flags {
+        no_match_group: true,
+        ..Flags::default()
+    };
+    let automaton = compile_with_flags(r"(?P<b>(.)(?P<c>.))(?P<a>.)", flags).unwrap();
+    let indexes: Vec<_> = automaton
+        .variables()
+        .into_iter()
+        .map(|var| (var.get_name().to_string(), var.get_index()))
+        .collect();
+    assert_eq!(
+        indexes,
+        vec![
+            ("b".to_string(), Some(1)),
+            ("c".to_string(), Some(3)),
+            ("a".to_string(), Some(4))
+        ]
+    );
+}
+
+#[test]
+fn group_tree() {
+    use super::super::mapping::GroupNode;
+    use super::{compile, compile_matches};
+
+    let node = |name: &str, span, children| GroupNode {
+        name: name.to_string(),
+        span,
+        children,
+    };
+
+    // Each variable knows the group containing it in the pattern
+    let automaton = compile(r"((?P<inner>a)(?P<other>b))(?P<outer>(?P<deep>c)d)").unwrap();
+    let parents: Vec<_> = automaton
+        .variables()
+        .into_iter()
+        .map(|var| (var.get_name().to_string(), var.get_parent()))
+        .collect();
+    assert_eq!(
+        parents,
+        vec![
+            ("match".to_string(), None),
+            ("inner".to_string(), Some(4)),
+            ("other".to_string(), Some(4)),
+            ("outer".to_string(), Some(4)),
+            ("deep".to_string(), Some(3)),
+        ]
+    );
+
+    let compiled = compile_matches(automaton, "xabcd");
+    let mapping = compiled.iter().next().unwrap();
+    assert_eq!(
+        mapping.group_tree(),
+        node(
+            "match",
+            1..5,
+            vec![
+                node("inner", 1..2, vec![]),
+                node("other", 2..3, vec![]),
+                node("outer", 3..5, vec![node("deep", 3..4, vec![])]),
+            ]
+        )
+    );
+}
+
+#[test]
+fn dotall() {
+    use super::{compile_matches, compile_with_flags, Flags};
+
+    assert!(is_match(r"(?s)a.b", "a\nb"));
+    assert!(!is_match(r"a.b", "a\nb"));
+
+    let flags = Flags {
+        dotall: true,
+        ..Flags::default()
+    };
+    let is_match_dotall = |regex, text| {
+        let automaton = compile_with_flags(regex, flags).unwrap();
+        compile_matches(automaton, text).iter().next().is_some()
+    };
+
+    assert!(is_match_dotall(r"a.b", "a\nb"));
+    assert!(is_match_dotall(r"a.b", "x\naxb\n"));
+    assert!(!is_match_dotall(r"(?-s)a.b", "a\nb"));
+
+    // Dots of the pattern follow the flag, while the prefix and suffix added
+    // to find matches anywhere can always contain a line break.
+    let count = |regex, flags| {
+        let automaton = compile_with_flags(regex, flags).unwrap();
+        compile_matches(automaton, "a\nb").iter().count()
+    };
+
+    assert_eq!(count(r"b", Flags::default()), 1);
+    assert_eq!(count(r"b", flags), 1);
+    assert_eq!(count(r"a", Flags::default()), 1);
+    assert_eq!(count(r".", Flags::default()), 2);
+    assert_eq!(count(r".", flags), 3);
+    assert_eq!(count(r"a.*", Flags::default()), 1);
+    assert_eq!(count(r"a.*", flags), 3);
+    assert_eq!(count(r"(?-s:.)", flags), 2);
+}
+
+#[test]
+fn no_match_group() {
+    use super::{compile, compile_matches, compile_with_flags, CompileError, Flags};
+
+    let flags = Flags {
+        no_match_group: true,
+        ..Flags::default()
+    };
+
+    // Only groups of the pattern are assigned
+    let compiled = compile_matches(compile_with_flags(r"(?P<x>a)b?", flags).unwrap(), "abac");
+    let mut groups: Vec<Vec<_>> = compiled
+        .iter()
+        .map(|mapping| {
+            mapping
+                .iter_groups()
+                .map(|(name, range)| (name.to_string(), range.start, range.end))
+                .collect()
+        })
+        .collect();
+    groups.sort();
+    assert_eq!(groups, vec![vec![("x".to_string(), 0, 1)], vec![("x".to_string(), 2, 3)]]);
+
+    // A match without any group spans the whole input
+    let compiled = compile_matches(compile_with_flags(r"a", flags).unwrap(), "bab");
+    let mappings: Vec<_> = compiled.iter().collect();
+    assert_eq!(mappings.len(), 1);
+    assert!(mappings[0].is_empty());
+    assert_eq!(mappings[0].main_span(), 0..3);
+
+    // The name of the implicit group can only be used without it
+    let compiled = compile_matches(compile_with_flags(r"(?P<match>a)", flags).unwrap(), "ba");
+    let mapping = compiled.iter().next().unwrap();
+    assert_eq!(mapping.iter_groups().collect::<Vec<_>>(), vec![("match", 1..2)]);
+
+    match compile(r"x(?P<match>a)") {
+        Err(err @ CompileError::ReservedName(_)) => {
+            assert!(err.to_string().contains("`match` is reserved"))
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn find_all() {
+    use super::find_all;
+
+    let matches = find_all(r"\d+", "a1b22").unwrap();
+    let mut texts = Vec::new();
+
+    for mapping in &matches {
+        texts.push(mapping.group_text("match").unwrap());
+    }
+
+    texts.sort_unstable();
+    assert_eq!(texts, vec!["1", "2", "2", "22"]);
+    assert!(find_all(r"(", "a").is_err());
+}
+
+#[test]
+fn union_patterns() {
+    use super::{find_all, read_patterns, union};
+
+    let patterns = read_patterns("# digits\n\\d+\n\n[ab]\n");
+    assert_eq!(patterns, vec![r"\d+", "[ab]"]);
+    assert_eq!(union(&["a+"]), "a+");
+
+    let matches = find_all(&union(&patterns), "a1b22").unwrap();
+    let mut texts = Vec::new();
+
+    for mapping in &matches {
+        let pattern = mapping
+            .iter_groups()
+            .map(|(name, _)| name.to_string())
+            .find(|name| name != "match")
+            .unwrap();
+        texts.push((pattern, mapping.group_text("match").unwrap()));
+    }
+
+    texts.sort_unstable();
+    assert_eq!(
+        texts,
+        vec![
+            ("pattern1".to_string(), "1"),
+            ("pattern1".to_string(), "2"),
+            ("pattern1".to_string(), "2"),
+            ("pattern1".to_string(), "22"),
+            ("pattern2".to_string(), "a"),
+            ("pattern2".to_string(), "b"),
+        ]
+    );
+}
+
+#[test]
+fn literal() {
+    use super::literal::LiteralMatcher;
+    use super::{as_literal, find_all, Flags};
+
+    let literal = |regex| as_literal(regex, Flags::default());
+    assert_eq!(literal("abc"), Some("abc".to_string()));
+    assert_eq!(literal(r"a\.(?:b)(c)"), Some("a.bc".to_string()));
+    assert_eq!(literal(""), Some("".to_string()));
+    assert_eq!(literal("a(?P<x>b)"), None);
+    assert_eq!(literal("ab+"), None);
+    assert_eq!(literal("^ab"), None);
+    assert_eq!(literal("(?i)ab"), None);
+
+    let anchored = Flags {
+        anchor_start: true,
+        ..Flags::default()
+    };
+    assert_eq!(as_literal("abc", anchored), None);
+
+    for &(regex, text) in &[
+        ("abc", "abcabcabxabc abc"),
+        ("aa", "aaaa"),
+        ("é", "aéé"),
+        ("", "ab"),
+        ("abc", "ab"),
+    ] {
+        let mut expected: Vec<_> = find_all(regex, text).unwrap().iter().collect();
+        let mut spans: Vec<_> = LiteralMatcher::new(regex, text).collect();
+        expected.sort();
+        spans.sort();
+        assert_eq!(spans, expected);
+    }
+}
+
+#[test]
+fn anchor_flags() {
+    use super::{compile_matches, compile_with_flags, Flags};
+
+    let spans = |anchor_start, anchor_end| {
+        let flags = Flags {
+            anchor_start,
+            anchor_end,
+            ..Flags::default()
+        };
+        let automaton = compile_with_flags(r"x?foox?", flags).unwrap();
+        let mut spans: Vec<_> = compile_matches(automaton, "xfoox").iter_spans().collect();
+        spans.sort_by_key(|span| (span.start, span.end));
+        spans
+    };
+
+    assert_eq!(spans(false, false), vec![0..4, 0..5, 1..4, 1..5]);
+    assert_eq!(spans(true, false), vec![0..4, 0..5]);
+    assert_eq!(spans(false, true), vec![0..5, 1..5]);
+    assert_eq!(spans(true, true), vec![0..5]);
+
+    // Anchors in the regex are still taken into account
+    let flags = Flags {
+        anchor_end: true,
+        ..Flags::default()
+    };
+    let automaton = compile_with_flags(r"^x?foo", flags).unwrap();
+    assert_eq!(compile_matches(automaton, "xfoox").iter().count(), 0);
+}
+
+#[test]
+fn factor_alternations() {
+    use super::super::mapping::naive::NaiveEnum;
+    use super::parse::Hir;
+    use super::{compile_raw, glushkov};
+    use std::collections::HashSet;
+
+    let unfactored = |regex| {
+        let hir = Hir::from_regex(regex).unwrap();
+        glushkov::LocalLang::from_hir(hir, 0).into_automaton()
+    };
+
+    let factored = compile_raw(r"foobar|foobaz").unwrap();
+    assert!(factored.get_nb_states() < unfactored(r"foobar|foobaz").get_nb_states());
+    assert_eq!(factored.get_nb_states(), 8);
+    assert!(is_match(r"foobar|foobaz", "foobar"));
+    assert!(is_match(r"foobar|foobaz", "foobaz"));
+    assert!(!is_match(r"^(foobar|foobaz)$", "fooba"));
+
+    let hir = Hir::from_regex(r"xa|ya").unwrap().factor_alternations();
+    assert_eq!(hir.to_string().matches("Label 'a'").count(), 1);
+
+    // Groups are never factored, neither are the atoms they contain
+    let regexes = [
+        r"foobar|foobaz|foobat",
+        r"(?P<x>ab)c|abd",
+        r"a(?P<x>b)c|a(?P<y>b)c",
+        r"ab|a|b",
+        r"(?P<x>a)b|(?P<y>a)c",
+        r"a*b|a*c",
+    ];
+
+    for regex in regexes.iter() {
+        let factored = compile_raw(regex).unwrap();
+        let unfactored = unfactored(regex);
+
+        for text in ["", "a", "b", "ab", "abc", "abd", "ac", "aab", "foobat"].iter() {
+            let expected: HashSet<_> = NaiveEnum::new(&unfactored, text).collect();
+            let mappings: HashSet<_> = NaiveEnum::new(&factored, text).collect();
+            assert_eq!(mappings, expected, "{} over {:?}", regex, text);
+        }
+    }
+}
+
+#[test]
+fn shared_atoms() {
+    use super::compile_raw;
+    use super::parse::Hir;
+
+    // Atoms are not at the border of both branches, thus are not factored
+    let automaton = compile_raw(r"[a-z]0|1[a-z]").unwrap();
+    let labels: Vec<_> = automaton
+        .iter_transitions()
+        .map(|(_, label, _)| label)
+        .filter(|label| label.to_string() == "[a-z]")
+        .collect();
+    assert_eq!(labels.len(), 2);
+    assert!(std::ptr::eq(labels[0], labels[1]));
+    assert_eq!(automaton.get_adj_for_char('q').iter().flatten().count(), 2);
+    assert!(is_match(r"^([a-z]0|1[a-z])$", "q0"));
+    assert!(is_match(r"^([a-z]|[a-z])$", "q"));
+
+    // Literals and classes matching the same chars are kept apart
+    let hir = Hir::from_regex(r"a[a]a").unwrap().to_string();
+    assert_eq!(hir.matches("Label 'a' #1\n").count(), 2);
+    assert_eq!(hir.matches("Label [a-a]\n").count(), 1);
+}
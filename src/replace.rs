@@ -0,0 +1,225 @@
+use std::ops::Range;
+
+use super::mapping::Mapping;
+
+//  _____                    _       _
+// |_   _|__ _ __ ___  _ __ | | __ _| |_ ___
+//   | |/ _ \ '_ ` _ \| '_ \| |/ _` | __/ _ \
+//   | |  __/ | | | | | |_) | | (_| | ||  __/
+//   |_|\___|_| |_| |_| .__/|_|\__,_|\__\___|
+//                    |_|
+
+/// A replacement pattern, where `${name}` is substituted with the text of the
+/// group *name* and `$$` with a single `$`.
+///
+/// A group which is not assigned by a mapping is replaced with an empty text,
+/// and a `$` which is not followed by a valid group name is kept as is.
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Group(String),
+}
+
+impl Template {
+    pub fn new(template: &str) -> Template {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut remaining = template;
+
+        while let Some(index) = remaining.find('$') {
+            text.push_str(&remaining[..index]);
+            remaining = &remaining[index..];
+
+            if let Some(next) = remaining.strip_prefix("$$") {
+                text.push('$');
+                remaining = next;
+                continue;
+            }
+
+            let group_end = remaining.strip_prefix("${").and_then(|group| group.find('}'));
+
+            match group_end {
+                Some(end) => {
+                    if !text.is_empty() {
+                        parts.push(Part::Text(text));
+                        text = String::new();
+                    }
+
+                    parts.push(Part::Group(remaining[2..end + 2].to_string()));
+                    remaining = &remaining[end + 3..];
+                }
+                None => {
+                    text.push('$');
+                    remaining = &remaining[1..];
+                }
+            }
+        }
+
+        text.push_str(remaining);
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Template { parts }
+    }
+
+    /// Build the replacement text for a mapping.
+    pub fn expand(&self, mapping: &Mapping) -> String {
+        let mut ret = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => ret.push_str(text),
+                Part::Group(name) => {
+                    if let Some((_, text)) = mapping
+                        .iter_groups_text()
+                        .find(|(group, _)| group == name)
+                    {
+                        ret.push_str(text);
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+}
+
+//  ____            _
+// |  _ \ ___ _ __ | | __ _  ___ ___
+// | |_) / _ \ '_ \| |/ _` |/ __/ _ \
+// |  _ <  __/ |_) | | (_| | (_|  __/
+// |_| \_\___| .__/|_|\__,_|\___\___|
+//           |_|
+
+/// Which match is selected among overlapping matches starting at the same
+/// position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Preference {
+    Longest,
+    Shortest,
+}
+
+/// Select leftmost non-overlapping matches: the match starting first is
+/// selected, the longest or shortest one if there are several, then the
+/// process is repeated after its end. If several mappings share the selected
+/// span, the smallest one for the ordering of mappings is used.
+///
+/// An empty match right after a selected match is skipped.
+pub fn select_leftmost<'t, T>(mappings: T, preference: Preference) -> Vec<Mapping<'t>>
+where
+    T: Iterator<Item = Mapping<'t>>,
+{
+    let mut mappings: Vec<(Range<usize>, Mapping)> = mappings
+        .map(|mapping| (mapping.main_span(), mapping))
+        .collect();
+
+    mappings.sort_by(|(span_1, mapping_1), (span_2, mapping_2)| {
+        let ends = match preference {
+            Preference::Longest => span_2.end.cmp(&span_1.end),
+            Preference::Shortest => span_1.end.cmp(&span_2.end),
+        };
+
+        span_1
+            .start
+            .cmp(&span_2.start)
+            .then(ends)
+            .then_with(|| mapping_1.cmp(mapping_2))
+    });
+
+    let mut selected = Vec::new();
+    let mut last_end = None;
+
+    for (span, mapping) in mappings {
+        match last_end {
+            Some(end) if span.start < end || (span.start == end && span.is_empty()) => continue,
+            _ => {}
+        }
+
+        last_end = Some(span.end);
+        selected.push(mapping);
+    }
+
+    selected
+}
+
+/// Substitute matches of a text with a template.
+///
+/// As matches may overlap, only the leftmost-longest non-overlapping matches
+/// are replaced, as defined in `select_leftmost`.
+pub fn replace_all<'t, T>(text: &str, mappings: T, template: &Template) -> String
+where
+    T: Iterator<Item = Mapping<'t>>,
+{
+    let mut ret = String::new();
+    let mut last_end = 0;
+
+    for mapping in select_leftmost(mappings, Preference::Longest) {
+        let span = mapping.main_span();
+        ret.push_str(&text[last_end..span.start]);
+        ret.push_str(&template.expand(&mapping));
+        last_end = span.end;
+    }
+
+    ret.push_str(&text[last_end..]);
+    ret
+}
+
+//  _____         _
+// |_   _|__  ___| |_ ___
+//   | |/ _ \/ __| __/ __|
+//   | |  __/\__ \ |_\__ \
+//   |_|\___||___/\__|___/
+//
+
+#[cfg(test)]
+mod tests {
+    use super::super::regex;
+    use super::{replace_all, select_leftmost, Preference, Template};
+
+    /// Replace all matches of a regex in a text.
+    fn replace(regex: &str, text: &str, template: &str) -> String {
+        let compiled = regex::compile_matches(regex::compile(regex).unwrap(), text);
+        replace_all(text, compiled.iter(), &Template::new(template))
+    }
+
+    #[test]
+    fn digits() {
+        assert_eq!(replace(r"(?P<y>\d+)", "a12b3", "<${y}>"), "a<12>b<3>");
+        assert_eq!(replace(r"(?P<y>\d+)", "abc", "<${y}>"), "abc");
+    }
+
+    #[test]
+    fn groups() {
+        let regex = r"(?P<login>\w+)@(?P<server>\w+)";
+        let text = "mail bob@home now";
+        assert_eq!(replace(regex, text, "${server}/${login}"), "mail home/bob now");
+        assert_eq!(replace(regex, text, "${match}:${missing}"), "mail bob@home: now");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(replace(r"b", "abc", "$$"), "a$c");
+        assert_eq!(replace(r"b", "abc", "${"), "a${c");
+        assert_eq!(replace(r"b", "abc", "$x"), "a$xc");
+    }
+
+    #[test]
+    fn leftmost() {
+        let spans = |regex, text, preference| -> Vec<_> {
+            let compiled = regex::compile_matches(regex::compile(regex).unwrap(), text);
+            select_leftmost(compiled.iter(), preference)
+                .iter()
+                .map(|mapping| mapping.main_span())
+                .collect()
+        };
+
+        assert_eq!(spans(r"a+", "aaba", Preference::Longest), vec![0..2, 3..4]);
+        assert_eq!(spans(r"a+", "aaba", Preference::Shortest), vec![0..1, 1..2, 3..4]);
+        assert_eq!(spans(r"a*", "ba", Preference::Longest), vec![0..0, 1..2]);
+    }
+}
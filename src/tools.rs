@@ -3,8 +3,8 @@ use std::iter;
 /// Return an iterator over the elements of a range that are not part if
 /// elements of an input iterator.
 ///
-/// Note: This function assumes that all elements are inside of the input
-/// range.
+/// Input elements may be unsorted or repeated, but they must all be inside of
+/// the input range.
 pub fn iter_complement<U>(
     start: usize,
     end: usize,
@@ -14,7 +14,17 @@ where
     U: Iterator<Item = usize>,
 {
     let mut del_elements: Vec<_> = iterator.collect();
-    del_elements.sort();
+    del_elements.sort_unstable();
+    del_elements.dedup();
+
+    debug_assert!(
+        del_elements.iter().all(|&index| start <= index && index < end),
+        "Elements {:?} are not all in range [{}, {})",
+        del_elements,
+        start,
+        end
+    );
+
     del_elements
         .into_iter()
         .chain(iter::once(end))
@@ -25,3 +35,42 @@ where
         })
         .flatten()
 }
+
+//  _____         _
+// |_   _|__  ___| |_ ___
+//   | |/ _ \/ __| __/ __|
+//   | |  __/\__ \ |_\__ \
+//   |_|\___||___/\__|___/
+//
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::iter_complement;
+
+    #[test]
+    fn complement() {
+        let kept: Vec<_> = iter_complement(2, 8, vec![6, 3, 4].into_iter()).collect();
+        assert_eq!(kept, vec![2, 5, 7]);
+
+        let kept: Vec<_> = iter_complement(0, 3, iter::empty()).collect();
+        assert_eq!(kept, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn duplicates() {
+        let kept: Vec<_> = iter_complement(0, 6, vec![4, 1, 4, 1, 1].into_iter()).collect();
+        assert_eq!(kept, vec![0, 2, 3, 5]);
+
+        let kept: Vec<_> = iter_complement(0, 2, vec![0, 1, 1, 0].into_iter()).collect();
+        assert!(kept.is_empty());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "are not all in range [0, 5)")]
+    fn out_of_range() {
+        iter_complement(0, 5, vec![1, 5].into_iter()).for_each(drop);
+    }
+}
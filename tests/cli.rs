@@ -0,0 +1,566 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+
+/// Run the binary with given arguments and text as standard input.
+fn run(args: &[&str], text: &str) -> Output {
+    run_bytes(args, text.as_bytes())
+}
+
+/// Run the binary with given arguments and raw bytes as standard input.
+fn run_bytes(args: &[&str], input: &[u8]) -> Output {
+    run_in(&env::temp_dir(), args, input)
+}
+
+/// Run the binary from a working directory, see `run_bytes`.
+fn run_in(dir: &Path, args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_enum-spanner-rs"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run the binary");
+
+    // The binary may exit before reading its input, on usage errors for example
+    match child.stdin.take().unwrap().write_all(input) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        res => res.unwrap(),
+    }
+
+    child.wait_with_output().unwrap()
+}
+
+/// Write a file in the temporary directory and return its path.
+fn temp_file(name: &str, content: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("enum-spanner-{}-{}", process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn exit_status() {
+    assert_eq!(run(&["a+"], "baab").status.code(), Some(0));
+    assert_eq!(run(&["c+"], "baab").status.code(), Some(1));
+    assert_eq!(run(&["a+", "/non/existing/file"], "").status.code(), Some(2));
+
+    // Usage errors are errors, while help and version are not
+    assert_eq!(run(&["--unknown-option", "a+"], "a").status.code(), Some(2));
+    assert_eq!(run(&[], "a").status.code(), Some(2));
+    assert_eq!(run(&["--help"], "").status.code(), Some(0));
+    assert_eq!(run(&["--version"], "").status.code(), Some(0));
+}
+
+#[test]
+fn invalid_number() {
+    // Invalid values are reported by clap instead of panicking
+    let output = run(&["--benchmark-iterations", "x", "a"], "a");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--benchmark-iterations"));
+    assert!(stderr.contains("`x` is not a valid number"));
+
+    let output = run(&["--clean-every", "-1", "a"], "a");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("--clean-every"));
+
+    let output = run(&["--clean-every", "0", "a"], "a");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--clean-every"));
+    assert!(stderr.contains("`0` is not a positive number"));
+
+    let output = run(&["--no-clean", "--clean-every", "2", "a"], "a");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn unsupported_regex() {
+    let output = run(&[r"foo\b"], "foo");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("unsupported construct"));
+}
+
+#[test]
+fn ill_formed_mapping() {
+    // Repeating `x` assigns it twice for "aa", other matches are still output
+    let output = run(&["-c", "(?P<x>a)+"], "aa");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("already assigned"));
+
+    let output = run(&["--spans-only", "(?P<o>(?P<x>a){1,3}b)"], "aabc");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1 - 1,3\n");
+}
+
+#[test]
+fn quiet() {
+    let output = run(&["--quiet", "a+"], "baab");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["-q", "c+"], "baab");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn multiple_files() {
+    let file_1 = temp_file("multiple_files_1.txt", "ab");
+    let file_2 = temp_file("multiple_files_2.txt", "b");
+    let file_1 = file_1.to_str().unwrap();
+    let file_2 = file_2.to_str().unwrap();
+
+    let output = run(&["--count", "b", file_1, file_2], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, format!("{}:1\n{}:1\n", file_1, file_2));
+
+    let output = run(&["--count", "a", file_1, file_2], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, format!("{}:1\n{}:0\n", file_1, file_2));
+
+    let output = run(&["a", file_1, file_2], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, format!("{}:1 - match:\"a\"\n", file_1));
+
+    let output = run(&["--no-filename", "--count", "b", file_1, file_2], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "1\n1\n");
+
+    let output = run(&["--with-filename", "--count", "b", file_2], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, format!("{}:1\n", file_2));
+
+    fs::remove_file(file_1).unwrap();
+    fs::remove_file(file_2).unwrap();
+}
+
+#[test]
+fn all_substrings() {
+    // Output is buffered, every match must still be written once flushed
+    let text = "lorem ipsum ".repeat(20);
+    let output = run(&[r"(.|\n)+"], &text);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected = text.len() * (text.len() + 1) / 2;
+    assert_eq!(stdout.lines().count(), expected);
+    assert!(stdout.lines().last().unwrap().starts_with(&format!("{} -", expected)));
+}
+
+#[test]
+fn stable_group_order() {
+    let outputs: Vec<_> = (0..5)
+        .map(|_| run(&[r"(?P<b>.)(?P<a>.)"], "xyz").stdout)
+        .collect();
+    assert!(outputs.windows(2).all(|pair| pair[0] == pair[1]));
+
+    let stdout = String::from_utf8(outputs[0].clone()).unwrap();
+    assert!(stdout.contains(r#" - match:"xy" b:"x" a:"y""#));
+}
+
+#[test]
+fn full_match() {
+    let output = run(&["--full-match", "a+"], "aaa");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    assert_eq!(run(&["-x", "a+"], "aab").status.code(), Some(1));
+    assert_eq!(run(&["-x", "a|b+"], "bb").status.code(), Some(0));
+}
+
+#[test]
+fn dotall() {
+    assert_eq!(run(&["-c", "a.b"], "a\nb").stdout, b"0\n");
+    assert_eq!(run(&["-c", "--dotall", "a.b"], "a\nb").stdout, b"1\n");
+    assert_eq!(run(&["-c", "(?s)a.b"], "a\nb").stdout, b"1\n");
+}
+
+#[test]
+fn no_match_group() {
+    let output = run(&["--no-match-group", "(?P<x>a)"], "ab");
+    assert_eq!(output.stdout, b"1 - x:\"a\"\n");
+
+    let output = run(&["(?P<match>a)"], "ab");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("reserved"));
+}
+
+#[test]
+fn json_array() {
+    let parse = |args: &[&str], text: &str| -> serde_json::Value {
+        let output = run(args, text);
+        serde_json::from_slice(&output.stdout).unwrap()
+    };
+
+    let value = parse(&["--json-array", "a(?P<x>b)?"], "ab ab");
+    let objects = value.as_array().unwrap();
+    assert_eq!(objects.len(), 4);
+    assert!(objects.contains(&serde_json::json!({
+        "match": "ab",
+        "span": [3, 5],
+        "groups": {"match": [3, 5], "x": [4, 5]},
+    })));
+    assert!(objects.contains(&serde_json::json!({
+        "match": "a",
+        "span": [0, 1],
+        "groups": {"match": [0, 1], "x": null},
+    })));
+
+    assert_eq!(parse(&["--json-array", "a"], "bc"), serde_json::json!([]));
+
+    // Matches of all files are part of a single array
+    let file_1 = temp_file("json_array_1.txt", "ab");
+    let file_2 = temp_file("json_array_2.txt", "b");
+    let file_1 = file_1.to_str().unwrap();
+    let file_2 = file_2.to_str().unwrap();
+
+    let value = parse(&["--json-array", "b", file_1, file_2], "");
+    let files: Vec<_> = value.as_array().unwrap().iter().map(|object| &object["file"]).collect();
+    assert_eq!(files, vec![file_1, file_2]);
+
+    let value = parse(&["--json-array", "a", file_1, file_2], "");
+    assert_eq!(value.as_array().unwrap().len(), 1);
+
+    fs::remove_file(file_1).unwrap();
+    fs::remove_file(file_2).unwrap();
+}
+
+#[test]
+fn spans_only() {
+    let output = run(&["--spans-only", r"a(?P<x>b)?b?"], "cabb");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines: Vec<_> = stdout.lines().map(|line| line.split(" - ").nth(1)).collect();
+    lines.sort();
+    assert_eq!(lines, vec![Some("1,2"), Some("1,3"), Some("1,4")]);
+}
+
+#[test]
+fn context() {
+    let log = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/log.txt");
+    let log = log.to_str().unwrap();
+
+    // Matches on lines 3 and 4 share their context, which is printed once
+    let output = run(&["-C", "1", "ERROR", log], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2-listening on port 8080\n\
+         3:ERROR could not open cache\n\
+         4:ERROR falling back to disk\n\
+         5-request served\n\
+         --\n\
+         8-request served\n\
+         9:ERROR disk is full\n\
+         10-shutting down\n"
+    );
+
+    let output = run(&["-B", "1", "-A", "0", "full"], "a\nb\ndisk is full");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "2-b\n3:disk is full\n");
+
+    for option in &["-A", "-B", "-C"] {
+        assert_eq!(run(&[option, "x", "full"], "full").status.code(), Some(2));
+    }
+}
+
+#[test]
+fn color() {
+    let output = run(&["--color", "always", "b+"], "abba");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("match:\"bb\"\na\x1b[1;31mbb\x1b[0ma\n"));
+
+    let output = run(&["--color", "always", "a(?P<x>b+)"], "abba");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\x1b[1;31ma\x1b[0m\x1b[1;32mbb\x1b[0ma\n"));
+
+    let output = run(&["--color", "never", "b+"], "abba");
+    assert!(!output.stdout.contains(&b'\x1b'));
+}
+
+#[test]
+fn csv() {
+    // Pattern of the benchmark "First columns of CSV"
+    let pattern = r"\n(?P<x>[^,]+),(?P<y>[^,]+),(?P<z>[^,]+),";
+    let text = "id,name,city,zip\n1,Ann \"A\",Paris,75\n2,Bob,Lyon,69";
+
+    let output = run(&["--csv", pattern], text);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.remove(0), "x,y,z");
+    lines.sort();
+    assert_eq!(lines, vec![r#"1,"Ann ""A""",Paris"#, "2,Bob,Lyon"]);
+
+    let output = run(&["--csv", "--csv-match", r"(?P<x>a)?b"], "b");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "match,x\nb,\n");
+
+    // The header is written once for all files
+    let file_1 = temp_file("csv_1.txt", "ab");
+    let file_2 = temp_file("csv_2.txt", "b");
+    let file_1 = file_1.to_str().unwrap();
+    let file_2 = file_2.to_str().unwrap();
+
+    let output = run(&["--csv", r"(?P<x>a)?b", file_1, file_2], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.remove(0), "file,x");
+    lines.sort();
+    let expected = vec![format!("{},", file_1), format!("{},a", file_1), format!("{},", file_2)];
+    assert_eq!(lines, expected);
+
+    fs::remove_file(file_1).unwrap();
+    fs::remove_file(file_2).unwrap();
+}
+
+#[test]
+fn only_matching() {
+    let output = run(&["-o", "a+"], "aaa");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "aaa\n");
+
+    let output = run(&["-o", "a+?"], "aaa");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a\na\na\n");
+
+    // Greediness doesn't change the set of matches
+    let greedy = run(&["--spans-only", "a+"], "aaa");
+    let lazy = run(&["--spans-only", "a+?"], "aaa");
+    assert_eq!(greedy.stdout, lazy.stdout);
+    assert_eq!(String::from_utf8(lazy.stdout).unwrap().lines().count(), 6);
+}
+
+#[test]
+fn leftmost_longest() {
+    let output = run(&["--leftmost-longest", "--spans-only", "a+"], "aaa baa");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2);
+
+    let output = run(&["--leftmost-longest", "--count", "a+"], "aaa baa");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+}
+
+#[test]
+fn pattern_file() {
+    let patterns = temp_file("pattern_file.txt", "# Numbers\n\\d+\n\n[ab]\n");
+    let text = temp_file("pattern_file_text.txt", "a1b22");
+    let patterns = patterns.to_str().unwrap();
+    let text = text.to_str().unwrap();
+
+    let output = run(&["--count", "--pattern-file", patterns, text], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+
+    let output = run(&["-o", "-e", r"\d+", "-e", "[ab]"], "a1b22");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a\n1\nb\n22\n");
+
+    let output = run(&["--count", "--pattern-file", "/non/existing/file"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn compare_groups() {
+    let records = |args: &[&str], text: &str| -> Vec<serde_json::Value> {
+        let stdout = String::from_utf8(run(args, text).stdout).unwrap();
+        stdout
+            .lines()
+            .map(|line| serde_json::from_str(line.trim_start_matches(">>>>")).unwrap())
+            .collect()
+    };
+
+    let records = records(&["--compare", "--compare-groups", r"(?P<a>.)(?P<b>.)"], "x\"y");
+    assert_eq!(records.len(), 3);
+    assert_eq!(records.last().unwrap()["match"], "EOF");
+
+    let mut groups: Vec<_> = records[..2]
+        .iter()
+        .map(|record| (record["match"].clone(), record["groups"].clone()))
+        .collect();
+    groups.sort_by_key(|(text, _)| text.to_string());
+
+    assert_eq!(
+        groups,
+        vec![
+            ("\"y".into(), serde_json::json!({"a": [1, 2], "b": [2, 3]})),
+            ("x\"".into(), serde_json::json!({"a": [0, 1], "b": [1, 2]})),
+        ]
+    );
+
+    // Groups are only listed on demand
+    let stdout = String::from_utf8(run(&["--compare", "(?P<a>a)"], "a").stdout).unwrap();
+    assert!(stdout.starts_with(r#">>>>{"match": "a", "span": [0,1], "time": "#));
+}
+
+#[test]
+fn replace() {
+    let output = run(&["--replace", "<${y}>", r"(?P<y>\d+)"], "a12b3");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a<12>b<3>\n");
+
+    let output = run(&["--replace", "<${y}>", "--with-filename", r"(?P<y>\d+)"], "a1\nb\n2");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "<stdin>:a<1>\n<stdin>:b\n<stdin>:<2>\n");
+}
+
+#[test]
+fn timeout() {
+    let text = "a@b ".repeat(10_000);
+    assert_eq!(run(&["--timeout", "0", r"\w+@\w+"], &text).status.code(), Some(2));
+    assert_eq!(run(&["--timeout", "3600", "-c", "a@b"], "a@b").status.code(), Some(0));
+    assert_eq!(run(&["--timeout", "1e12", "-c", "a@b"], "a@b").status.code(), Some(0));
+
+    for timeout in &["-1", "inf", "NaN", "1e30", "x"] {
+        let output = run(&["--timeout", timeout, "a"], "a");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr).unwrap().contains("--timeout"));
+    }
+}
+
+#[test]
+fn dot() {
+    let path = temp_file("dot.dot", "");
+    let output = run(&["--dot", path.to_str().unwrap(), "-c", "ab"], "ab");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(fs::read_to_string(&path).unwrap().starts_with("digraph automaton {"));
+    fs::remove_file(path).unwrap();
+
+    let output = run(&["--dot", "/non/existing/dir/automaton.dot", "ab"], "ab");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Could not write the automaton"));
+}
+
+#[test]
+fn jump_trace() {
+    let file_1 = temp_file("jump_trace_1.txt", "ab");
+    let file_2 = temp_file("jump_trace_2.txt", "b");
+    let file_1 = file_1.to_str().unwrap();
+    let file_2 = file_2.to_str().unwrap();
+    let path = temp_file("jump_trace.txt", "");
+
+    // Traces of both inputs are kept
+    let output = run(&["--jump-trace", path.to_str().unwrap(), "b", file_1, file_2], "");
+    assert_eq!(output.status.code(), Some(0));
+    let trace = fs::read_to_string(&path).unwrap();
+    assert!(trace.contains(&format!("===== {} =====\nLevel 0:", file_1)));
+    assert!(trace.contains(&format!("===== {} =====\nLevel 0:", file_2)));
+
+    // Debug infos don't write any trace, in the working directory or elsewhere
+    let dir = env::temp_dir().join(format!("enum-spanner-{}-jump_trace", process::id()));
+    fs::create_dir(&dir).unwrap();
+    run_in(&dir, &["--debug-infos", "b", file_1], b"");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(dir).unwrap();
+
+    let output = run(&["--jump-trace", "/non/existing/dir/jump.txt", "b"], "b");
+    assert_eq!(output.status.code(), Some(2));
+
+    fs::remove_file(file_1).unwrap();
+    fs::remove_file(file_2).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn max_levels() {
+    let text = "a".repeat(1000);
+    let output = run(&["--max-levels", "10", "-c", "a+"], &text);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("more than 10 levels"));
+
+    let output = run(&["--max-levels", "10000", "-c", "a+"], "aaa");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+    assert_eq!(run(&["--max-levels", "ten", "a+"], "aaa").status.code(), Some(2));
+}
+
+#[test]
+fn max_width() {
+    let text = "a".repeat(60);
+    let output = run(&["--max-width", "40", "-c", r"(?:a?){30}a{30}"], &text);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("blew up at level 9 with 41 vertices"));
+
+    let output = run(&["--max-width", "40", "-c", "a+"], "aaa");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+    assert_eq!(run(&["--max-width", "4.5", "a+"], "aaa").status.code(), Some(2));
+}
+
+#[test]
+fn invalid_utf8() {
+    let input = b"ab\xffab";
+    assert_eq!(run_bytes(&["b"], input).status.code(), Some(2));
+
+    let output = run_bytes(&["--lossy", "-b", "b"], input);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "1 - match:1,2\n2 - match:4,5\n");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_input() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let plain = fixtures.join("emails.txt");
+    let gzipped = fixtures.join("emails.txt.gz");
+
+    let expected = run(&["-b", r"\w+@\w+", plain.to_str().unwrap()], "");
+    let output = run(&["-b", r"\w+@\w+", gzipped.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, expected.stdout);
+
+    let input = fs::read(&gzipped).unwrap();
+    let output = run_bytes(&["--gzip", "-b", r"\w+@\w+"], &input);
+    assert_eq!(output.stdout, expected.stdout);
+}
+
+#[test]
+fn trailing_newline() {
+    // Only one trailing line break is removed by default
+    let output = run(&["--count", r"\n$"], "a\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0\n");
+    let output = run(&["--count", r"\n$"], "a\n\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let output = run(&["--count", r"a$"], "a\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+
+    let output = run(&["--keep-trailing-newline", "--count", r"\n$"], "a\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let output = run(&["--keep-trailing-newline", "--count", r"a\z"], "a\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0\n");
+
+    let output = run(&["--keep-trailing-newline", "-b", r"\n\z"], "a\n\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1 - match:2,3\n");
+
+    // Without stripping, `$` still matches before a final line break
+    let output = run(&["--keep-trailing-newline", "--count", r"a$"], "a\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let output = run(&["--keep-trailing-newline", "-b", r"\n$"], "a\n\n");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1 - match:1,2\n2 - match:2,3\n"
+    );
+}
+
+#[test]
+fn crlf() {
+    let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/crlf.txt");
+    let fixture = fixture.to_str().unwrap();
+
+    let output = run(&["--crlf", "-C", "0", "match", fixture], "");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2:second match\n--\n4:fourth match\n"
+    );
+
+    // Without the flag, carriage returns are part of the lines
+    let output = run(&["-C", "0", "match", fixture], "");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2:second match\r\n--\n4:fourth match\r\n"
+    );
+
+    // The end anchor matches before the trailing line terminator
+    let output = run(&["--crlf", "--count", "match$", fixture], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let output = run(&["--count", "match$", fixture], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0\n");
+}